@@ -1,6 +1,7 @@
 mod processor;
 
-use processor::{ProcessOptions, ProcessResult, ImageInfo, PreviewFileInfo};
+use chrono::Datelike;
+use processor::{ProcessOptions, ProcessResult, ImageInfo, PreviewFileInfo, LayerFilter};
 use rayon::prelude::*;
 use rayon::ThreadPoolBuilder;
 use serde::{Deserialize, Serialize};
@@ -113,7 +114,7 @@ async fn get_image_files(folder_path: String) -> Result<Vec<String>, String> {
     // フォルダ切り替え時にPSDキャッシュをクリア
     processor::clear_psd_cache();
 
-    let extensions = ["png", "jpg", "jpeg", "gif", "webp", "psd", "tif", "tiff"];
+    let extensions = ["png", "jpg", "jpeg", "gif", "webp", "psd", "psb", "tif", "tiff"];
     let mut files: Vec<String> = Vec::new();
 
     for entry in walkdir::WalkDir::new(&path)
@@ -152,11 +153,13 @@ async fn get_image_preview(file_path: String, max_size: u32) -> Result<ImageInfo
 
 /// 画像のプレビューをファイルに保存して取得（高速化版）
 /// Base64エンコードを回避し、ファイルシステム経由で転送
+/// layer_filterを指定するとPSDのレイヤー表示ルールを反映したプレビューになる
 #[tauri::command]
 async fn get_image_preview_as_file(
     app_handle: tauri::AppHandle,
     file_path: String,
-    max_size: u32
+    max_size: u32,
+    layer_filter: Option<LayerFilter>,
 ) -> Result<PreviewFileInfo, String> {
     // 進捗通知: 読み込み開始
     let _ = app_handle.emit("preview_progress", "reading");
@@ -169,7 +172,7 @@ async fn get_image_preview_as_file(
 
     // 非同期でブロッキング処理を実行（UIフリーズを防止）
     let result = tokio::task::spawn_blocking(move || {
-        processor::get_image_preview_file(&file_path, max_size, &temp_dir_str)
+        processor::get_image_preview_file(&file_path, max_size, &temp_dir_str, layer_filter.as_ref())
     }).await.map_err(|e| format!("タスクエラー: {}", e))?;
 
     result
@@ -210,26 +213,42 @@ async fn process_images(
 
     let base_output_path = PathBuf::from(&output_folder);
 
-    // JPEGは "jpg" サブフォルダに出力（一時フォルダの場合はそのまま）
+    // 出力形式の拡張子名のサブフォルダに出力（"jpg", "png", "tif"...、一時フォルダの場合はそのまま）
     // 既存フォルダがある場合は連番で新しいフォルダを作成: jpg → jpg(1) → jpg(2) ...
+    let extension = processor::image_writer::output_extension(&options.output_format);
     let output_path = if output_folder.contains("_temp_pdf_source") {
         base_output_path.clone()
     } else {
-        let jpg_path = base_output_path.join("jpg");
-        if jpg_path.exists() {
-            let mut counter = 1u32;
-            loop {
-                let new_path = base_output_path.join(format!("jpg({})", counter));
-                if !new_path.exists() {
-                    break new_path;
-                }
-                counter += 1;
-            }
-        } else {
-            jpg_path
-        }
+        processor::image_writer::output_subfolder(&base_output_path, &options.output_format)
     };
 
+    // 出力ファイル名をテンプレートから生成し、処理開始前に重複を検出
+    let today = chrono::Local::now();
+    let date = processor::naming::NamingDate {
+        year: today.year(),
+        month: today.month(),
+        day: today.day(),
+    };
+    let output_names = processor::naming::build_output_names(
+        &files,
+        &options.naming_template,
+        extension,
+        options.nombre_start_number,
+        options.work_info.as_ref(),
+        date,
+    )?;
+    let collisions = processor::naming::find_name_collisions(&files, &output_names);
+    if !collisions.is_empty() {
+        return Ok(ProcessResult {
+            processed: 0,
+            total: files.len(),
+            errors: collisions,
+            output_folder: output_path.to_string_lossy().to_string(),
+            output_files: output_names,
+            pages: Vec::new(),
+        });
+    }
+
     // 出力フォルダを作成
     std::fs::create_dir_all(&output_path)
         .map_err(|e| format!("出力フォルダの作成に失敗: {}", e))?;
@@ -238,6 +257,7 @@ async fn process_images(
     let processed = AtomicUsize::new(0);
     let in_progress = AtomicUsize::new(0);  // 現在処理中のファイル数
     let errors: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    let pages: Mutex<Vec<(usize, processor::PageReport)>> = Mutex::new(Vec::new());
 
     // rayon並列処理で複数ファイルを同時処理
     // enumerate()でインデックスを取得してノンブル用のページ番号に使用
@@ -259,20 +279,7 @@ async fn process_images(
         });
 
         let input_file = input_path.join(filename);
-        let output_file = match PathBuf::from(filename)
-            .with_extension("jpg")
-            .file_name()
-        {
-            Some(name) => output_path.join(name),
-            None => {
-                if let Ok(mut errs) = errors.lock() {
-                    errs.push(format!("{}: 無効なファイル名", filename));
-                }
-                in_progress.fetch_sub(1, Ordering::SeqCst);
-                processed.fetch_add(1, Ordering::SeqCst);
-                return;
-            }
-        };
+        let output_file = output_path.join(&output_names[index]);
 
         // ページ番号 = 開始番号 + インデックス
         let page_number = options.nombre_start_number + index as u32;
@@ -297,7 +304,11 @@ async fn process_images(
         });
 
         match result {
-            Ok(_) => {}
+            Ok(report) => {
+                if let Ok(mut pages) = pages.lock() {
+                    pages.push((index, report));
+                }
+            }
             Err(e) => {
                 if let Ok(mut errs) = errors.lock() {
                     errs.push(format!("{}: {}", filename, e));
@@ -306,9 +317,14 @@ async fn process_images(
         }
     });
 
-    // 実際のJPEG出力パス（連番フォルダの場合はjpg(N)になる）
+    // 実際の出力パス（連番フォルダの場合はjpg(N)などになる）
     let actual_output_folder = output_path.to_string_lossy().to_string();
 
+    // ページごとの結果を入力順に並べる
+    let mut page_list = pages.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner());
+    page_list.sort_by_key(|(index, _)| *index);
+    let page_reports: Vec<processor::PageReport> = page_list.into_iter().map(|(_, report)| report).collect();
+
     // キャンセルされた場合は早期リターン
     if CANCEL_FLAG.load(Ordering::Relaxed) {
         let done = processed.load(Ordering::SeqCst);
@@ -317,6 +333,8 @@ async fn process_images(
             total,
             errors: vec![format!("処理がキャンセルされました ({}/{}完了)", done, total)],
             output_folder: actual_output_folder,
+            output_files: output_names,
+            pages: page_reports,
         });
     }
 
@@ -331,6 +349,8 @@ async fn process_images(
         total,
         errors: error_list,
         output_folder: actual_output_folder,
+        output_files: output_names,
+        pages: page_reports,
     })
 }
 
@@ -342,7 +362,7 @@ async fn generate_pdf(
     output_path: String,
     files: Vec<String>,
     options: processor::PdfOptions,
-) -> Result<String, String> {
+) -> Result<processor::PdfResult, String> {
     // PDF生成前にPSDキャッシュを解放してメモリを確保
     processor::clear_psd_cache();
     processor::generate_pdf(&app_handle, &input_folder, &output_path, &files, &options)
@@ -622,6 +642,27 @@ async fn get_psd_guides(file_path: String) -> Result<Vec<processor::image_loader
     processor::image_loader::extract_psd_guides(&path)
 }
 
+/// スキャン画像などの仕上がり枠を自動検出（トンボ → 紙・絵柄の境界、画像座標と信頼度を返す）
+#[tauri::command]
+async fn detect_crop(file_path: String) -> Result<Option<processor::crop_detection::DetectedCrop>, String> {
+    let path = PathBuf::from(&file_path);
+    let img = processor::image_loader::load_image(&path)?;
+    Ok(processor::crop_detection::detect_crop(&img))
+}
+
+/// フォント選択用に、システムのフォントファイルと書体名の一覧を取得
+#[tauri::command]
+async fn list_fonts() -> Result<Vec<processor::fonts::FontFileInfo>, String> {
+    Ok(processor::fonts::list_fonts())
+}
+
+/// PSDファイルのレイヤー/グループ一覧を取得
+#[tauri::command]
+async fn get_psd_layers(file_path: String) -> Result<Vec<processor::psd_layers::PsdLayerInfo>, String> {
+    let path = PathBuf::from(&file_path);
+    processor::psd_layers::list_psd_layers(&path)
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // 並列処理のスレッドプールを初期化（CPUコア数の2倍）
@@ -651,6 +692,9 @@ pub fn run() {
             ensure_folder_exists,
             file_exists,
             get_psd_guides,
+            get_psd_layers,
+            detect_crop,
+            list_fonts,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
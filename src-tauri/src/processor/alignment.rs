@@ -0,0 +1,226 @@
+//! タチミ - スキャン原稿の傾き補正・位置合わせ
+//! 傾き: 暗い画素を角度ごとに縦横へ投影し、枠線・トンボ・文字の並びが最も揃う角度を求める
+//! 位置合わせ: トンボ（なければ紙・絵柄の境界）の中心を共通のクロップ範囲の中心へ平行移動する
+
+use ::image::imageops::{self, FilterType};
+use ::image::{DynamicImage, GenericImageView, Rgba};
+use imageproc::definitions::Clamp;
+
+use super::crop_detection::detect_crop;
+use super::image_processing::{scaled_crop_rect, WorkBuffer, WorkPixel, WorkSample};
+use super::page_side::PageSide;
+use super::types::ProcessOptions;
+
+/// 傾き検出に使う縮小画像の長辺（ピクセル）
+const SKEW_ANALYSIS_SIZE: u32 = 1600;
+/// この輝度未満を線・文字とみなす
+const SKEW_INK_THRESHOLD: u8 = 128;
+/// 投影に使う最大の画素数（多ければ間引く）
+const SKEW_MAX_POINTS: usize = 400_000;
+/// 傾き検出に必要な最小の画素数
+const SKEW_MIN_POINTS: usize = 1_000;
+/// 粗い探索・細かい探索の刻み（度）
+const SKEW_COARSE_STEP: f32 = 0.1;
+const SKEW_FINE_STEP: f32 = 0.01;
+
+/// 傾き補正・位置合わせの結果
+pub struct AlignedPage {
+    pub image: DynamicImage,
+    /// 補正した傾き（度、時計回りが正）
+    pub skew_degrees: Option<f32>,
+    /// 平行移動した量（ピクセル）
+    pub offset: Option<[i32; 2]>,
+}
+
+/// 画像の傾き（度、内容が時計回りに回っている場合に正）を検出
+/// 線・文字が少なく判定できない場合は None
+pub fn detect_skew(img: &DynamicImage, max_degrees: f32) -> Option<f32> {
+    let (width, height) = img.dimensions();
+    let scale = (SKEW_ANALYSIS_SIZE as f32 / width.max(height) as f32).min(1.0);
+    let gray = if scale < 1.0 {
+        let (w, h) = ((width as f32 * scale).round() as u32, (height as f32 * scale).round() as u32);
+        imageops::resize(&img.to_luma8(), w.max(1), h.max(1), FilterType::Triangle)
+    } else {
+        img.to_luma8()
+    };
+
+    let (cx, cy) = (gray.width() as f32 / 2.0, gray.height() as f32 / 2.0);
+    let points: Vec<(f32, f32)> = gray
+        .enumerate_pixels()
+        .filter(|(_, _, p)| p[0] < SKEW_INK_THRESHOLD)
+        .map(|(x, y, _)| (x as f32 - cx, y as f32 - cy))
+        .collect();
+    if points.len() < SKEW_MIN_POINTS {
+        return None;
+    }
+    let step = points.len().div_ceil(SKEW_MAX_POINTS);
+    let points: Vec<(f32, f32)> = points.into_iter().step_by(step).collect();
+
+    // 横線は y - x·tanθ、縦線は x + y·tanθ が一定になる角度で投影のヒストグラムが最も尖る
+    // 投影位置は隣り合う2つのビンに按分し、ビンの量子化による角度の偏りを抑える
+    let pad = (cx.max(cy) * 2.0) as usize + 2;
+    let score = |degrees: f32| -> f64 {
+        let t = degrees.to_radians().tan();
+        let mut rows = vec![0f32; pad * 2 + 1];
+        let mut columns = vec![0f32; pad * 2 + 1];
+        let vote = |bins: &mut [f32], position: f32| {
+            let position = position + pad as f32;
+            let index = position as usize;
+            let fraction = position - index as f32;
+            bins[index] += 1.0 - fraction;
+            bins[index + 1] += fraction;
+        };
+        for (x, y) in &points {
+            vote(&mut rows, y - x * t);
+            vote(&mut columns, x + y * t);
+        }
+        rows.iter().chain(&columns).map(|c| (*c as f64) * (*c as f64)).sum()
+    };
+    let best_in = |from: f32, to: f32, step: f32| -> f32 {
+        let count = ((to - from) / step).round() as i32;
+        (0..=count)
+            .map(|i| from + i as f32 * step)
+            .map(|degrees| (degrees, score(degrees)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(degrees, _)| degrees)
+            .unwrap_or(0.0)
+    };
+
+    // 45度を超えると縦横の区別がつかない
+    let max_degrees = max_degrees.abs().min(45.0);
+    let coarse = best_in(-max_degrees, max_degrees, SKEW_COARSE_STEP);
+    Some(best_in(coarse - SKEW_COARSE_STEP, coarse + SKEW_COARSE_STEP, SKEW_FINE_STEP))
+}
+
+/// 画像の中心を軸に内容を回転（度、時計回りが正）。バイキュービック補間、はみ出した部分は白
+pub fn rotate_buffer<P: WorkPixel>(img: &WorkBuffer<P>, degrees: f32) -> WorkBuffer<P>
+where
+    P::Subpixel: WorkSample,
+{
+    let (width, height) = img.dimensions();
+    let white = P::from_rgba8(Rgba([255, 255, 255, 255]));
+    let mut out = WorkBuffer::<P>::from_pixel(width, height, white);
+    let channels = P::CHANNEL_COUNT as usize;
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (cx, cy) = ((width as f32 - 1.0) / 2.0, (height as f32 - 1.0) / 2.0);
+    let src: &[P::Subpixel] = img.as_raw();
+    let stride = width as usize * channels;
+
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        // 出力画素に対応する元画像の位置（逆回転）
+        let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+        let sx = cx + dx * cos + dy * sin;
+        let sy = cy - dx * sin + dy * cos;
+        if sx < 0.0 || sy < 0.0 || sx > (width - 1) as f32 || sy > (height - 1) as f32 {
+            continue;
+        }
+
+        let (x0, y0) = (sx.floor() as i64, sy.floor() as i64);
+        let weights_x = cubic_weights(sx - x0 as f32);
+        let weights_y = cubic_weights(sy - y0 as f32);
+        let mut sum = [0.0f32; 4];
+        for (j, wy) in weights_y.iter().enumerate() {
+            let row = (y0 + j as i64 - 1).clamp(0, height as i64 - 1) as usize * stride;
+            for (i, wx) in weights_x.iter().enumerate() {
+                let col = (x0 + i as i64 - 1).clamp(0, width as i64 - 1) as usize * channels;
+                for (c, total) in sum.iter_mut().take(channels).enumerate() {
+                    let value: f32 = src[row + col + c].into();
+                    *total += value * wx * wy;
+                }
+            }
+        }
+        for (channel, total) in pixel.channels_mut().iter_mut().zip(sum) {
+            *channel = <P::Subpixel as Clamp<f32>>::clamp(total.round());
+        }
+    }
+    out
+}
+
+/// Catmull-Rom（a = -0.5）の4点の重み
+fn cubic_weights(t: f32) -> [f32; 4] {
+    let a = -0.5;
+    let w = |d: f32| {
+        let d = d.abs();
+        if d <= 1.0 {
+            (a + 2.0) * d * d * d - (a + 3.0) * d * d + 1.0
+        } else if d < 2.0 {
+            a * d * d * d - 5.0 * a * d * d + 8.0 * a * d - 4.0 * a
+        } else {
+            0.0
+        }
+    };
+    [w(1.0 + t), w(t), w(1.0 - t), w(2.0 - t)]
+}
+
+/// 内容を平行移動（ピクセル）。空いた部分は白
+pub fn translate_buffer<P: WorkPixel>(img: &WorkBuffer<P>, dx: i64, dy: i64) -> WorkBuffer<P> {
+    let white = P::from_rgba8(Rgba([255, 255, 255, 255]));
+    let mut out = WorkBuffer::<P>::from_pixel(img.width(), img.height(), white);
+    imageops::replace(&mut out, img, dx, dy);
+    out
+}
+
+/// 設定に従って傾き補正・位置合わせを行う
+/// 位置合わせの基準は共通のクロップ範囲（crop_*、左右ページのずらし量込み）の中心
+pub fn align_page<P: WorkPixel>(img: &DynamicImage, options: &ProcessOptions, side: PageSide) -> AlignedPage
+where
+    P::Subpixel: WorkSample,
+{
+    let align = &options.align;
+    let mut buffer = P::buffer_from(img);
+
+    let skew_degrees = if align.deskew {
+        detect_skew(img, align.max_skew_degrees).filter(|skew| skew.abs() >= align.min_skew_degrees)
+    } else {
+        None
+    };
+    if let Some(skew) = skew_degrees {
+        buffer = rotate_buffer(&buffer, -skew);
+    }
+
+    let mut image = P::into_dynamic(buffer);
+    let mut offset = None;
+    if align.register {
+        if let Some(detected) = detect_crop(&image).filter(|d| d.confidence >= options.auto_crop_min_confidence) {
+            let (width, height) = image.dimensions();
+            let (left, top, right, bottom) = scaled_crop_rect(options, side, width, height);
+            let (l, t, r, b) = detected.rect();
+            let dx = (left as i64 + right as i64 - l as i64 - r as i64) / 2;
+            let dy = (top as i64 + bottom as i64 - t as i64 - b as i64) / 2;
+            if dx != 0 || dy != 0 {
+                image = P::into_dynamic(translate_buffer(&P::buffer_from(&image), dx, dy));
+                offset = Some([dx as i32, dy as i32]);
+            }
+        }
+    }
+
+    AlignedPage { image, skew_degrees, offset }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::image::{GrayImage, Luma};
+    use imageproc::drawing::draw_filled_rect_mut;
+    use imageproc::rect::Rect;
+
+    #[test]
+    fn test_detect_and_correct_skew() {
+        // コマ枠と罫線
+        let mut page = GrayImage::from_pixel(800, 1000, Luma([255]));
+        for y in (100..900).step_by(60) {
+            draw_filled_rect_mut(&mut page, Rect::at(100, y).of_size(600, 3), Luma([0]));
+        }
+        for x in [100, 400, 697] {
+            draw_filled_rect_mut(&mut page, Rect::at(x, 100).of_size(3, 783), Luma([0]));
+        }
+
+        let rotated = rotate_buffer::<Luma<u8>>(&page, 1.5);
+        let skew = detect_skew(&DynamicImage::ImageLuma8(rotated.clone()), 3.0).unwrap();
+        assert!((skew - 1.5).abs() < 0.05, "skew = {}", skew);
+
+        let corrected = rotate_buffer::<Luma<u8>>(&rotated, -skew);
+        let residual = detect_skew(&DynamicImage::ImageLuma8(corrected), 3.0).unwrap();
+        assert!(residual.abs() < 0.05, "residual = {}", residual);
+    }
+}
@@ -0,0 +1,204 @@
+//! タチミ - 奥付テンプレート
+//! テンプレートの1行が奥付の1行になる
+//!
+//! 行の書き方:
+//! - `---`: 罫線
+//! - `# テキスト`: 見出し（大きめの文字）
+//! - `ラベル|値`: ラベルを左端、値を右端に揃える
+//! - それ以外: 左揃えのテキスト
+//!
+//! 使えるプレースホルダー:
+//! - {title}, {subtitle}, {volume}, {label}, {author}: 作品情報（{author} は著者表記を全角空白でつなぐ）
+//! - {date}, {publisher}, {address}, {printer}, {copyright}, {notes}: 奥付情報
+//!
+//! プレースホルダーを含む行は、すべて空に展開されたときは出力しない。
+//! 値に改行を含む場合は複数行になる（ラベルは最初の行のみ）。波括弧そのものは {{ と }} で書く。
+
+use super::pdf::generated::work_info_author_lines;
+use super::types::{ColophonInfo, WorkInfo};
+
+/// 罫線の行
+const RULE_LINE: &str = "---";
+/// 見出し行の接頭辞
+const HEADING_PREFIX: &str = "# ";
+/// ラベルと値の区切り
+const FIELD_SEPARATOR: char = '|';
+
+/// 標準の奥付テンプレート
+pub const DEFAULT_COLOPHON_TEMPLATE: &str = "\
+# {title}　{volume}
+{subtitle}
+---
+{date}
+---
+著者|{author}
+発行所|{publisher}
+|{address}
+印刷所|{printer}
+---
+{copyright}
+{notes}";
+
+/// 奥付の1行
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColophonRow {
+    /// 見出し
+    Heading(String),
+    /// 左揃えのテキスト
+    Text(String),
+    /// ラベル（左揃え）と値（右揃え）
+    Field { label: String, value: String },
+    /// 罫線
+    Rule,
+}
+
+/// テンプレートを展開して奥付の行に分ける
+/// 連続する罫線・先頭と末尾の罫線は、間の行が省かれた結果として1本にまとめる
+pub fn build_colophon_rows(colophon: &ColophonInfo, work_info: Option<&WorkInfo>) -> Result<Vec<ColophonRow>, String> {
+    let template = if colophon.template.trim().is_empty() { DEFAULT_COLOPHON_TEMPLATE } else { &colophon.template };
+    let mut rows = Vec::new();
+
+    for line in template.lines() {
+        let line = line.trim_end();
+        if line == RULE_LINE {
+            if !matches!(rows.last(), None | Some(ColophonRow::Rule)) {
+                rows.push(ColophonRow::Rule);
+            }
+            continue;
+        }
+
+        let (heading, body) = match line.strip_prefix(HEADING_PREFIX) {
+            Some(body) => (true, body),
+            None => (false, line),
+        };
+        let (label, value) = match body.split_once(FIELD_SEPARATOR) {
+            Some((label, value)) => (Some(expand_template(label, colophon, work_info)?), value),
+            None => (None, body),
+        };
+        let expanded = expand_template(value, colophon, work_info)?;
+        if expanded.has_placeholder && expanded.text.trim().is_empty() {
+            continue;
+        }
+
+        for (i, text) in expanded.text.lines().enumerate() {
+            let text = text.trim().to_string();
+            rows.push(match &label {
+                Some(label) => ColophonRow::Field {
+                    label: if i == 0 { label.text.trim().to_string() } else { String::new() },
+                    value: text,
+                },
+                None if heading => ColophonRow::Heading(text),
+                None => ColophonRow::Text(text),
+            });
+        }
+    }
+
+    if rows.last() == Some(&ColophonRow::Rule) {
+        rows.pop();
+    }
+    Ok(rows)
+}
+
+/// テンプレート1行分の展開結果
+struct Expanded {
+    text: String,
+    has_placeholder: bool,
+}
+
+/// プレースホルダーを展開
+fn expand_template(template: &str, colophon: &ColophonInfo, work_info: Option<&WorkInfo>) -> Result<Expanded, String> {
+    let mut text = String::new();
+    let mut has_placeholder = false;
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return Err(format!("奥付テンプレートの '{{' が閉じられていません: {}", template)),
+                    }
+                }
+                text.push_str(&expand_placeholder(&placeholder, colophon, work_info)?);
+                has_placeholder = true;
+            }
+            '}' => return Err(format!("奥付テンプレートに対応しない '}}' があります: {}", template)),
+            _ => text.push(c),
+        }
+    }
+    Ok(Expanded { text, has_placeholder })
+}
+
+/// プレースホルダー1つを展開
+fn expand_placeholder(placeholder: &str, colophon: &ColophonInfo, work_info: Option<&WorkInfo>) -> Result<String, String> {
+    let field = |f: fn(&WorkInfo) -> &String| work_info.map(|w| f(w).clone()).unwrap_or_default();
+    match placeholder {
+        "title" => Ok(field(|w| &w.title)),
+        "subtitle" => Ok(field(|w| &w.subtitle)),
+        "volume" => Ok(field(|w| &w.version)),
+        "label" => Ok(field(|w| &w.label)),
+        "author" => Ok(work_info.map(|w| work_info_author_lines(w).join("　")).unwrap_or_default()),
+        "date" => Ok(colophon.publication_date.clone()),
+        "publisher" => Ok(colophon.publisher.clone()),
+        "address" => Ok(colophon.publisher_address.clone()),
+        "printer" => Ok(colophon.printer.clone()),
+        "copyright" => Ok(colophon.copyright.clone()),
+        "notes" => Ok(colophon.notes.clone()),
+        _ => Err(format!("奥付テンプレートに不明なプレースホルダーがあります: {{{}}}", placeholder)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn work_info() -> WorkInfo {
+        WorkInfo {
+            title: "作品名".to_string(),
+            version: "3".to_string(),
+            author1: "作者".to_string(),
+            ..WorkInfo::default()
+        }
+    }
+
+    #[test]
+    fn test_default_template() {
+        let colophon = ColophonInfo {
+            publication_date: "2024年3月9日　初版第1刷発行\n2024年5月1日　第2刷発行".to_string(),
+            publisher: "タチミ出版".to_string(),
+            ..ColophonInfo::default()
+        };
+        let rows = build_colophon_rows(&colophon, Some(&work_info())).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ColophonRow::Heading("作品名　3".to_string()),
+                ColophonRow::Rule,
+                ColophonRow::Text("2024年3月9日　初版第1刷発行".to_string()),
+                ColophonRow::Text("2024年5月1日　第2刷発行".to_string()),
+                ColophonRow::Rule,
+                ColophonRow::Field { label: "著者".to_string(), value: "著　作者".to_string() },
+                ColophonRow::Field { label: "発行所".to_string(), value: "タチミ出版".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_template_errors() {
+        let colophon = ColophonInfo { template: "{isbn}".to_string(), ..ColophonInfo::default() };
+        assert!(build_colophon_rows(&colophon, None).is_err());
+        let colophon = ColophonInfo { template: "{{固定}}|{title".to_string(), ..ColophonInfo::default() };
+        assert!(build_colophon_rows(&colophon, None).is_err());
+    }
+}
@@ -0,0 +1,425 @@
+//! タチミ - カラープロファイルモジュール
+//! 埋め込みICCプロファイルの取得と、出力プロファイル（sRGB / Gray Gamma 2.2）への変換
+
+use ::image::{DynamicImage, ImageBuffer, LumaA, Primitive, Rgba};
+use moxcms::{ColorProfile, DataColorSpace, Layout, ProfileText, TransformOptions};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use super::image_processing::is_high_bit_depth;
+
+/// 出力プロファイル: 変換せず元のプロファイルを引き継ぐ
+pub const COLOR_PROFILE_KEEP: &str = "keep";
+/// 出力プロファイル: sRGB IEC61966-2.1
+pub const COLOR_PROFILE_SRGB: &str = "srgb";
+/// 出力プロファイル: Gray Gamma 2.2
+pub const COLOR_PROFILE_GRAY_GAMMA22: &str = "gray_gamma22";
+
+static SRGB_ICC: OnceLock<Vec<u8>> = OnceLock::new();
+static GRAY_GAMMA22_ICC: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// 出力プロファイル名からICCデータを取得（"keep"などの場合はNone）
+pub fn target_icc_profile(target: &str) -> Option<&'static [u8]> {
+    match target {
+        COLOR_PROFILE_SRGB => Some(SRGB_ICC.get_or_init(|| {
+            ColorProfile::new_srgb().encode().unwrap_or_default()
+        })),
+        COLOR_PROFILE_GRAY_GAMMA22 => Some(GRAY_GAMMA22_ICC.get_or_init(|| {
+            ColorProfile::new_gray_with_gamma(2.2).encode().unwrap_or_default()
+        })),
+        _ => None,
+    }
+    .map(|data| data.as_slice())
+    .filter(|data| !data.is_empty())
+}
+
+/// ICCプロファイルの色空間を取得（解析できなければNone）
+fn profile_color_space(icc: &[u8]) -> Option<DataColorSpace> {
+    ColorProfile::new_from_slice(icc).ok().map(|p| p.color_space)
+}
+
+/// グレースケール用のICCプロファイルか判定
+pub fn is_gray_profile(icc: &[u8]) -> bool {
+    profile_color_space(icc) == Some(DataColorSpace::Gray)
+}
+
+/// RGB用のICCプロファイルか判定
+pub fn is_rgb_profile(icc: &[u8]) -> bool {
+    profile_color_space(icc) == Some(DataColorSpace::Rgb)
+}
+
+/// CMYK用のICCプロファイルか判定
+pub fn is_cmyk_profile(icc: &[u8]) -> bool {
+    profile_color_space(icc) == Some(DataColorSpace::Cmyk)
+}
+
+/// ICCプロファイルの説明（プロファイル名）を取得
+pub fn profile_description(icc: &[u8]) -> Option<String> {
+    let text = match ColorProfile::new_from_slice(icc).ok()?.description? {
+        ProfileText::PlainString(s) => s,
+        ProfileText::Localizable(strings) => strings.into_iter().next()?.value,
+        ProfileText::Description(d) => d.ascii_string,
+    };
+    let text = text.trim_end_matches('\0').trim().to_string();
+    if text.is_empty() { None } else { Some(text) }
+}
+
+/// ICCプロファイルが出力プロファイルと同等か判定
+/// プロファイルなしはsRGBとみなし、RGBは原色のXYZ値で比較する（sRGBは配布元ごとにバイト列が異なるため）
+pub fn matches_target_profile(icc: Option<&[u8]>, target: &str) -> bool {
+    let Some(target_icc) = target_icc_profile(target) else {
+        return true;
+    };
+    let Some(icc) = icc else {
+        return target == COLOR_PROFILE_SRGB;
+    };
+    if icc == target_icc {
+        return true;
+    }
+
+    match (ColorProfile::new_from_slice(icc), ColorProfile::new_from_slice(target_icc)) {
+        (Ok(src), Ok(dst)) if src.color_space == DataColorSpace::Rgb && dst.color_space == DataColorSpace::Rgb => {
+            let close = |a: f64, b: f64| (a - b).abs() < 0.002;
+            [(src.red_colorant, dst.red_colorant), (src.green_colorant, dst.green_colorant), (src.blue_colorant, dst.blue_colorant)]
+                .iter()
+                .all(|(a, b)| close(a.x, b.x) && close(a.y, b.y) && close(a.z, b.z))
+        }
+        _ => false,
+    }
+}
+
+/// JPEGのAPP2（ICC_PROFILE）マーカーからICCプロファイルを取得
+/// SOSまでのマーカーだけを読むので、画像データは読み込まない
+pub fn read_jpeg_icc_profile(path: &Path) -> Option<Vec<u8>> {
+    let mut file = BufReader::new(File::open(path).ok()?);
+    let mut buf2 = [0u8; 2];
+
+    file.read_exact(&mut buf2).ok()?;
+    if buf2 != [0xFF, 0xD8] {
+        return None;
+    }
+
+    let mut chunks: Vec<(u8, Vec<u8>)> = Vec::new();
+    loop {
+        file.read_exact(&mut buf2).ok()?;
+        if buf2[0] != 0xFF {
+            return None;
+        }
+        let marker = buf2[1];
+        // SOS / EOI 以降にICCは現れない
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        file.read_exact(&mut buf2).ok()?;
+        let len = u16::from_be_bytes(buf2) as usize;
+        if len < 2 {
+            return None;
+        }
+
+        if marker == 0xE2 {
+            let mut data = vec![0u8; len - 2];
+            file.read_exact(&mut data).ok()?;
+            // "ICC_PROFILE\0" + 連番(1) + 総数(1) + データ
+            if data.len() > 14 && &data[..12] == b"ICC_PROFILE\0" {
+                chunks.push((data[12], data[14..].to_vec()));
+            }
+        } else {
+            file.seek(SeekFrom::Current(len as i64 - 2)).ok()?;
+        }
+    }
+
+    if chunks.is_empty() {
+        return None;
+    }
+    chunks.sort_by_key(|(seq, _)| *seq);
+    Some(chunks.into_iter().flat_map(|(_, data)| data).collect())
+}
+
+/// 画像を出力プロファイルに変換
+/// 戻り値は変換後の画像と、書き出し時に埋め込むICCプロファイル
+/// "keep" の場合は変換せず、元のプロファイルをそのまま返す
+pub fn convert_to_output_profile(
+    img: DynamicImage,
+    source_icc: Option<Vec<u8>>,
+    target: &str,
+) -> Result<(DynamicImage, Option<Vec<u8>>), String> {
+    let Some(target_icc) = target_icc_profile(target) else {
+        return Ok((img, source_icc));
+    };
+
+    // 同じプロファイルなら変換不要
+    if matches_target_profile(source_icc.as_deref(), target) {
+        return Ok((img, Some(target_icc.to_vec())));
+    }
+
+    // プロファイルなし・解析不能・RGB/グレー以外はsRGBとみなす
+    let src = source_icc
+        .as_deref()
+        .and_then(|icc| ColorProfile::new_from_slice(icc).ok())
+        .filter(|p| matches!(p.color_space, DataColorSpace::Rgb | DataColorSpace::Gray))
+        .unwrap_or_else(ColorProfile::new_srgb);
+    let dst = ColorProfile::new_from_slice(target_icc)
+        .map_err(|e| format!("出力プロファイルの読み込みに失敗: {:?}", e))?;
+
+    let src_gray = src.color_space == DataColorSpace::Gray;
+    let dst_gray = dst.color_space == DataColorSpace::Gray;
+    let src_layout = if src_gray { Layout::Gray } else { Layout::Rgb };
+    let dst_layout = if dst_gray { Layout::Gray } else { Layout::Rgb };
+    let (width, height) = (img.width(), img.height());
+
+    let converted = if is_high_bit_depth(&img) {
+        let transform = src
+            .create_transform_16bit(src_layout, &dst, dst_layout, TransformOptions::default())
+            .map_err(|e| format!("カラー変換の作成に失敗: {:?}", e))?;
+        let rgba = img.into_rgba16().into_raw();
+        let samples = transform_rgba(&rgba, src_gray, dst_gray, |src, dst| {
+            transform.transform(src, dst).map_err(|e| format!("カラー変換に失敗: {:?}", e))
+        })?;
+        if dst_gray {
+            ImageBuffer::<LumaA<u16>, Vec<u16>>::from_raw(width, height, samples).map(DynamicImage::ImageLumaA16)
+        } else {
+            ImageBuffer::<Rgba<u16>, Vec<u16>>::from_raw(width, height, samples).map(DynamicImage::ImageRgba16)
+        }
+    } else {
+        let transform = src
+            .create_transform_8bit(src_layout, &dst, dst_layout, TransformOptions::default())
+            .map_err(|e| format!("カラー変換の作成に失敗: {:?}", e))?;
+        let rgba = img.into_rgba8().into_raw();
+        let samples = transform_rgba(&rgba, src_gray, dst_gray, |src, dst| {
+            transform.transform(src, dst).map_err(|e| format!("カラー変換に失敗: {:?}", e))
+        })?;
+        if dst_gray {
+            ImageBuffer::<LumaA<u8>, Vec<u8>>::from_raw(width, height, samples).map(DynamicImage::ImageLumaA8)
+        } else {
+            ImageBuffer::<Rgba<u8>, Vec<u8>>::from_raw(width, height, samples).map(DynamicImage::ImageRgba8)
+        }
+    };
+
+    let converted = converted.ok_or("変換後の画像バッファの作成に失敗しました")?;
+    Ok((converted, Some(target_icc.to_vec())))
+}
+
+/// RGBAサンプルの色チャンネルだけを変換し、アルファを付け直す
+/// moxcmsはアルファ付きのグレー→RGB変換に対応しないため、色とアルファを分けて処理する
+fn transform_rgba<T: Primitive>(
+    rgba: &[T],
+    src_gray: bool,
+    dst_gray: bool,
+    run: impl Fn(&[T], &mut [T]) -> Result<(), String>,
+) -> Result<Vec<T>, String> {
+    let pixels = rgba.len() / 4;
+    let src_channels = if src_gray { 1 } else { 3 };
+    let dst_channels = if dst_gray { 1 } else { 3 };
+
+    // グレーの画像はRGBAに展開済みなのでRチャンネルをそのまま使う
+    let color: Vec<T> = rgba
+        .chunks_exact(4)
+        .flat_map(|px| px[..src_channels].iter().copied())
+        .collect();
+    let mut converted = vec![T::zero(); pixels * dst_channels];
+    run(&color, &mut converted)?;
+
+    let out = converted
+        .chunks_exact(dst_channels)
+        .zip(rgba.chunks_exact(4))
+        .flat_map(|(c, px)| c.iter().copied().chain(std::iter::once(px[3])))
+        .collect();
+    Ok(out)
+}
+
+/// PSDのCMYKプレーン（反転値）をICCプロファイルでsRGBプレーンに変換（アルファがあれば末尾に残す）
+pub fn cmyk_planes_to_srgb<T: Primitive>(planes: &[Vec<T>], icc: &[u8]) -> Result<Vec<Vec<T>>, String> {
+    if planes.len() < 4 {
+        return Err("CMYKチャンネルが不足しています".to_string());
+    }
+    let src = ColorProfile::new_from_slice(icc).map_err(|e| format!("ICCプロファイルの読み込みに失敗: {:?}", e))?;
+    let dst = ColorProfile::new_srgb();
+    let max = T::DEFAULT_MAX_VALUE;
+    let pixels = planes[0].len();
+
+    // PSDは 最大値 = インクなし で格納されているため、ICC（0 = インクなし）に合わせて反転する
+    let mut cmyk = Vec::with_capacity(pixels * 4);
+    for i in 0..pixels {
+        for plane in &planes[..4] {
+            cmyk.push(max - plane[i]);
+        }
+    }
+
+    let mut rgb = vec![T::zero(); pixels * 3];
+    // CMYKはRgbaと同じ4チャンネルレイアウトで渡す
+    let bytes = std::mem::size_of::<T>();
+    if bytes == 1 {
+        let transform = src
+            .create_transform_8bit(Layout::Rgba, &dst, Layout::Rgb, TransformOptions::default())
+            .map_err(|e| format!("カラー変換の作成に失敗: {:?}", e))?;
+        let src8: Vec<u8> = cmyk.iter().map(|v| v.to_u8().unwrap_or(0)).collect();
+        let mut dst8 = vec![0u8; pixels * 3];
+        transform.transform(&src8, &mut dst8).map_err(|e| format!("カラー変換に失敗: {:?}", e))?;
+        for (d, s) in rgb.iter_mut().zip(dst8) {
+            *d = T::from(s).unwrap_or(max);
+        }
+    } else {
+        let transform = src
+            .create_transform_16bit(Layout::Rgba, &dst, Layout::Rgb, TransformOptions::default())
+            .map_err(|e| format!("カラー変換の作成に失敗: {:?}", e))?;
+        let src16: Vec<u16> = cmyk.iter().map(|v| v.to_u16().unwrap_or(0)).collect();
+        let mut dst16 = vec![0u16; pixels * 3];
+        transform.transform(&src16, &mut dst16).map_err(|e| format!("カラー変換に失敗: {:?}", e))?;
+        for (d, s) in rgb.iter_mut().zip(dst16) {
+            *d = T::from(s).unwrap_or(max);
+        }
+    }
+
+    let mut out: Vec<Vec<T>> = (0..3)
+        .map(|ch| rgb.iter().skip(ch).step_by(3).copied().collect())
+        .collect();
+    out.extend(planes.get(4).cloned());
+    Ok(out)
+}
+
+/// カラーモード: 入力画像と出力プロファイルから自動判定
+pub const COLOR_MODE_AUTO: &str = "auto";
+/// カラーモード: 常にRGBで出力
+pub const COLOR_MODE_RGB: &str = "rgb";
+/// カラーモード: 常に1チャンネルのグレースケールで出力
+pub const COLOR_MODE_GRAY: &str = "gray";
+
+/// グレースケールとみなすRGBの差の許容値（8bit換算、JPEG由来の色ノイズを吸収する）
+const GRAYSCALE_TOLERANCE: i32 = 2;
+
+/// 画像がグレースケール（全画素でR≒G≒B）か判定
+pub fn is_grayscale_image(img: &DynamicImage) -> bool {
+    fn is_neutral<T: Copy + Into<i32>>(samples: &[T], channels: usize, tolerance: i32) -> bool {
+        samples.chunks_exact(channels).all(|px| {
+            let (r, g, b) = (px[0].into(), px[1].into(), px[2].into());
+            (r - g).abs() <= tolerance && (g - b).abs() <= tolerance && (r - b).abs() <= tolerance
+        })
+    }
+
+    let tolerance16 = GRAYSCALE_TOLERANCE * 257;
+    match img {
+        _ if !img.color().has_color() => true,
+        DynamicImage::ImageRgb8(buf) => is_neutral(buf.as_raw(), 3, GRAYSCALE_TOLERANCE),
+        DynamicImage::ImageRgba8(buf) => is_neutral(buf.as_raw(), 4, GRAYSCALE_TOLERANCE),
+        DynamicImage::ImageRgb16(buf) => is_neutral(buf.as_raw(), 3, tolerance16),
+        DynamicImage::ImageRgba16(buf) => is_neutral(buf.as_raw(), 4, tolerance16),
+        _ => is_neutral(img.to_rgb16().as_raw(), 3, tolerance16),
+    }
+}
+
+/// グレースケールで出力するか判定
+/// "auto" はグレーの出力プロファイルなら常にグレー、sRGB出力ならRGB、"keep" なら画像の内容で判定する
+pub fn should_output_grayscale(img: &DynamicImage, color_mode: &str, color_profile: &str) -> bool {
+    match color_mode {
+        COLOR_MODE_GRAY => true,
+        COLOR_MODE_RGB => false,
+        _ => match color_profile {
+            COLOR_PROFILE_GRAY_GAMMA22 => true,
+            COLOR_PROFILE_SRGB => false,
+            _ => is_grayscale_image(img),
+        },
+    }
+}
+
+/// 画像を1チャンネルのグレースケールに変換
+/// RGBプロファイル付きの画像はGray Gamma 2.2へ色変換し、それ以外はビット深度を保ったまま輝度に変換する
+pub fn convert_to_grayscale(
+    img: DynamicImage,
+    icc_profile: Option<Vec<u8>>,
+) -> Result<(DynamicImage, Option<Vec<u8>>), String> {
+    let (img, icc_profile) = match icc_profile {
+        Some(icc) if is_rgb_profile(&icc) => convert_to_output_profile(img, Some(icc), COLOR_PROFILE_GRAY_GAMMA22)?,
+        icc_profile => (img, icc_profile),
+    };
+
+    let gray = if is_high_bit_depth(&img) {
+        DynamicImage::ImageLuma16(img.to_luma16())
+    } else {
+        DynamicImage::ImageLuma8(img.to_luma8())
+    };
+    Ok((gray, icc_profile))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::image::{GrayImage, Luma, Rgb, RgbImage};
+
+    fn display_p3() -> Vec<u8> {
+        ColorProfile::new_display_p3().encode().unwrap()
+    }
+
+    #[test]
+    fn test_matches_target_profile() {
+        let srgb = target_icc_profile(COLOR_PROFILE_SRGB).unwrap();
+        let gray = target_icc_profile(COLOR_PROFILE_GRAY_GAMMA22).unwrap();
+
+        // "keep" はどのプロファイルでも変換しない
+        assert!(matches_target_profile(Some(&display_p3()), COLOR_PROFILE_KEEP));
+        // プロファイルなしはsRGBとみなす
+        assert!(matches_target_profile(None, COLOR_PROFILE_SRGB));
+        assert!(!matches_target_profile(None, COLOR_PROFILE_GRAY_GAMMA22));
+
+        assert!(matches_target_profile(Some(srgb), COLOR_PROFILE_SRGB));
+        assert!(matches_target_profile(Some(gray), COLOR_PROFILE_GRAY_GAMMA22));
+        assert!(!matches_target_profile(Some(gray), COLOR_PROFILE_SRGB));
+        assert!(!matches_target_profile(Some(&display_p3()), COLOR_PROFILE_SRGB));
+
+        // バイト列が違っても原色が同じsRGBは一致とみなす
+        let mut other_srgb = ColorProfile::new_srgb();
+        other_srgb.description = Some(ProfileText::PlainString("sRGB built-in".to_string()));
+        let other_srgb = other_srgb.encode().unwrap();
+        assert_ne!(other_srgb.as_slice(), srgb);
+        assert!(matches_target_profile(Some(&other_srgb), COLOR_PROFILE_SRGB));
+    }
+
+    #[test]
+    fn test_convert_to_output_profile() {
+        let red = DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, Rgb([255, 0, 0])));
+
+        // "keep" は画像も元のプロファイルもそのまま返す
+        let p3 = display_p3();
+        let (img, icc) = convert_to_output_profile(red.clone(), Some(p3.clone()), COLOR_PROFILE_KEEP).unwrap();
+        assert_eq!(img, red);
+        assert_eq!(icc, Some(p3.clone()));
+
+        // プロファイルなし → sRGBは変換せず、sRGBプロファイルを付ける
+        let (img, icc) = convert_to_output_profile(red.clone(), None, COLOR_PROFILE_SRGB).unwrap();
+        assert_eq!(img.to_rgb8().get_pixel(0, 0).0, [255, 0, 0]);
+        assert_eq!(icc.as_deref(), target_icc_profile(COLOR_PROFILE_SRGB));
+
+        // Display P3の中間の赤はsRGBではより鮮やかな値になる
+        let p3_red = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, Rgb([200, 0, 0])));
+        let (img, _) = convert_to_output_profile(p3_red, Some(p3), COLOR_PROFILE_SRGB).unwrap();
+        let [r, g, b] = img.to_rgb8().get_pixel(0, 0).0;
+        assert!(r > 200 && g < 10 && b < 10, "{:?}", [r, g, b]);
+
+        // グレーの出力プロファイルには1チャンネルで変換し、16bitは16bitのまま
+        let (img, icc) = convert_to_output_profile(red.clone(), None, COLOR_PROFILE_GRAY_GAMMA22).unwrap();
+        assert!(!img.color().has_color());
+        assert_eq!(icc.as_deref(), target_icc_profile(COLOR_PROFILE_GRAY_GAMMA22));
+        let red16 = DynamicImage::ImageRgb16(red.to_rgb16());
+        let (img, _) = convert_to_output_profile(red16, None, COLOR_PROFILE_GRAY_GAMMA22).unwrap();
+        assert!(is_high_bit_depth(&img));
+    }
+
+    #[test]
+    fn test_should_output_grayscale() {
+        let gray = DynamicImage::ImageLuma8(GrayImage::from_pixel(2, 2, Luma([128])));
+        // JPEG由来の許容範囲内の色ずれはグレーとみなす
+        let near_gray = DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, Rgb([128, 129, 127])));
+        let color = DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, Rgb([128, 140, 128])));
+
+        assert!(should_output_grayscale(&color, COLOR_MODE_GRAY, COLOR_PROFILE_SRGB));
+        assert!(!should_output_grayscale(&gray, COLOR_MODE_RGB, COLOR_PROFILE_GRAY_GAMMA22));
+
+        // "auto" は出力プロファイルで決まり、"keep" なら画像の内容で判定する
+        assert!(should_output_grayscale(&color, COLOR_MODE_AUTO, COLOR_PROFILE_GRAY_GAMMA22));
+        assert!(!should_output_grayscale(&gray, COLOR_MODE_AUTO, COLOR_PROFILE_SRGB));
+        assert!(should_output_grayscale(&gray, COLOR_MODE_AUTO, COLOR_PROFILE_KEEP));
+        assert!(should_output_grayscale(&near_gray, COLOR_MODE_AUTO, COLOR_PROFILE_KEEP));
+        assert!(!should_output_grayscale(&color, COLOR_MODE_AUTO, COLOR_PROFILE_KEEP));
+    }
+}
@@ -0,0 +1,338 @@
+//! タチミ - スキャン原稿の仕上がり枠の自動検出
+//! 1. トンボ（左右・上下の端で対になる細い線）があれば、その最も内側の線を仕上がり線とする
+//! 2. トンボがなければ、紙・絵柄の境界（背景と異なる領域の外接矩形）を求める
+//!
+//! どちらも信頼度（0.0〜1.0）を付けて返す
+
+use ::image::{DynamicImage, GrayImage};
+use serde::Serialize;
+
+use super::image_processing::PixelRect;
+
+/// 検出方法
+pub const DETECT_METHOD_TRIM_MARKS: &str = "trim_marks";
+pub const DETECT_METHOD_CONTENT: &str = "content";
+
+/// 背景との輝度差がこれを超える画素をインクとみなす
+const INK_THRESHOLD: i16 = 64;
+/// 背景色を求める外周の幅（画像サイズに対する割合）
+const BORDER_RATIO: f32 = 0.01;
+/// トンボの線を探す端の範囲（画像サイズに対する割合）
+const MARK_EDGE_RATIO: f32 = 0.25;
+/// 仕上がり線とみなす範囲（中央付近のセンタートンボを除く）
+const MARK_LINE_RATIO: f32 = 0.35;
+/// トンボの線の最小長さ（画像の長辺に対する割合）
+const MARK_MIN_LENGTH_RATIO: f32 = 0.01;
+/// 内容のある行・列とみなすインクの割合
+const CONTENT_MIN_INK_RATIO: f32 = 0.005;
+/// 内容の境界に必要な連続行・列数（画像サイズに対する割合、ゴミ・ノイズやトンボの線を無視する）
+const CONTENT_RUN_RATIO: f32 = 0.005;
+/// 紙・絵柄の境界による検出の信頼度の上限（トンボより不確か）
+const CONTENT_MAX_CONFIDENCE: f32 = 0.8;
+
+/// 検出した仕上がり枠（画像座標）
+#[derive(Debug, Clone, Serialize)]
+pub struct DetectedCrop {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+    /// 信頼度（0.0〜1.0）
+    pub confidence: f32,
+    /// "trim_marks"（トンボ）, "content"（紙・絵柄の境界）
+    pub method: String,
+}
+
+impl DetectedCrop {
+    pub fn rect(&self) -> PixelRect {
+        (self.left, self.top, self.right, self.bottom)
+    }
+}
+
+/// インク画素のマスク
+struct InkMask {
+    width: u32,
+    height: u32,
+    ink: Vec<bool>,
+}
+
+impl InkMask {
+    /// 外周の輝度の中央値を背景（紙・スキャナーの地）として、背景との差でインクを判定
+    fn new(gray: &GrayImage) -> Self {
+        let (width, height) = gray.dimensions();
+        let border_x = ((width as f32 * BORDER_RATIO) as u32).max(1);
+        let border_y = ((height as f32 * BORDER_RATIO) as u32).max(1);
+        let mut border: Vec<u8> = gray
+            .enumerate_pixels()
+            .filter(|(x, y, _)| *x < border_x || *x >= width - border_x || *y < border_y || *y >= height - border_y)
+            .map(|(_, _, p)| p[0])
+            .collect();
+        border.sort_unstable();
+        let background = border.get(border.len() / 2).copied().unwrap_or(255) as i16;
+
+        let ink = gray.as_raw().iter().map(|v| (*v as i16 - background).abs() > INK_THRESHOLD).collect();
+        Self { width, height, ink }
+    }
+
+    fn get(&self, x: u32, y: u32) -> bool {
+        self.ink[y as usize * self.width as usize + x as usize]
+    }
+}
+
+/// 細い線分（across: 線に直交する方向の中心、start..end: 線の方向の範囲）
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    across: f32,
+    start: u32,
+    end: u32,
+}
+
+/// 画像の仕上がり枠を検出（トンボ → 紙・絵柄の境界の順）
+/// どちらも見つからなければ None
+pub fn detect_crop(img: &DynamicImage) -> Option<DetectedCrop> {
+    let mask = InkMask::new(&img.to_luma8());
+    if mask.width == 0 || mask.height == 0 {
+        return None;
+    }
+    detect_trim_marks(&mask).or_else(|| detect_content(&mask))
+}
+
+/// 細い線分を探す
+/// ink(along, across) は線の方向・直交方向の座標でインクを判定する
+/// 直交方向に gap 離れた画素が背景の区間を線とし、隣り合う行の区間は1本の線（太さ数ピクセル）にまとめる
+fn find_thin_segments(
+    ink: impl Fn(u32, u32) -> bool,
+    along_len: u32,
+    across_len: u32,
+    min_len: u32,
+    gap: u32,
+) -> Vec<Segment> {
+    // (start, end, first, last)
+    let mut open: Vec<(u32, u32, u32, u32)> = Vec::new();
+    let mut closed = Vec::new();
+
+    for a in 0..across_len {
+        let is_thin = |p: u32| {
+            ink(p, a)
+                && (a < gap || !ink(p, a - gap))
+                && (a + gap >= across_len || !ink(p, a + gap))
+        };
+
+        let mut next_open = Vec::new();
+        let mut p = 0;
+        while p < along_len {
+            if !is_thin(p) {
+                p += 1;
+                continue;
+            }
+            let start = p;
+            while p < along_len && is_thin(p) {
+                p += 1;
+            }
+            if p - start < min_len {
+                continue;
+            }
+            match open.iter().position(|(s, e, _, _)| *s < p && start < *e) {
+                Some(index) => {
+                    let (s, e, first, _) = open.swap_remove(index);
+                    next_open.push((s.min(start), e.max(p), first, a));
+                }
+                None => next_open.push((start, p, a, a)),
+            }
+        }
+        closed.append(&mut open);
+        open = next_open;
+    }
+    closed.append(&mut open);
+
+    closed
+        .into_iter()
+        .filter(|(_, _, first, last)| last - first < gap)
+        .map(|(start, end, first, last)| Segment { across: (first + last) as f32 / 2.0, start, end })
+        .collect()
+}
+
+/// 両端（手前側・奥側）で同じ位置にある線分の位置を返す（トンボは左右・上下対称に付く）
+fn paired_lines(segments: &[Segment], along_len: u32, tolerance: f32) -> Vec<f32> {
+    let near_edge = along_len as f32 * MARK_EDGE_RATIO;
+    let far_edge = along_len as f32 * (1.0 - MARK_EDGE_RATIO);
+    segments
+        .iter()
+        .filter(|s| (s.end as f32) < near_edge)
+        .filter_map(|near| {
+            segments
+                .iter()
+                .filter(|s| s.start as f32 > far_edge)
+                .find(|far| (far.across - near.across).abs() <= tolerance)
+                .map(|far| (near.across + far.across) / 2.0)
+        })
+        .collect()
+}
+
+/// 画像の両側にある線のうち、最も内側の組（裁ち落とし線より内側の仕上がり線）
+fn innermost_pair(lines: &[f32], size: u32) -> Option<(f32, f32)> {
+    let start = lines.iter().copied().filter(|v| *v < size as f32 * MARK_LINE_RATIO).reduce(f32::max)?;
+    let end = lines.iter().copied().filter(|v| *v > size as f32 * (1.0 - MARK_LINE_RATIO)).reduce(f32::min)?;
+    Some((start, end))
+}
+
+/// トンボから仕上がり線を検出
+/// 4本の仕上がり線が見つかれば基本の信頼度、角ごとに縦横のトンボが枠の外側にあることを確かめて加点する
+fn detect_trim_marks(mask: &InkMask) -> Option<DetectedCrop> {
+    let (width, height) = (mask.width, mask.height);
+    let min_len = ((width.max(height) as f32 * MARK_MIN_LENGTH_RATIO) as u32).max(8);
+    let gap = (width.max(height) / 400).max(3);
+    let tolerance = gap as f32;
+
+    let horizontal = find_thin_segments(|x, y| mask.get(x, y), width, height, min_len, gap);
+    let vertical = find_thin_segments(|y, x| mask.get(x, y), height, width, min_len, gap);
+
+    let (top, bottom) = innermost_pair(&paired_lines(&horizontal, width, tolerance), height)?;
+    let (left, right) = innermost_pair(&paired_lines(&vertical, height, tolerance), width)?;
+
+    // 角の確認: 仕上がり線上の横線・縦線が枠の外側にある
+    let outside = |segments: &[Segment], across: f32, limit: f32, before: bool| {
+        segments.iter().any(|s| {
+            (s.across - across).abs() <= tolerance
+                && if before { s.end as f32 <= limit + tolerance } else { s.start as f32 >= limit - tolerance }
+        })
+    };
+    let verified_corners = [(left, true), (right, false)]
+        .iter()
+        .flat_map(|&(x, is_left)| [(x, is_left, top, true), (x, is_left, bottom, false)])
+        .filter(|&(x, is_left, y, is_top)| {
+            outside(&horizontal, y, x, is_left) && outside(&vertical, x, y, is_top)
+        })
+        .count();
+
+    Some(DetectedCrop {
+        left: left.round() as u32,
+        top: top.round() as u32,
+        right: right.round() as u32,
+        bottom: bottom.round() as u32,
+        confidence: 0.6 + 0.1 * verified_corners as f32,
+        method: DETECT_METHOD_TRIM_MARKS.to_string(),
+    })
+}
+
+/// 最初に内容のある行・列が run 本続く位置
+fn first_content(profile: &[f32], run: usize) -> Option<usize> {
+    (0..=profile.len().checked_sub(run)?).find(|i| profile[*i..*i + run].iter().all(|v| *v >= CONTENT_MIN_INK_RATIO))
+}
+
+/// 最後に内容のある行・列が run 本続く位置（終端、排他的）
+fn last_content(profile: &[f32], run: usize) -> Option<usize> {
+    (run..=profile.len()).rev().find(|end| profile[*end - run..*end].iter().all(|v| *v >= CONTENT_MIN_INK_RATIO))
+}
+
+/// 境界の内側と外側のインク密度の差（0.0〜1.0、外側がない場合は0）
+fn edge_contrast(profile: &[f32], inside: std::ops::Range<usize>, outside: std::ops::Range<usize>) -> f32 {
+    if outside.is_empty() || inside.is_empty() {
+        return 0.0;
+    }
+    let mean = |range: std::ops::Range<usize>| {
+        let len = range.len() as f32;
+        profile[range].iter().sum::<f32>() / len
+    };
+    let (d_in, d_out) = (mean(inside), mean(outside));
+    if d_in + d_out <= 0.0 {
+        return 0.0;
+    }
+    ((d_in - d_out) / (d_in + d_out)).clamp(0.0, 1.0)
+}
+
+/// 紙・絵柄の境界を検出
+/// 内容のある行・列が連続する範囲の外接矩形とし、各辺の内外の密度差を信頼度にする
+fn detect_content(mask: &InkMask) -> Option<DetectedCrop> {
+    let (width, height) = (mask.width as usize, mask.height as usize);
+    let run_y = ((height as f32 * CONTENT_RUN_RATIO) as usize).max(1);
+    let run_x = ((width as f32 * CONTENT_RUN_RATIO) as usize).max(1);
+
+    let rows: Vec<f32> = (0..height)
+        .map(|y| mask.ink[y * width..(y + 1) * width].iter().filter(|v| **v).count() as f32 / width as f32)
+        .collect();
+    let top = first_content(&rows, run_y)?;
+    let bottom = last_content(&rows, run_y)?;
+    if bottom <= top {
+        return None;
+    }
+
+    // 列は内容のある行の範囲だけで数える（余白のトンボ・ゴミを含めない）
+    let mut column_counts = vec![0u32; width];
+    for y in top..bottom {
+        for (count, ink) in column_counts.iter_mut().zip(&mask.ink[y * width..(y + 1) * width]) {
+            *count += *ink as u32;
+        }
+    }
+    let columns: Vec<f32> = column_counts.iter().map(|c| *c as f32 / (bottom - top) as f32).collect();
+    let left = first_content(&columns, run_x)?;
+    let right = last_content(&columns, run_x)?;
+    if right <= left || (left == 0 && top == 0 && right == width && bottom == height) {
+        return None;
+    }
+
+    let band_y = run_y * 2;
+    let band_x = run_x * 2;
+    let scores = [
+        edge_contrast(&rows, top..(top + band_y).min(bottom), top.saturating_sub(band_y)..top),
+        edge_contrast(&rows, bottom.saturating_sub(band_y).max(top)..bottom, bottom..(bottom + band_y).min(height)),
+        edge_contrast(&columns, left..(left + band_x).min(right), left.saturating_sub(band_x)..left),
+        edge_contrast(&columns, right.saturating_sub(band_x).max(left)..right, right..(right + band_x).min(width)),
+    ];
+
+    Some(DetectedCrop {
+        left: left as u32,
+        top: top as u32,
+        right: right as u32,
+        bottom: bottom as u32,
+        confidence: CONTENT_MAX_CONFIDENCE * scores.iter().sum::<f32>() / scores.len() as f32,
+        method: DETECT_METHOD_CONTENT.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::image_processing::{crop_with_bleed, draw_trim_marks};
+    use crate::processor::types::TrimMarkOptions;
+    use ::image::{Luma, Rgba, RgbaImage};
+
+    #[test]
+    fn test_detect_trim_marks() {
+        // 自前のトンボ描画で作ったページから仕上がり枠を求める
+        let art = DynamicImage::ImageRgba8(RgbaImage::from_pixel(800, 1100, Rgba([90, 90, 90, 255])));
+        let marks = TrimMarkOptions::default();
+        let (page, origin) = crop_with_bleed::<Luma<u8>>(&art, 50, 50, 700, 1000, &marks, 150.0);
+        let mut page = page;
+        draw_trim_marks(&mut page, origin, 700, 1000, &marks, 150.0);
+
+        let detected = detect_crop(&DynamicImage::ImageLuma8(page)).unwrap();
+        assert_eq!(detected.method, DETECT_METHOD_TRIM_MARKS);
+        let expected = (origin, origin, origin + 700, origin + 1000);
+        let (l, t, r, b) = detected.rect();
+        for (got, want) in [(l, expected.0), (t, expected.1), (r, expected.2), (b, expected.3)] {
+            assert!(got.abs_diff(want) <= 1, "{:?} != {:?}", detected.rect(), expected);
+        }
+        assert!(detected.confidence >= 0.9);
+    }
+
+    #[test]
+    fn test_detect_content() {
+        // 白い紙に絵柄とゴミ1点
+        let mut gray = GrayImage::from_pixel(600, 800, Luma([250]));
+        for y in 100..700 {
+            for x in 80..520 {
+                gray.put_pixel(x, y, Luma([if (x / 20 + y / 20) % 2 == 0 { 20 } else { 200 }]));
+            }
+        }
+        gray.put_pixel(10, 10, Luma([0]));
+
+        let detected = detect_crop(&DynamicImage::ImageLuma8(gray)).unwrap();
+        assert_eq!(detected.method, DETECT_METHOD_CONTENT);
+        assert_eq!(detected.rect(), (80, 100, 520, 700));
+        assert!(detected.confidence > 0.5);
+
+        // 何もない紙は検出しない
+        assert!(detect_crop(&DynamicImage::ImageLuma8(GrayImage::from_pixel(100, 100, Luma([255])))).is_none());
+    }
+}
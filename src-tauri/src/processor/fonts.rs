@@ -0,0 +1,359 @@
+//! タチミ - フォントの検索・読み込み
+//! Windows・macOS・Linuxのフォントフォルダから欧文（数字用）・和文フォントを探す
+//! ユーザーが指定したフォントファイルと書体番号（TTC・OTCの何番目か）にも対応する
+
+use ab_glyph::FontRef;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use walkdir::WalkDir;
+
+use super::types::FontSpec;
+
+/// 検索したフォントファイルの一覧（一度だけ検索）
+static FONT_FILES: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+/// フォントデータのキャッシュ（キー: ファイルパス）
+static FONT_DATA_CACHE: OnceLock<Mutex<HashMap<PathBuf, Arc<Vec<u8>>>>> = OnceLock::new();
+
+/// フォントフォルダを検索する深さ
+const FONT_SEARCH_DEPTH: usize = 5;
+
+/// 数字用の欧文フォント（優先順、ファイル名は小文字）
+const LATIN_FONT_FILES: &[&str] = &[
+    "arial.ttf",
+    "segoeui.ttf",
+    "calibri.ttf",
+    "helvetica.ttc",
+    "helveticaneue.ttc",
+    "dejavusans.ttf",
+    "liberationsans-regular.ttf",
+    "notosans-regular.ttf",
+];
+
+/// 和文フォント（優先順、ファイル名は小文字）
+const JP_FONT_FILES: &[&str] = &[
+    "yugothb.ttc",                // Yu Gothic Bold
+    "yugothm.ttc",                // Yu Gothic Medium
+    "yugothib.ttf",               // Yu Gothic UI Bold
+    "meiryob.ttc",                // Meiryo Bold
+    "meiryo.ttc",                 // Meiryo
+    "msgothic.ttc",               // MS Gothic
+    "ヒラギノ角ゴシック w6.ttc",  // macOS
+    "ヒラギノ角ゴシック w3.ttc",
+    "notosanscjk-bold.ttc",       // Linux（先頭の書体が日本語）
+    "notosanscjk-regular.ttc",
+    "notosanscjkjp-regular.otf",
+    "notosansjp-regular.otf",
+    "notosansjp-regular.ttf",
+    "ipaexg.ttf",
+    "ipagp.ttf",
+    "ipag.ttf",
+    "takaopgothic.ttf",
+    "vl-gothic-regular.ttf",
+];
+
+/// フォントの用途
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontRole {
+    /// 数字・欧文（見つからなければ和文フォントを使う）
+    Latin,
+    /// 日本語
+    Japanese,
+}
+
+/// 読み込んだフォント
+#[derive(Clone)]
+pub struct LoadedFont {
+    pub path: PathBuf,
+    pub data: Arc<Vec<u8>>,
+    /// TTC・OTCの書体番号（単体のフォントは0）
+    pub face_index: u32,
+}
+
+impl LoadedFont {
+    /// 描画用のフォント
+    pub fn font_ref(&self) -> Result<FontRef<'_>, String> {
+        FontRef::try_from_slice_and_index(&self.data, self.face_index).map_err(|e| {
+            format!("フォントを読み込めません（{} の書体番号 {}）: {}", self.path.display(), self.face_index, e)
+        })
+    }
+}
+
+/// フォントファイルの情報（フォント選択用）
+#[derive(Debug, Serialize, Clone)]
+pub struct FontFileInfo {
+    pub path: String,
+    /// 書体名（書体番号順）
+    pub faces: Vec<String>,
+}
+
+/// 用途に合うフォントを読み込む
+/// spec を指定した場合はそのファイル・書体番号、なければシステムから検索する
+pub fn load_font(spec: Option<&FontSpec>, role: FontRole) -> Result<LoadedFont, String> {
+    let (path, face_index) = match spec.filter(|spec| !spec.path.is_empty()) {
+        Some(spec) => (PathBuf::from(&spec.path), spec.face_index),
+        None => (find_default_font(role).ok_or_else(|| missing_font_message(role))?, 0),
+    };
+    let font = LoadedFont { data: read_font_data(&path)?, path, face_index };
+    font.font_ref()?;
+    Ok(font)
+}
+
+fn missing_font_message(role: FontRole) -> String {
+    match role {
+        FontRole::Latin => "数字用のフォントが見つかりません。フォントファイルを指定してください".to_string(),
+        FontRole::Japanese => "日本語フォントが見つかりません。フォントファイルを指定してください".to_string(),
+    }
+}
+
+/// 用途に合うシステムフォントを検索
+pub fn find_default_font(role: FontRole) -> Option<PathBuf> {
+    let files = font_files();
+    let find = |names: &[&str]| {
+        names.iter().find_map(|name| {
+            files.iter().find(|path| normalized_file_name(path).as_deref() == Some(*name)).cloned()
+        })
+    };
+
+    match role {
+        FontRole::Latin => find(LATIN_FONT_FILES).or_else(|| find(JP_FONT_FILES)),
+        FontRole::Japanese => find(JP_FONT_FILES),
+    }
+}
+
+/// 検索したフォントファイルと書体名の一覧
+pub fn list_fonts() -> Vec<FontFileInfo> {
+    font_files()
+        .iter()
+        .map(|path| FontFileInfo {
+            path: path.to_string_lossy().to_string(),
+            faces: read_face_names(path).unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// フォントファイルを読み込む（キャッシュ）
+fn read_font_data(path: &Path) -> Result<Arc<Vec<u8>>, String> {
+    let cache = FONT_DATA_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(data) = cache.lock().ok().and_then(|cache| cache.get(path).cloned()) {
+        return Ok(data);
+    }
+    let data = Arc::new(
+        std::fs::read(path).map_err(|e| format!("フォントファイルを開けません（{}）: {}", path.display(), e))?,
+    );
+    if let Ok(mut cache) = cache.lock() {
+        cache.insert(path.to_path_buf(), data.clone());
+    }
+    Ok(data)
+}
+
+/// OSごとのフォントフォルダ
+fn font_dirs() -> Vec<PathBuf> {
+    let mut folders = Vec::new();
+    let home = dirs::home_dir();
+
+    if cfg!(target_os = "windows") {
+        let windir = std::env::var_os("WINDIR").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("C:\\Windows"));
+        folders.push(windir.join("Fonts"));
+        if let Some(local) = dirs::data_local_dir() {
+            folders.push(local.join("Microsoft").join("Windows").join("Fonts"));
+        }
+    } else if cfg!(target_os = "macos") {
+        folders.push(PathBuf::from("/System/Library/Fonts"));
+        folders.push(PathBuf::from("/Library/Fonts"));
+        if let Some(home) = &home {
+            folders.push(home.join("Library").join("Fonts"));
+        }
+    } else {
+        folders.push(PathBuf::from("/usr/share/fonts"));
+        folders.push(PathBuf::from("/usr/local/share/fonts"));
+        if let Some(data) = dirs::data_dir() {
+            folders.push(data.join("fonts"));
+        }
+        if let Some(home) = &home {
+            folders.push(home.join(".fonts"));
+        }
+    }
+    folders
+}
+
+/// フォントフォルダ内のフォントファイル（.ttf, .otf, .ttc, .otc）
+fn font_files() -> &'static Vec<PathBuf> {
+    FONT_FILES.get_or_init(|| {
+        font_dirs()
+            .iter()
+            .filter(|dir| dir.is_dir())
+            .flat_map(|dir| WalkDir::new(dir).max_depth(FONT_SEARCH_DEPTH).follow_links(true))
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| entry.into_path())
+            .filter(|path| {
+                let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+                matches!(ext.as_str(), "ttf" | "otf" | "ttc" | "otc")
+            })
+            .collect()
+    })
+}
+
+/// 比較用のファイル名（小文字、濁点・半濁点は合成済みの文字にする）
+fn normalized_file_name(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    Some(compose_kana(name).to_lowercase())
+}
+
+/// 濁点・半濁点の結合文字（macOSのファイル名で使われる）を合成済みのかなにする
+fn compose_kana(text: &str) -> String {
+    let mut composed = String::with_capacity(text.len());
+    for c in text.chars() {
+        let offset = match c {
+            '\u{3099}' => 1,
+            '\u{309A}' => 2,
+            _ => {
+                composed.push(c);
+                continue;
+            }
+        };
+        // か〜と・は〜ほ（ひらがな・カタカナ）は濁音が+1、は〜ほの半濁音が+2の位置にある
+        let base = composed.pop();
+        let voiced = base
+            .filter(|b| match offset {
+                1 => matches!(b, 'か'..='と' | 'は'..='ほ' | 'カ'..='ト' | 'ハ'..='ホ'),
+                _ => matches!(b, 'は'..='ほ' | 'ハ'..='ホ'),
+            })
+            .and_then(|b| char::from_u32(b as u32 + offset));
+        match (voiced, base) {
+            (Some(v), _) => composed.push(v),
+            (None, Some(b)) => {
+                composed.push(b);
+                composed.push(c);
+            }
+            (None, None) => composed.push(c),
+        }
+    }
+    composed
+}
+
+/// フォントファイルの書体名（書体番号順）
+fn read_face_names(path: &Path) -> Result<Vec<String>, String> {
+    let mut file = File::open(path).map_err(|e| format!("フォントファイルを開けません: {}", e))?;
+    let header = read_at(&mut file, 0, 12)?;
+    let offsets = if &header[0..4] == b"ttcf" {
+        let count = be_u32(&header, 8) as usize;
+        let table = read_at(&mut file, 12, count.min(256) * 4)?;
+        (0..count.min(256)).map(|i| be_u32(&table, i * 4) as u64).collect()
+    } else {
+        vec![0]
+    };
+
+    let mut names = Vec::new();
+    for offset in offsets {
+        let directory = read_at(&mut file, offset, 12)?;
+        let table_count = be_u16(&directory, 4) as usize;
+        let records = read_at(&mut file, offset + 12, table_count * 16)?;
+        let name_record = (0..table_count).map(|i| &records[i * 16..i * 16 + 16]).find(|r| &r[0..4] == b"name");
+        let name = match name_record {
+            Some(record) => {
+                let table = read_at(&mut file, be_u32(record, 8) as u64, be_u32(record, 12) as usize)?;
+                parse_full_name(&table)
+            }
+            None => None,
+        };
+        names.push(name.unwrap_or_default());
+    }
+    Ok(names)
+}
+
+/// nameテーブルから表示名を取り出す（日本語名 → 英語名 → その他の順）
+fn parse_full_name(table: &[u8]) -> Option<String> {
+    if table.len() < 6 {
+        return None;
+    }
+    let count = be_u16(table, 2) as usize;
+    let string_offset = be_u16(table, 4) as usize;
+
+    let mut best: Option<(u8, String)> = None;
+    for i in 0..count {
+        let record = table.get(6 + i * 12..6 + i * 12 + 12)?;
+        let (platform, language, name_id) = (be_u16(record, 0), be_u16(record, 4), be_u16(record, 6));
+        if name_id != 4 {
+            continue;
+        }
+        let (length, offset) = (be_u16(record, 8) as usize, be_u16(record, 10) as usize);
+        let Some(bytes) = table.get(string_offset + offset..string_offset + offset + length) else {
+            continue;
+        };
+        let (rank, text) = match (platform, language) {
+            (3, 0x0411) => (0, decode_utf16_be(bytes)),
+            (3, 0x0409) => (1, decode_utf16_be(bytes)),
+            (0, _) | (3, _) => (2, decode_utf16_be(bytes)),
+            (1, 0) => (3, String::from_utf8_lossy(bytes).to_string()),
+            _ => continue,
+        };
+        let better = match &best {
+            Some((best_rank, _)) => rank < *best_rank,
+            None => true,
+        };
+        if better {
+            best = Some((rank, text));
+        }
+    }
+    best.map(|(_, text)| text)
+}
+
+fn decode_utf16_be(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn read_at(file: &mut File, offset: u64, length: usize) -> Result<Vec<u8>, String> {
+    let mut buf = vec![0u8; length];
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.read_exact(&mut buf))
+        .map_err(|e| format!("フォントファイルの読み込みに失敗: {}", e))?;
+    Ok(buf)
+}
+
+fn be_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+fn be_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compose_kana_file_name() {
+        let decomposed = "ヒラキ\u{3099}ノ角コ\u{3099}シック W6.ttc";
+        assert_eq!(normalized_file_name(Path::new(decomposed)).unwrap(), "ヒラギノ角ゴシック w6.ttc");
+        assert_eq!(compose_kana("ハ\u{309A}"), "パ");
+        assert_eq!(compose_kana("ア\u{3099}"), "ア\u{3099}");
+    }
+
+    #[test]
+    fn test_parse_full_name() {
+        // nameテーブル: 英語名と日本語名のフルネーム（nameID 4）
+        let english: Vec<u8> = "Gothic".encode_utf16().flat_map(|u| u.to_be_bytes()).collect();
+        let japanese: Vec<u8> = "ゴシック".encode_utf16().flat_map(|u| u.to_be_bytes()).collect();
+        let mut table = Vec::new();
+        for v in [0u16, 2, 6 + 24] {
+            table.extend_from_slice(&v.to_be_bytes());
+        }
+        for (language, length, offset) in [(0x0409u16, english.len(), 0usize), (0x0411, japanese.len(), english.len())] {
+            for v in [3u16, 1, language, 4, length as u16, offset as u16] {
+                table.extend_from_slice(&v.to_be_bytes());
+            }
+        }
+        table.extend_from_slice(&english);
+        table.extend_from_slice(&japanese);
+
+        assert_eq!(parse_full_name(&table).as_deref(), Some("ゴシック"));
+    }
+}
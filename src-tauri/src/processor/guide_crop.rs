@@ -0,0 +1,79 @@
+//! タチミ - PSDのガイドからの仕上がり枠の推定
+//! 縦・横それぞれ、画像中心より手前と奥からガイドを1本ずつ選んで仕上がり枠とする
+//! ガイドが片側でも足りなければ推定しない（共通のクロップ範囲を使う）
+
+use super::image_loader::PsdGuide;
+use super::image_processing::PixelRect;
+
+/// 仕上がり枠の取得元（"auto" は crop_detection で検出する）
+pub const CROP_SOURCE_GLOBAL: &str = "global";
+pub const CROP_SOURCE_GUIDES: &str = "guides";
+pub const CROP_SOURCE_AUTO: &str = "auto";
+
+/// ガイドの選び方
+pub const GUIDE_RULE_NEAREST: &str = "nearest";
+pub const GUIDE_RULE_INNERMOST: &str = "innermost";
+pub const GUIDE_RULE_OUTERMOST: &str = "outermost";
+
+/// ガイドから仕上がり枠を求める
+/// reference: 共通のクロップ範囲（画像座標）。"nearest" ではこれに最も近いガイドを選ぶ
+pub fn crop_from_guides(
+    guides: &[PsdGuide],
+    width: u32,
+    height: u32,
+    rule: &str,
+    reference: PixelRect,
+) -> Option<PixelRect> {
+    let positions = |guide_type: &str| -> Vec<f64> {
+        guides.iter().filter(|g| g.guide_type == guide_type).map(|g| g.position).collect()
+    };
+    let (left, right) = pick_pair(&positions("v"), width, rule, (reference.0, reference.2))?;
+    let (top, bottom) = pick_pair(&positions("h"), height, rule, (reference.1, reference.3))?;
+    Some((left, top, right, bottom))
+}
+
+/// 1軸分のガイドから始点・終点を選ぶ（画像の端ちょうど・外側のガイドは使わない）
+fn pick_pair(positions: &[f64], size: u32, rule: &str, reference: (u32, u32)) -> Option<(u32, u32)> {
+    let center = size as f64 / 2.0;
+    let before: Vec<f64> = positions.iter().copied().filter(|p| *p > 0.0 && *p < center).collect();
+    let after: Vec<f64> = positions.iter().copied().filter(|p| *p > center && *p < size as f64).collect();
+
+    let nearest = |candidates: &[f64], target: u32| {
+        candidates.iter().copied().min_by(|a, b| {
+            (a - target as f64).abs().total_cmp(&(b - target as f64).abs())
+        })
+    };
+    let (start, end) = match rule {
+        GUIDE_RULE_INNERMOST => (before.iter().copied().reduce(f64::max)?, after.iter().copied().reduce(f64::min)?),
+        GUIDE_RULE_OUTERMOST => (before.iter().copied().reduce(f64::min)?, after.iter().copied().reduce(f64::max)?),
+        _ => (nearest(&before, reference.0)?, nearest(&after, reference.1)?),
+    };
+    Some((start.round() as u32, end.round() as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guide(guide_type: &str, position: f64) -> PsdGuide {
+        PsdGuide { guide_type: guide_type.to_string(), position }
+    }
+
+    #[test]
+    fn test_crop_from_guides() {
+        // 裁ち落とし（20/980, 30/1470）・仕上がり（50/950, 60/1440）の2組
+        let guides = vec![
+            guide("v", 20.0), guide("v", 50.0), guide("v", 950.0), guide("v", 980.0),
+            guide("h", 30.0), guide("h", 60.0), guide("h", 1440.0), guide("h", 1470.0),
+            guide("v", 0.0), guide("h", 1500.0),
+        ];
+        let reference = (48, 62, 952, 1438);
+        assert_eq!(crop_from_guides(&guides, 1000, 1500, GUIDE_RULE_INNERMOST, reference), Some((50, 60, 950, 1440)));
+        assert_eq!(crop_from_guides(&guides, 1000, 1500, GUIDE_RULE_OUTERMOST, reference), Some((20, 30, 980, 1470)));
+        assert_eq!(crop_from_guides(&guides, 1000, 1500, GUIDE_RULE_NEAREST, (25, 28, 975, 1465)), Some((20, 30, 980, 1470)));
+
+        // 横ガイドが片側しかない場合は推定しない
+        let partial = vec![guide("v", 50.0), guide("v", 950.0), guide("h", 60.0)];
+        assert_eq!(crop_from_guides(&partial, 1000, 1500, GUIDE_RULE_INNERMOST, reference), None);
+    }
+}
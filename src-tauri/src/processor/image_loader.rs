@@ -1,12 +1,15 @@
 //! タチミ - 画像読み込みモジュール
 //! PSD/PNG/JPEG/TIFFなどの画像読み込みを担当
 
-use ::image::{DynamicImage, ImageBuffer, ImageFormat, RgbaImage};
+use ::image::{DynamicImage, ImageBuffer, ImageDecoder, ImageFormat, ImageReader, Primitive, Rgba, Rgba32FImage, RgbaImage};
+use flate2::read::ZlibDecoder;
 use serde::Serialize;
 use std::fs::File;
 use std::io::{BufReader, Read, Seek, SeekFrom};
 use std::path::Path;
 
+use super::color::{cmyk_planes_to_srgb, is_cmyk_profile, target_icc_profile, COLOR_PROFILE_SRGB};
+
 /// PSDファイルから抽出されたガイド情報
 #[derive(Debug, Clone, Serialize)]
 pub struct PsdGuide {
@@ -16,165 +19,324 @@ pub struct PsdGuide {
     pub position: f64,
 }
 
-/// 画像ファイルを読み込む
-pub fn load_image(path: &Path) -> Result<DynamicImage, String> {
-    let ext = path
-        .extension()
+/// PSDカラーモード
+pub const COLOR_MODE_BITMAP: u16 = 0;
+pub const COLOR_MODE_GRAYSCALE: u16 = 1;
+pub const COLOR_MODE_INDEXED: u16 = 2;
+pub const COLOR_MODE_RGB: u16 = 3;
+pub const COLOR_MODE_CMYK: u16 = 4;
+pub const COLOR_MODE_DUOTONE: u16 = 8;
+
+/// ファイルがPSD/PSBかどうか判定
+pub fn is_psd_file(path: &Path) -> bool {
+    path.extension()
         .and_then(|e| e.to_str())
-        .unwrap_or("")
-        .to_lowercase();
+        .map(|e| e.to_lowercase())
+        .map(|e| e == "psd" || e == "psb")
+        .unwrap_or(false)
+}
 
-    if ext == "psd" {
+/// 画像ファイルを読み込む
+pub fn load_image(path: &Path) -> Result<DynamicImage, String> {
+    if is_psd_file(path) {
         load_psd_fast(path)
     } else {
         ::image::open(path).map_err(|e| format!("画像の読み込みに失敗: {}", e))
     }
 }
 
+/// 画像ファイルを埋め込みICCプロファイルと一緒に読み込む
+/// 返すプロファイルは読み込んだピクセルの色空間を表す（CMYKのPSDはsRGBに変換済み）
+pub fn load_image_with_profile(path: &Path) -> Result<(DynamicImage, Option<Vec<u8>>), String> {
+    if is_psd_file(path) {
+        return load_psd_fast_with_profile(path);
+    }
+
+    let mut decoder = ImageReader::open(path)
+        .and_then(|r| r.with_guessed_format())
+        .map_err(|e| format!("画像の読み込みに失敗: {}", e))?
+        .into_decoder()
+        .map_err(|e| format!("画像の読み込みに失敗: {}", e))?;
+    let icc_profile = decoder.icc_profile().ok().flatten();
+    let img = DynamicImage::from_decoder(decoder).map_err(|e| format!("画像の読み込みに失敗: {}", e))?;
+    Ok((img, icc_profile))
+}
+
 /// PSDファイルを高速読み込み
 /// まずフラット化画像を試し、失敗したらレイヤー合成にフォールバック
 pub fn load_psd_fast(path: &Path) -> Result<DynamicImage, String> {
+    load_psd_fast_with_profile(path).map(|(img, _)| img)
+}
+
+/// PSDファイルを埋め込みICCプロファイルと一緒に高速読み込み
+pub fn load_psd_fast_with_profile(path: &Path) -> Result<(DynamicImage, Option<Vec<u8>>), String> {
     match load_psd_composite(path) {
-        Ok(img) => Ok(img),
+        Ok(loaded) => Ok(loaded),
         Err(_) => {
             // フォールバック: psd crateでレイヤー合成（遅いが確実）
-            load_psd_with_layers(path)
+            // CMYKはpsd crateの簡易変換になるため、プロファイルは引き継がない
+            let img = load_psd_with_layers(path)?;
+            let icc_profile = extract_psd_icc_profile(path).filter(|icc| !is_cmyk_profile(icc));
+            Ok((img, icc_profile))
         }
     }
 }
 
-/// PSDファイルのImage Dataセクションを直接読み込む（高速版）
-/// Photoshopの「互換性を最大に」で保存されたPSDには、
-/// 合成済みのフラット化画像が含まれている。
-fn load_psd_composite(path: &Path) -> Result<DynamicImage, String> {
-    let file = File::open(path).map_err(|e| format!("ファイルを開けません: {}", e))?;
-    let mut file = BufReader::with_capacity(64 * 1024, file);
-    let mut buf4 = [0u8; 4];
-    let mut buf2 = [0u8; 2];
+/// PSD/PSBファイルヘッダー
+#[derive(Debug, Clone, Copy)]
+pub struct PsdHeader {
+    /// 1 = PSD, 2 = PSB（ラージドキュメント形式）
+    pub version: u16,
+    pub channels: usize,
+    pub height: u32,
+    pub width: u32,
+    pub depth: u16,
+    pub color_mode: u16,
+}
+
+/// ファイルヘッダー（26バイト）を読み込む
+pub fn read_psd_header<R: Read>(file: &mut R) -> Result<PsdHeader, String> {
+    let mut header = [0u8; 26];
+    file.read_exact(&mut header).map_err(|e| format!("PSD読み込みエラー: {}", e))?;
 
-    // === Header (26 bytes) ===
     // Signature: "8BPS"
-    file.read_exact(&mut buf4).map_err(|e| format!("PSD読み込みエラー: {}", e))?;
-    if &buf4 != b"8BPS" {
+    if &header[0..4] != b"8BPS" {
         return Err("無効なPSDファイル".to_string());
     }
 
     // Version (2 bytes)
-    file.read_exact(&mut buf2).map_err(|e| format!("PSD読み込みエラー: {}", e))?;
-    let version = u16::from_be_bytes(buf2);
+    let version = u16::from_be_bytes([header[4], header[5]]);
     if version != 1 && version != 2 {
         return Err("サポートされていないPSDバージョン".to_string());
     }
 
-    // Reserved (6 bytes)
-    file.seek(SeekFrom::Current(6)).map_err(|e| format!("シークエラー: {}", e))?;
+    // Reserved (6 bytes) + Channels (2) + Height (4) + Width (4) + Depth (2) + Color Mode (2)
+    Ok(PsdHeader {
+        version,
+        channels: u16::from_be_bytes([header[12], header[13]]) as usize,
+        height: u32::from_be_bytes([header[14], header[15], header[16], header[17]]),
+        width: u32::from_be_bytes([header[18], header[19], header[20], header[21]]),
+        depth: u16::from_be_bytes([header[22], header[23]]),
+        color_mode: u16::from_be_bytes([header[24], header[25]]),
+    })
+}
 
-    // Channels (2 bytes)
-    file.read_exact(&mut buf2).map_err(|e| format!("PSD読み込みエラー: {}", e))?;
-    let channels = u16::from_be_bytes(buf2) as usize;
+/// セクション長を読み込む（PSBは8バイト、PSDは4バイト）
+pub fn read_section_length<R: Read>(file: &mut R, version: u16) -> Result<u64, String> {
+    if version == 2 {
+        let mut buf8 = [0u8; 8];
+        file.read_exact(&mut buf8).map_err(|e| format!("PSD読み込みエラー: {}", e))?;
+        Ok(u64::from_be_bytes(buf8))
+    } else {
+        let mut buf4 = [0u8; 4];
+        file.read_exact(&mut buf4).map_err(|e| format!("PSD読み込みエラー: {}", e))?;
+        Ok(u32::from_be_bytes(buf4) as u64)
+    }
+}
 
-    // Height (4 bytes)
-    file.read_exact(&mut buf4).map_err(|e| format!("PSD読み込みエラー: {}", e))?;
-    let height = u32::from_be_bytes(buf4);
+/// ヘッダーの直後から Color Mode Data を読み飛ばし、Image Resources から指定IDのリソースを探す
+/// Image Resources と Color Mode Data の長さフィールドはPSBでも4バイト
+fn find_image_resource<R: Read + Seek>(file: &mut R, ids: &[u16]) -> Result<Option<(u16, Vec<u8>)>, String> {
+    let mut buf4 = [0u8; 4];
 
-    // Width (4 bytes)
-    file.read_exact(&mut buf4).map_err(|e| format!("PSD読み込みエラー: {}", e))?;
-    let width = u32::from_be_bytes(buf4);
+    // Color Mode Data Section — skip
+    file.read_exact(&mut buf4).map_err(|e| format!("読み込みエラー: {}", e))?;
+    let color_mode_len = u32::from_be_bytes(buf4);
+    file.seek(SeekFrom::Current(color_mode_len as i64)).map_err(|e| format!("シークエラー: {}", e))?;
 
-    // Depth (2 bytes)
-    file.read_exact(&mut buf2).map_err(|e| format!("PSD読み込みエラー: {}", e))?;
-    let depth = u16::from_be_bytes(buf2);
-    if depth != 8 {
-        return Err(format!("サポートされていないビット深度: {}", depth));
+    read_image_resources(file, ids)
+}
+
+/// Image Resources セクションから指定IDのリソースを探す
+/// 見つかったかどうかに関わらず、セクションの末尾まで読み進めて返す
+fn read_image_resources<R: Read + Seek>(file: &mut R, ids: &[u16]) -> Result<Option<(u16, Vec<u8>)>, String> {
+    let mut buf4 = [0u8; 4];
+    let mut buf2 = [0u8; 2];
+    let mut found = None;
+
+    file.read_exact(&mut buf4).map_err(|e| format!("読み込みエラー: {}", e))?;
+    let resources_len = u32::from_be_bytes(buf4);
+    let resources_end = file.stream_position().map_err(|e| format!("位置取得エラー: {}", e))? + resources_len as u64;
+
+    while file.stream_position().map_err(|e| format!("位置取得エラー: {}", e))? < resources_end {
+        // Signature "8BIM"
+        file.read_exact(&mut buf4).map_err(|e| format!("読み込みエラー: {}", e))?;
+        if &buf4 != b"8BIM" {
+            break;
+        }
+
+        // Resource ID (2 bytes)
+        file.read_exact(&mut buf2).map_err(|e| format!("読み込みエラー: {}", e))?;
+        let resource_id = u16::from_be_bytes(buf2);
+
+        // Pascal string name (padded to even)
+        let mut name_len_buf = [0u8; 1];
+        file.read_exact(&mut name_len_buf).map_err(|e| format!("読み込みエラー: {}", e))?;
+        let name_len = name_len_buf[0] as u64;
+        let padded_name_len = if (name_len + 1) % 2 == 0 { name_len } else { name_len + 1 };
+        file.seek(SeekFrom::Current(padded_name_len as i64)).map_err(|e| format!("シークエラー: {}", e))?;
+
+        // Data size (4 bytes)
+        file.read_exact(&mut buf4).map_err(|e| format!("読み込みエラー: {}", e))?;
+        let data_size = u32::from_be_bytes(buf4);
+
+        if found.is_none() && ids.contains(&resource_id) {
+            let mut data = vec![0u8; data_size as usize];
+            file.read_exact(&mut data).map_err(|e| format!("読み込みエラー: {}", e))?;
+            found = Some((resource_id, data));
+            if data_size % 2 == 1 {
+                file.seek(SeekFrom::Current(1)).map_err(|e| format!("シークエラー: {}", e))?;
+            }
+            continue;
+        }
+
+        // Skip this resource (padded to even)
+        let padded_size = if data_size % 2 == 0 { data_size } else { data_size + 1 };
+        file.seek(SeekFrom::Current(padded_size as i64)).map_err(|e| format!("シークエラー: {}", e))?;
     }
 
-    // Color Mode (2 bytes)
-    file.read_exact(&mut buf2).map_err(|e| format!("PSD読み込みエラー: {}", e))?;
-    let color_mode = u16::from_be_bytes(buf2);
-    if color_mode != 3 && color_mode != 1 {
-        return Err(format!("サポートされていないカラーモード: {} (RGB/Grayscaleのみ対応)", color_mode));
+    file.seek(SeekFrom::Start(resources_end)).map_err(|e| format!("シークエラー: {}", e))?;
+    Ok(found)
+}
+
+/// PSDファイルのImage Dataセクションを直接読み込む（高速版）
+/// Photoshopの「互換性を最大に」で保存されたPSDには、
+/// 合成済みのフラット化画像が含まれている。
+/// 埋め込みICCプロファイル（Resource ID 1039）も合わせて返す
+fn load_psd_composite(path: &Path) -> Result<(DynamicImage, Option<Vec<u8>>), String> {
+    let file = File::open(path).map_err(|e| format!("ファイルを開けません: {}", e))?;
+    let mut file = BufReader::with_capacity(64 * 1024, file);
+    let mut buf4 = [0u8; 4];
+    let mut buf2 = [0u8; 2];
+
+    // === Header (26 bytes) ===
+    let PsdHeader { version, channels, height, width, depth, color_mode } = read_psd_header(&mut file)?;
+
+    // カラーモードとビット深度の組み合わせを検証
+    match (color_mode, depth) {
+        (COLOR_MODE_BITMAP, 1)
+        | (COLOR_MODE_GRAYSCALE | COLOR_MODE_RGB, 8 | 16 | 32)
+        | (COLOR_MODE_CMYK | COLOR_MODE_DUOTONE, 8 | 16)
+        | (COLOR_MODE_INDEXED, 8) => {}
+        (COLOR_MODE_BITMAP | COLOR_MODE_GRAYSCALE | COLOR_MODE_INDEXED | COLOR_MODE_RGB | COLOR_MODE_CMYK | COLOR_MODE_DUOTONE, _) => {
+            return Err(format!("サポートされていないビット深度: {} (カラーモード {})", depth, color_mode));
+        }
+        _ => {
+            return Err(format!("サポートされていないカラーモード: {}", color_mode));
+        }
     }
 
     // === Color Mode Data Section ===
+    // インデックスカラーのみカラーテーブル（256色×RGB）を保持する
     file.read_exact(&mut buf4).map_err(|e| format!("PSD読み込みエラー: {}", e))?;
     let color_mode_len = u32::from_be_bytes(buf4);
-    file.seek(SeekFrom::Current(color_mode_len as i64)).map_err(|e| format!("シークエラー: {}", e))?;
+    let palette = if color_mode == COLOR_MODE_INDEXED {
+        let mut data = vec![0u8; color_mode_len as usize];
+        file.read_exact(&mut data).map_err(|e| format!("カラーテーブル読み込みエラー: {}", e))?;
+        data
+    } else {
+        file.seek(SeekFrom::Current(color_mode_len as i64)).map_err(|e| format!("シークエラー: {}", e))?;
+        Vec::new()
+    };
 
     // === Image Resources Section ===
-    file.read_exact(&mut buf4).map_err(|e| format!("PSD読み込みエラー: {}", e))?;
-    let resources_len = u32::from_be_bytes(buf4);
-    file.seek(SeekFrom::Current(resources_len as i64)).map_err(|e| format!("シークエラー: {}", e))?;
+    // Resource ID 1039 (0x040F) = ICC Profile
+    let icc_profile = read_image_resources(&mut file, &[1039])?.map(|(_, data)| data);
 
     // === Layer and Mask Information Section ===
-    if version == 2 {
-        let mut buf8 = [0u8; 8];
-        file.read_exact(&mut buf8).map_err(|e| format!("PSD読み込みエラー: {}", e))?;
-        let layer_len = u64::from_be_bytes(buf8);
-        file.seek(SeekFrom::Current(layer_len as i64)).map_err(|e| format!("シークエラー: {}", e))?;
-    } else {
-        file.read_exact(&mut buf4).map_err(|e| format!("PSD読み込みエラー: {}", e))?;
-        let layer_len = u32::from_be_bytes(buf4);
-        file.seek(SeekFrom::Current(layer_len as i64)).map_err(|e| format!("シークエラー: {}", e))?;
-    }
+    let layer_len = read_section_length(&mut file, version)?;
+    file.seek(SeekFrom::Current(layer_len as i64)).map_err(|e| format!("シークエラー: {}", e))?;
 
     // === Image Data Section ===
     file.read_exact(&mut buf2).map_err(|e| format!("PSD読み込みエラー: {}", e))?;
     let compression = u16::from_be_bytes(buf2);
 
-    let pixels = (width as usize) * (height as usize);
-    let num_channels = channels.min(4);
+    // カラー + アルファ1枚まで読み込み、残りのスポットチャンネルは無視
+    let num_channels = channels.min(color_channel_count(color_mode) + 1);
+    // 1行あたりのバイト数（1bitは8ピクセル単位で詰められ、16bit/32bitはビッグエンディアン）
+    let row_bytes = psd_row_bytes(width, depth);
 
-    match compression {
+    let channel_data = match compression {
         0 => {
             // Raw (非圧縮)
-            let mut channel_data = vec![vec![0u8; pixels]; num_channels];
+            let mut channel_data = vec![vec![0u8; row_bytes * height as usize]; num_channels];
             for ch in 0..num_channels {
                 file.read_exact(&mut channel_data[ch]).map_err(|e| format!("画像データ読み込みエラー: {}", e))?;
             }
-            channels_to_rgba(channel_data, width, height, color_mode)
+            channel_data
         }
         1 => {
             // RLE圧縮
-            decode_rle_image(&mut file, width, height, num_channels, color_mode, version)
+            decode_rle_channels(&mut file, row_bytes, height, channels, num_channels, version)?
+        }
+        2 | 3 => {
+            // ZIP圧縮（3は差分予測付き）
+            decode_zip_channels(&mut file, width, height, depth, num_channels, compression == 3)?
         }
         _ => {
-            Err(format!("サポートされていない圧縮方式: {}", compression))
+            return Err(format!("サポートされていない圧縮方式: {}", compression));
         }
+    };
+
+    // CMYKはICCプロファイルがあればsRGBへ変換されるので、返すプロファイルもsRGBにする
+    let cmyk_icc = icc_profile.as_deref().filter(|icc| color_mode == COLOR_MODE_CMYK && is_cmyk_profile(icc));
+    let img = channels_to_image(channel_data, width, height, color_mode, depth, &palette, cmyk_icc)?;
+    let icc_profile = if color_mode == COLOR_MODE_CMYK {
+        cmyk_icc.and_then(|_| target_icc_profile(COLOR_PROFILE_SRGB)).map(|icc| icc.to_vec())
+    } else {
+        icc_profile
+    };
+
+    Ok((img, icc_profile))
+}
+
+/// カラーモードごとの色チャンネル数（アルファを除く）
+fn color_channel_count(color_mode: u16) -> usize {
+    match color_mode {
+        COLOR_MODE_RGB => 3,
+        COLOR_MODE_CMYK => 4,
+        _ => 1,
     }
 }
 
-/// RLE圧縮された画像データをデコード
-fn decode_rle_image<R: Read>(
+/// 1チャンネル1行あたりのバイト数
+pub fn psd_row_bytes(width: u32, depth: u16) -> usize {
+    (width as usize * depth as usize).div_ceil(8)
+}
+
+/// RLE圧縮された画像データをチャンネルごとにデコード
+/// 行長テーブルは全チャンネル分が先頭に並ぶため、読み込まないチャンネルの分も読み飛ばす
+fn decode_rle_channels<R: Read>(
     file: &mut R,
-    width: u32,
+    row_bytes: usize,
     height: u32,
+    total_channels: usize,
     num_channels: usize,
-    color_mode: u16,
     version: u16,
-) -> Result<DynamicImage, String> {
+) -> Result<Vec<Vec<u8>>, String> {
     let rows = height as usize;
-    let pixels = (width as usize) * rows;
 
     // 各チャンネルの各行のバイト数を読み取る
-    let total_rows = rows * num_channels;
-    let mut row_lengths = vec![0u16; total_rows];
+    let total_rows = rows * total_channels;
+    // PSBは各行4バイト（65535バイトを超える行がある）、PSDは2バイト
+    let mut row_lengths = vec![0u32; total_rows];
 
     if version == 2 {
         let mut buf4 = [0u8; 4];
         for i in 0..total_rows {
             file.read_exact(&mut buf4).map_err(|e| format!("行長読み込みエラー: {}", e))?;
-            row_lengths[i] = u32::from_be_bytes(buf4) as u16;
+            row_lengths[i] = u32::from_be_bytes(buf4);
         }
     } else {
         let mut buf2 = [0u8; 2];
         for i in 0..total_rows {
             file.read_exact(&mut buf2).map_err(|e| format!("行長読み込みエラー: {}", e))?;
-            row_lengths[i] = u16::from_be_bytes(buf2);
+            row_lengths[i] = u16::from_be_bytes(buf2) as u32;
         }
     }
 
     // 各チャンネルをデコード
-    let mut channel_data = vec![vec![0u8; pixels]; num_channels];
+    let mut channel_data = vec![vec![0u8; row_bytes * rows]; num_channels];
 
     for ch in 0..num_channels {
         for row in 0..rows {
@@ -184,17 +346,79 @@ fn decode_rle_image<R: Read>(
             let mut compressed = vec![0u8; row_len];
             file.read_exact(&mut compressed).map_err(|e| format!("RLEデータ読み込みエラー: {}", e))?;
 
-            let row_start = row * width as usize;
-            let row_data = &mut channel_data[ch][row_start..row_start + width as usize];
+            let row_start = row * row_bytes;
+            let row_data = &mut channel_data[ch][row_start..row_start + row_bytes];
             decode_packbits(&compressed, row_data);
         }
     }
 
-    channels_to_rgba(channel_data, width, height, color_mode)
+    Ok(channel_data)
+}
+
+/// ZIP圧縮された画像データをチャンネルごとにデコード
+/// 全チャンネルが1本のzlibストリームに連続して格納されている
+fn decode_zip_channels<R: Read>(
+    file: &mut R,
+    width: u32,
+    height: u32,
+    depth: u16,
+    num_channels: usize,
+    predicted: bool,
+) -> Result<Vec<Vec<u8>>, String> {
+    if predicted && depth == 1 {
+        return Err("1bit画像の差分予測付きZIPには対応していません".to_string());
+    }
+
+    let row_bytes = psd_row_bytes(width, depth);
+    let mut decoder = ZlibDecoder::new(file);
+    let mut channel_data = vec![vec![0u8; row_bytes * height as usize]; num_channels];
+
+    for channel in channel_data.iter_mut() {
+        decoder.read_exact(channel).map_err(|e| format!("ZIPデータ展開エラー: {}", e))?;
+        if predicted {
+            for row in channel.chunks_exact_mut(row_bytes) {
+                undo_zip_prediction(row, width as usize, depth);
+            }
+        }
+    }
+
+    Ok(channel_data)
+}
+
+/// ZIP差分予測を1行分元に戻す
+/// 8bit/16bitはサンプル単位の差分、32bitはバイトプレーンに分割した上でのバイト差分
+pub fn undo_zip_prediction(row: &mut [u8], width: usize, depth: u16) {
+    match depth {
+        16 => {
+            let mut prev = 0u16;
+            for sample in row.chunks_exact_mut(2) {
+                let value = prev.wrapping_add(u16::from_be_bytes([sample[0], sample[1]]));
+                sample.copy_from_slice(&value.to_be_bytes());
+                prev = value;
+            }
+        }
+        32 => {
+            for i in 1..row.len() {
+                row[i] = row[i].wrapping_add(row[i - 1]);
+            }
+            // [全ピクセルの第1バイト][第2バイト]... の並びをピクセル順に戻す
+            let planes = row.to_vec();
+            for x in 0..width {
+                for b in 0..4 {
+                    row[x * 4 + b] = planes[b * width + x];
+                }
+            }
+        }
+        _ => {
+            for i in 1..row.len() {
+                row[i] = row[i].wrapping_add(row[i - 1]);
+            }
+        }
+    }
 }
 
 /// PackBits RLEデコード
-fn decode_packbits(input: &[u8], output: &mut [u8]) {
+pub fn decode_packbits(input: &[u8], output: &mut [u8]) {
     let mut i = 0;
     let mut o = 0;
 
@@ -227,37 +451,195 @@ fn decode_packbits(input: &[u8], output: &mut [u8]) {
     }
 }
 
-/// チャンネルデータをRGBA画像に変換
-fn channels_to_rgba(channel_data: Vec<Vec<u8>>, width: u32, height: u32, color_mode: u16) -> Result<DynamicImage, String> {
+/// チャンネルデータを画像に変換
+/// 16bitはRgba16、32bitはRgba32Fのまま返し、8bitへの量子化は書き出し時に行う
+/// CMYKはcmyk_iccがあればそのプロファイルでsRGBに変換し、なければ単純変換する
+fn channels_to_image(
+    mut channel_data: Vec<Vec<u8>>,
+    width: u32,
+    height: u32,
+    color_mode: u16,
+    depth: u16,
+    palette: &[u8],
+    cmyk_icc: Option<&[u8]>,
+) -> Result<DynamicImage, String> {
     let pixels = (width as usize) * (height as usize);
-    let mut rgba = vec![255u8; pixels * 4];
 
     match color_mode {
-        3 => {
+        COLOR_MODE_BITMAP => {
+            // 1bit（1 = 黒）を8bitグレースケールに展開
+            if let Some(bits) = channel_data.first_mut() {
+                *bits = unpack_bitmap(bits, width, height);
+            }
+            return channels_to_image(channel_data, width, height, COLOR_MODE_GRAYSCALE, 8, palette, None);
+        }
+        COLOR_MODE_INDEXED => {
+            // カラーテーブルでRGBに展開
+            let indices = channel_data.first().ok_or("インデックスカラーのチャンネルがありません")?;
+            let rgb_channels = expand_indexed(indices, palette)?;
+            return channels_to_image(rgb_channels, width, height, COLOR_MODE_RGB, 8, palette, None);
+        }
+        COLOR_MODE_DUOTONE => {
+            // ダブルトーンのインク指定は非公開形式のため、仕様どおりグレースケールとして扱う
+            return channels_to_image(channel_data, width, height, COLOR_MODE_GRAYSCALE, depth, palette, None);
+        }
+        _ => {}
+    }
+
+    match depth {
+        16 => {
+            let mut samples: Vec<Vec<u16>> = channel_data.iter()
+                .map(|c| c.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect())
+                .collect();
+            let color_mode = if color_mode == COLOR_MODE_CMYK {
+                samples = convert_cmyk_channels(samples, u16::MAX as f32, cmyk_icc)?;
+                COLOR_MODE_RGB
+            } else {
+                color_mode
+            };
+            let rgba = interleave_rgba(&samples, pixels, color_mode, 0, u16::MAX);
+            let img: ImageBuffer<Rgba<u16>, Vec<u16>> = ImageBuffer::from_raw(width, height, rgba)
+                .ok_or_else(|| format!("RGBA16画像の作成に失敗しました ({}x{})", width, height))?;
+            Ok(DynamicImage::ImageRgba16(img))
+        }
+        32 => {
+            // 32bitはリニアなので、色チャンネルのみsRGBガンマを適用（アルファはそのまま）
+            let color_channels = color_channel_count(color_mode);
+            let samples: Vec<Vec<f32>> = channel_data.iter()
+                .enumerate()
+                .map(|(ch, c)| {
+                    c.chunks_exact(4)
+                        .map(|b| f32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+                        .map(|v| if ch < color_channels { linear_to_srgb(v) } else { v.clamp(0.0, 1.0) })
+                        .collect()
+                })
+                .collect();
+            let rgba = interleave_rgba(&samples, pixels, color_mode, 0.0, 1.0);
+            let img: Rgba32FImage = ImageBuffer::from_raw(width, height, rgba)
+                .ok_or_else(|| format!("RGBA32F画像の作成に失敗しました ({}x{})", width, height))?;
+            Ok(DynamicImage::ImageRgba32F(img))
+        }
+        _ => {
+            let color_mode = if color_mode == COLOR_MODE_CMYK {
+                channel_data = convert_cmyk_channels(channel_data, 255.0, cmyk_icc)?;
+                COLOR_MODE_RGB
+            } else {
+                color_mode
+            };
+            let rgba = interleave_rgba(&channel_data, pixels, color_mode, 0u8, 255u8);
+            let img: RgbaImage = ImageBuffer::from_raw(width, height, rgba)
+                .ok_or_else(|| format!("RGBA画像の作成に失敗しました ({}x{})", width, height))?;
+            Ok(DynamicImage::ImageRgba8(img))
+        }
+    }
+}
+
+/// プレーナーなチャンネルデータをRGBAのインターリーブ配列に並べ替える
+fn interleave_rgba<T: Copy>(channel_data: &[Vec<T>], pixels: usize, color_mode: u16, zero: T, opaque: T) -> Vec<T> {
+    let mut rgba = vec![opaque; pixels * 4];
+    let sample = |ch: usize, i: usize, default: T| channel_data.get(ch).map(|c| c[i]).unwrap_or(default);
+
+    match color_mode {
+        COLOR_MODE_RGB => {
             // RGB
             for i in 0..pixels {
-                rgba[i * 4] = channel_data.get(0).map(|c| c[i]).unwrap_or(0);
-                rgba[i * 4 + 1] = channel_data.get(1).map(|c| c[i]).unwrap_or(0);
-                rgba[i * 4 + 2] = channel_data.get(2).map(|c| c[i]).unwrap_or(0);
-                rgba[i * 4 + 3] = channel_data.get(3).map(|c| c[i]).unwrap_or(255);
+                rgba[i * 4] = sample(0, i, zero);
+                rgba[i * 4 + 1] = sample(1, i, zero);
+                rgba[i * 4 + 2] = sample(2, i, zero);
+                rgba[i * 4 + 3] = sample(3, i, opaque);
             }
         }
-        1 => {
+        COLOR_MODE_GRAYSCALE => {
             // Grayscale
             for i in 0..pixels {
-                let gray = channel_data.get(0).map(|c| c[i]).unwrap_or(0);
+                let gray = sample(0, i, zero);
                 rgba[i * 4] = gray;
                 rgba[i * 4 + 1] = gray;
                 rgba[i * 4 + 2] = gray;
-                rgba[i * 4 + 3] = channel_data.get(1).map(|c| c[i]).unwrap_or(255);
+                rgba[i * 4 + 3] = sample(1, i, opaque);
             }
         }
         _ => {}
     }
 
-    let img: RgbaImage = ImageBuffer::from_raw(width, height, rgba)
-        .ok_or_else(|| format!("RGBA画像の作成に失敗しました ({}x{})", width, height))?;
-    Ok(DynamicImage::ImageRgba8(img))
+    rgba
+}
+
+/// Bitmapモードの1bitデータ（1 = 黒、行はバイト境界で揃う）を8bitグレースケールに展開
+fn unpack_bitmap(bits: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let width = width as usize;
+    let row_bytes = psd_row_bytes(width as u32, 1);
+    let mut gray = vec![255u8; width * height as usize];
+
+    for (y, row) in gray.chunks_exact_mut(width).enumerate() {
+        let src = &bits[y * row_bytes..(y + 1) * row_bytes];
+        for (x, px) in row.iter_mut().enumerate() {
+            if src[x / 8] & (0x80 >> (x % 8)) != 0 {
+                *px = 0;
+            }
+        }
+    }
+
+    gray
+}
+
+/// インデックスカラーをカラーテーブル（R256 + G256 + B256）でRGBチャンネルに展開
+fn expand_indexed(indices: &[u8], palette: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    if palette.len() < 768 {
+        return Err(format!("カラーテーブルが不正です ({} bytes)", palette.len()));
+    }
+
+    Ok((0..3)
+        .map(|ch| indices.iter().map(|&i| palette[ch * 256 + i as usize]).collect())
+        .collect())
+}
+
+/// CMYKチャンネルをRGBチャンネルに変換（ICCプロファイルでの変換に失敗したら単純変換）
+fn convert_cmyk_channels<T: Primitive>(
+    channel_data: Vec<Vec<T>>,
+    max: f32,
+    cmyk_icc: Option<&[u8]>,
+) -> Result<Vec<Vec<T>>, String> {
+    match cmyk_icc.map(|icc| cmyk_planes_to_srgb(&channel_data, icc)) {
+        Some(Ok(rgb)) => Ok(rgb),
+        _ => cmyk_to_rgb_channels(channel_data, max),
+    }
+}
+
+/// CMYKチャンネルをRGBチャンネルに変換（アルファがあれば末尾に残す）
+/// PSDのCMYKは反転値で格納されている（最大値 = インクなし）ため、
+/// R = C' × K' のように乗算するだけで単純なプロセス変換になる
+fn cmyk_to_rgb_channels<T: Primitive>(channel_data: Vec<Vec<T>>, max: f32) -> Result<Vec<Vec<T>>, String> {
+    let mut channels = channel_data.into_iter();
+    let (c, m, y, k) = match (channels.next(), channels.next(), channels.next(), channels.next()) {
+        (Some(c), Some(m), Some(y), Some(k)) => (c, m, y, k),
+        _ => return Err("CMYKのチャンネルが不足".to_string()),
+    };
+
+    let convert = |ink: &[T]| -> Vec<T> {
+        ink.iter()
+            .zip(k.iter())
+            .map(|(&v, &kv)| {
+                let v = v.to_f32().unwrap_or(max);
+                let kv = kv.to_f32().unwrap_or(max);
+                T::from(v * kv / max + 0.5).unwrap_or(T::DEFAULT_MAX_VALUE)
+            })
+            .collect()
+    };
+
+    let mut rgb = vec![convert(&c), convert(&m), convert(&y)];
+    rgb.extend(channels.next());
+    Ok(rgb)
+}
+
+/// リニア値をsRGBガンマに変換（32bit PSD用）
+pub fn linear_to_srgb(v: f32) -> f32 {
+    let v = v.clamp(0.0, 1.0);
+    if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
 }
 
 /// PSDファイルをpsd crateで読み込む（レイヤー合成版、フォールバック用）
@@ -277,171 +659,381 @@ fn load_psd_with_layers(path: &Path) -> Result<DynamicImage, String> {
 
 /// PSDファイルからサムネイルを抽出（高速プレビュー用）
 pub fn extract_psd_thumbnail(path: &Path) -> Option<(DynamicImage, u32, u32)> {
-    let mut file = File::open(path).ok()?;
-    let mut buf4 = [0u8; 4];
-    let mut buf2 = [0u8; 2];
+    let mut file = BufReader::new(File::open(path).ok()?);
+    let header = read_psd_header(&mut file).ok()?;
+
+    // Search for thumbnail resource (ID 1036 or 1033)
+    let (_, data) = find_image_resource(&mut file, &[1036, 1033]).ok()??;
 
-    // PSD signature check
-    file.read_exact(&mut buf4).ok()?;
-    if &buf4 != b"8BPS" {
+    // Header: format(4) + width(4) + height(4) + widthbytes(4) + totalsize(4) + compressedsize(4) + bpp(2) + planes(2)
+    if data.len() <= 28 || u32::from_be_bytes([data[0], data[1], data[2], data[3]]) != 1 {
         return None;
     }
 
-    // Skip to dimensions
-    file.seek(SeekFrom::Current(10)).ok()?;
+    let img = ::image::load_from_memory_with_format(&data[28..], ImageFormat::Jpeg).ok()?;
+    Some((img, header.width, header.height))
+}
 
-    file.read_exact(&mut buf4).ok()?;
-    let height = u32::from_be_bytes(buf4);
+/// PSDファイルから埋め込みICCプロファイルを抽出（Resource ID 1039）
+pub fn extract_psd_icc_profile(path: &Path) -> Option<Vec<u8>> {
+    let mut file = BufReader::new(File::open(path).ok()?);
+    read_psd_header(&mut file).ok()?;
+    find_image_resource(&mut file, &[1039]).ok()?.map(|(_, data)| data)
+}
 
-    file.read_exact(&mut buf4).ok()?;
-    let width = u32::from_be_bytes(buf4);
+/// PSDファイルから解像度（dpi）を抽出
+/// Image Resources セクションの Resource ID 1005 (ResolutionInfo) の hRes（16.16固定小数、常にpixels/inch）
+pub fn extract_psd_resolution(path: &Path) -> Option<f32> {
+    let mut file = BufReader::new(File::open(path).ok()?);
+    read_psd_header(&mut file).ok()?;
+    let (_, data) = find_image_resource(&mut file, &[1005]).ok()??;
+    if data.len() < 4 {
+        return None;
+    }
+    let h_res = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+    Some(h_res as f32 / 65536.0)
+}
 
-    file.seek(SeekFrom::Current(4)).ok()?;
+/// PSDファイルからガイド情報を抽出
+/// Image Resources セクションの Resource ID 1032 (0x0408) をパース
+pub fn extract_psd_guides(path: &Path) -> Result<Vec<PsdGuide>, String> {
+    let file = File::open(path).map_err(|e| format!("ファイルを開けません: {}", e))?;
+    let mut file = BufReader::new(file);
+    read_psd_header(&mut file)?;
 
-    // Color Mode Data Section
-    file.read_exact(&mut buf4).ok()?;
-    let color_mode_len = u32::from_be_bytes(buf4);
-    file.seek(SeekFrom::Current(color_mode_len as i64)).ok()?;
+    // Resource ID 1032 (0x0408) = Grid and guides information を探す
+    let data = match find_image_resource(&mut file, &[1032])? {
+        Some((_, data)) => data,
+        // ガイドリソースが見つからなかった場合は空配列
+        None => return Ok(vec![]),
+    };
+
+    // Header: version(4) + gridH(4) + gridV(4) + guideCount(4) = 16 bytes
+    if data.len() < 16 {
+        return Ok(vec![]);
+    }
+    let guide_count = u32::from_be_bytes([data[12], data[13], data[14], data[15]]) as usize;
+
+    // 各ガイド: Position 4 bytes (fixed-point, 実値 = raw / 32.0 でピクセル) + Direction 1 byte (0 = vertical, 1 = horizontal)
+    let guides = data[16..]
+        .chunks_exact(5)
+        .take(guide_count)
+        .map(|g| {
+            let raw_pos = u32::from_be_bytes([g[0], g[1], g[2], g[3]]);
+            PsdGuide {
+                guide_type: if g[4] == 0 { "v" } else { "h" }.to_string(),
+                position: raw_pos as f64 / 32.0,
+            }
+        })
+        .collect();
 
-    // Image Resources Section
-    file.read_exact(&mut buf4).ok()?;
-    let resources_len = u32::from_be_bytes(buf4);
-    let resources_end = file.stream_position().ok()? + resources_len as u64;
+    Ok(guides)
+}
 
-    // Search for thumbnail resource (ID 1036 or 1033)
-    while file.stream_position().ok()? < resources_end {
-        file.read_exact(&mut buf4).ok()?;
-        if &buf4 != b"8BIM" {
-            break;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::image::GenericImageView;
+    use std::path::PathBuf;
+
+    /// tests/fixtures のファイルパス
+    /// rgb_rle.psd / rgb_rle.psb は同じ内容の4x2 RGB（RLE圧縮）で、
+    /// 垂直ガイド(x=1.0)・水平ガイド(y=1.5)と2x2のJPEGサムネイルを持つ
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+    }
 
-        file.read_exact(&mut buf2).ok()?;
-        let resource_id = u16::from_be_bytes(buf2);
+    fn assert_rgb_rle_fixture(path: &Path) {
+        let (img, _) = load_psd_composite(path).unwrap();
+        assert_eq!(img.dimensions(), (4, 2));
 
-        let mut name_len_buf = [0u8; 1];
-        file.read_exact(&mut name_len_buf).ok()?;
-        let name_len = name_len_buf[0] as u64;
-        let padded_name_len = if (name_len + 1) % 2 == 0 { name_len } else { name_len + 1 };
-        file.seek(SeekFrom::Current(padded_name_len as i64)).ok()?;
+        let rgba = img.to_rgba8();
+        assert_eq!(rgba.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(rgba.get_pixel(1, 0).0, [0, 255, 0, 255]);
+        assert_eq!(rgba.get_pixel(2, 0).0, [0, 0, 255, 255]);
+        assert_eq!(rgba.get_pixel(3, 0).0, [255, 255, 255, 255]);
+        assert_eq!(rgba.get_pixel(3, 1).0, [0, 0, 0, 255]);
+    }
 
-        file.read_exact(&mut buf4).ok()?;
-        let data_size = u32::from_be_bytes(buf4);
+    #[test]
+    fn test_load_psd_composite() {
+        assert_rgb_rle_fixture(&fixture("rgb_rle.psd"));
+    }
 
-        if resource_id == 1036 || resource_id == 1033 {
-            file.read_exact(&mut buf4).ok()?;
-            let format = u32::from_be_bytes(buf4);
+    #[test]
+    fn test_load_psb_composite() {
+        assert_rgb_rle_fixture(&fixture("rgb_rle.psb"));
+    }
 
-            if format == 1 {
-                file.seek(SeekFrom::Current(24)).ok()?;
+    #[test]
+    fn test_extract_psb_guides() {
+        let guides = extract_psd_guides(&fixture("rgb_rle.psb")).unwrap();
+        assert_eq!(guides.len(), 2);
+        assert_eq!(guides[0].guide_type, "v");
+        assert_eq!(guides[0].position, 1.0);
+        assert_eq!(guides[1].guide_type, "h");
+        assert_eq!(guides[1].position, 1.5);
+    }
 
-                let jpeg_size = data_size - 28;
-                let mut jpeg_data = vec![0u8; jpeg_size as usize];
-                file.read_exact(&mut jpeg_data).ok()?;
+    #[test]
+    fn test_extract_psb_thumbnail() {
+        let (thumb, width, height) = extract_psd_thumbnail(&fixture("rgb_rle.psb")).unwrap();
+        assert_eq!(thumb.dimensions(), (2, 2));
+        assert_eq!((width, height), (4, 2));
+    }
 
-                if let Ok(img) = ::image::load_from_memory_with_format(&jpeg_data, ImageFormat::Jpeg) {
-                    return Some((img, width, height));
-                }
-            }
-            return None;
+    #[test]
+    fn test_psb_rle_row_longer_than_u16() {
+        // 非圧縮リテラルだけで1行が65535バイトを超えるグレースケールPSB
+        let width = 70_000u32;
+        let row: Vec<u8> = (0..width).map(|x| (x % 251) as u8).collect();
+        let mut packed = Vec::new();
+        for chunk in row.chunks(128) {
+            packed.push((chunk.len() - 1) as u8);
+            packed.extend_from_slice(chunk);
         }
-
-        let padded_size = if data_size % 2 == 0 { data_size } else { data_size + 1 };
-        file.seek(SeekFrom::Current(padded_size as i64)).ok()?;
+        assert!(packed.len() > u16::MAX as usize);
+
+        let mut data = Vec::new();
+        data.extend_from_slice(b"8BPS");
+        data.extend_from_slice(&2u16.to_be_bytes());
+        data.extend_from_slice(&[0u8; 6]);
+        data.extend_from_slice(&1u16.to_be_bytes());
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&8u16.to_be_bytes());
+        data.extend_from_slice(&COLOR_MODE_GRAYSCALE.to_be_bytes());
+        data.extend_from_slice(&0u32.to_be_bytes()); // Color Mode Data
+        data.extend_from_slice(&0u32.to_be_bytes()); // Image Resources
+        data.extend_from_slice(&0u64.to_be_bytes()); // Layer and Mask (PSBは8バイト)
+        data.extend_from_slice(&1u16.to_be_bytes()); // RLE
+        data.extend_from_slice(&(packed.len() as u32).to_be_bytes());
+        data.extend_from_slice(&packed);
+
+        let path = std::env::temp_dir().join("tachimi_test_wide_row.psb");
+        std::fs::write(&path, &data).unwrap();
+        let img = load_psd_composite(&path);
+        let _ = std::fs::remove_file(&path);
+
+        let rgba = img.unwrap().0.to_rgba8();
+        assert_eq!(rgba.dimensions(), (width, 1));
+        assert_eq!(rgba.get_pixel(69_999, 0).0[0], row[69_999]);
     }
 
-    None
-}
+    /// 合成画像用のPSDヘッダー（version 1）
+    fn psd_header(color_mode: u16, depth: u16, channels: usize, width: u32, height: u32) -> PsdHeader {
+        PsdHeader { version: 1, channels, height, width, depth, color_mode }
+    }
 
-/// PSDファイルからガイド情報を抽出
-/// Image Resources セクションの Resource ID 1032 (0x0408) をパース
-pub fn extract_psd_guides(path: &Path) -> Result<Vec<PsdGuide>, String> {
-    let mut file = File::open(path).map_err(|e| format!("ファイルを開けません: {}", e))?;
-    let mut buf4 = [0u8; 4];
-    let mut buf2 = [0u8; 2];
+    /// 合成画像だけを持つ最小のPSDを一時ファイルに書き出して読み込む
+    /// image_data は Image Data セクションの圧縮方式（2バイト）より後ろ
+    fn load_synthetic_psd(
+        name: &str,
+        header: PsdHeader,
+        color_mode_data: &[u8],
+        compression: u16,
+        image_data: &[u8],
+    ) -> Result<DynamicImage, String> {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"8BPS");
+        data.extend_from_slice(&header.version.to_be_bytes());
+        data.extend_from_slice(&[0u8; 6]);
+        data.extend_from_slice(&(header.channels as u16).to_be_bytes());
+        data.extend_from_slice(&header.height.to_be_bytes());
+        data.extend_from_slice(&header.width.to_be_bytes());
+        data.extend_from_slice(&header.depth.to_be_bytes());
+        data.extend_from_slice(&header.color_mode.to_be_bytes());
+        data.extend_from_slice(&(color_mode_data.len() as u32).to_be_bytes());
+        data.extend_from_slice(color_mode_data);
+        data.extend_from_slice(&0u32.to_be_bytes()); // Image Resources
+        data.extend_from_slice(&0u32.to_be_bytes()); // Layer and Mask
+        data.extend_from_slice(&compression.to_be_bytes());
+        data.extend_from_slice(image_data);
+
+        let path = std::env::temp_dir().join(format!("tachimi_test_{}.psd", name));
+        std::fs::write(&path, &data).unwrap();
+        let img = load_psd_composite(&path);
+        let _ = std::fs::remove_file(&path);
+        img.map(|(img, _)| img)
+    }
 
-    // PSD signature
-    file.read_exact(&mut buf4).map_err(|e| format!("読み込みエラー: {}", e))?;
-    if &buf4 != b"8BPS" {
-        return Err("無効なPSDファイル".to_string());
+    /// 行ごとにリテラルだけでPackBits圧縮したRLEデータ（行長テーブル + 圧縮データ）
+    fn rle_literal_rows(channels: &[Vec<u8>], row_bytes: usize) -> Vec<u8> {
+        let rows: Vec<Vec<u8>> = channels.iter()
+            .flat_map(|channel| channel.chunks(row_bytes))
+            .map(|row| row.chunks(128).flat_map(|chunk| {
+                std::iter::once((chunk.len() - 1) as u8).chain(chunk.iter().copied())
+            }).collect())
+            .collect();
+        let mut data: Vec<u8> = rows.iter().flat_map(|row| (row.len() as u16).to_be_bytes()).collect();
+        data.extend(rows.concat());
+        data
     }
 
-    // Version (2) + Reserved (6) + Channels (2) + Height (4) + Width (4) + Depth (2) + ColorMode (2)
-    file.seek(SeekFrom::Current(22)).map_err(|e| format!("シークエラー: {}", e))?;
+    #[test]
+    fn test_decode_16bit() {
+        // 16bitグレースケール（非圧縮）は下位バイトも保持したまま Rgba16 になる
+        let gray: Vec<u8> = [0x1234u16, 0xFFFF].iter().flat_map(|v| v.to_be_bytes()).collect();
+        let header = psd_header(COLOR_MODE_GRAYSCALE, 16, 1, 2, 1);
+        let DynamicImage::ImageRgba16(img) = load_synthetic_psd("gray16", header, &[], 0, &gray).unwrap() else {
+            panic!("16bitのまま読み込まれていない");
+        };
+        assert_eq!(img.get_pixel(0, 0).0, [0x1234, 0x1234, 0x1234, 0xFFFF]);
+        assert_eq!(img.get_pixel(1, 0).0, [0xFFFF, 0xFFFF, 0xFFFF, 0xFFFF]);
+
+        // 16bit RGB + アルファ（RLE）
+        let channels: Vec<Vec<u8>> = [[0x0102u16, 0xFFFF], [0x0304, 0], [0x0506, 0x8000], [0xFFFF, 0x7FFF]]
+            .iter()
+            .map(|c| c.iter().flat_map(|v| v.to_be_bytes()).collect())
+            .collect();
+        let header = psd_header(COLOR_MODE_RGB, 16, 4, 2, 1);
+        let data = rle_literal_rows(&channels, psd_row_bytes(2, 16));
+        let DynamicImage::ImageRgba16(img) = load_synthetic_psd("rgb16_rle", header, &[], 1, &data).unwrap() else {
+            panic!("16bitのまま読み込まれていない");
+        };
+        assert_eq!(img.get_pixel(0, 0).0, [0x0102, 0x0304, 0x0506, 0xFFFF]);
+        assert_eq!(img.get_pixel(1, 0).0, [0xFFFF, 0, 0x8000, 0x7FFF]);
+    }
 
-    // Color Mode Data Section — skip
-    file.read_exact(&mut buf4).map_err(|e| format!("読み込みエラー: {}", e))?;
-    let color_mode_len = u32::from_be_bytes(buf4);
-    file.seek(SeekFrom::Current(color_mode_len as i64)).map_err(|e| format!("シークエラー: {}", e))?;
+    #[test]
+    fn test_decode_32bit() {
+        // 32bitはリニアな浮動小数点: 色チャンネルだけsRGBガンマを掛け、アルファは0〜1に収める
+        let channels: Vec<u8> = [1.0f32, 0.5, 0.0, 2.0].iter().flat_map(|v| v.to_be_bytes()).collect();
+        let header = psd_header(COLOR_MODE_RGB, 32, 4, 1, 1);
+        let DynamicImage::ImageRgba32F(img) = load_synthetic_psd("rgb32", header, &[], 0, &channels).unwrap() else {
+            panic!("32bitのまま読み込まれていない");
+        };
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-5;
+        let [r, g, b, a] = img.get_pixel(0, 0).0;
+        assert!(close(r, 1.0) && close(g, linear_to_srgb(0.5)) && close(b, 0.0) && close(a, 1.0));
+
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert!(close(linear_to_srgb(0.002), 0.002 * 12.92));
+        assert!((linear_to_srgb(0.5) - 0.735_357).abs() < 1e-4);
+        assert_eq!(linear_to_srgb(1.5), linear_to_srgb(1.0));
+    }
 
-    // Image Resources Section
-    file.read_exact(&mut buf4).map_err(|e| format!("読み込みエラー: {}", e))?;
-    let resources_len = u32::from_be_bytes(buf4);
-    let resources_end = file.stream_position().map_err(|e| format!("位置取得エラー: {}", e))? + resources_len as u64;
+    #[test]
+    fn test_decode_bitmap() {
+        // 1bit（1 = 黒）は行ごとにバイト境界まで詰められている
+        let header = psd_header(COLOR_MODE_BITMAP, 1, 1, 3, 2);
+        let data = [0b1010_0000u8, 0b0110_0000];
+        let img = load_synthetic_psd("bitmap", header, &[], 0, &data).unwrap().to_rgba8();
+        let grays: Vec<u8> = img.pixels().map(|p| p.0[0]).collect();
+        assert_eq!(grays, [0, 255, 0, 255, 0, 0]);
+    }
 
-    // Resource ID 1032 (0x0408) = Grid and guides information を探す
-    while file.stream_position().map_err(|e| format!("位置取得エラー: {}", e))? < resources_end {
-        // Signature "8BIM"
-        file.read_exact(&mut buf4).map_err(|e| format!("読み込みエラー: {}", e))?;
-        if &buf4 != b"8BIM" {
-            break;
-        }
+    #[test]
+    fn test_decode_indexed() {
+        let mut palette = vec![0u8; 768];
+        palette[1] = 200; // R[1]
+        palette[256 + 1] = 100; // G[1]
+        palette[512 + 2] = 50; // B[2]
+        let header = psd_header(COLOR_MODE_INDEXED, 8, 1, 3, 1);
+        let img = load_synthetic_psd("indexed", header, &palette, 0, &[1, 2, 0]).unwrap().to_rgba8();
+        assert_eq!(img.get_pixel(0, 0).0, [200, 100, 0, 255]);
+        assert_eq!(img.get_pixel(1, 0).0, [0, 0, 50, 255]);
+        assert_eq!(img.get_pixel(2, 0).0, [0, 0, 0, 255]);
+    }
 
-        // Resource ID (2 bytes)
-        file.read_exact(&mut buf2).map_err(|e| format!("読み込みエラー: {}", e))?;
-        let resource_id = u16::from_be_bytes(buf2);
+    #[test]
+    fn test_decode_cmyk() {
+        // CMYKは反転して格納されている（255 = 0%）: C100% → シアン、M100%+Y100% → 赤
+        let channels = [[0u8, 255], [255, 0], [255, 0], [255, 255]].concat();
+        let header = psd_header(COLOR_MODE_CMYK, 8, 4, 2, 1);
+        let img = load_synthetic_psd("cmyk", header, &[], 0, &channels).unwrap().to_rgba8();
+        assert_eq!(img.get_pixel(0, 0).0, [0, 255, 255, 255]);
+        assert_eq!(img.get_pixel(1, 0).0, [255, 0, 0, 255]);
+
+        // Kチャンネルが欠けている場合は黒い画像にせずエラーにする
+        let header = psd_header(COLOR_MODE_CMYK, 8, 3, 2, 1);
+        let err = load_synthetic_psd("cmyk_missing_k", header, &[], 0, &channels[..6]).unwrap_err();
+        assert!(err.contains("CMYKのチャンネルが不足"), "{}", err);
+    }
 
-        // Pascal string name (padded to even)
-        let mut name_len_buf = [0u8; 1];
-        file.read_exact(&mut name_len_buf).map_err(|e| format!("読み込みエラー: {}", e))?;
-        let name_len = name_len_buf[0] as u64;
-        let padded_name_len = if (name_len + 1) % 2 == 0 { name_len } else { name_len + 1 };
-        file.seek(SeekFrom::Current(padded_name_len as i64)).map_err(|e| format!("シークエラー: {}", e))?;
+    #[test]
+    fn test_decode_duotone() {
+        // ダブルトーンはグレースケールとして読み込む
+        let header = psd_header(COLOR_MODE_DUOTONE, 8, 1, 2, 1);
+        let img = load_synthetic_psd("duotone", header, &[0u8; 8], 0, &[40, 220]).unwrap().to_rgba8();
+        assert_eq!(img.get_pixel(0, 0).0, [40, 40, 40, 255]);
+        assert_eq!(img.get_pixel(1, 0).0, [220, 220, 220, 255]);
+    }
 
-        // Data size (4 bytes)
-        file.read_exact(&mut buf4).map_err(|e| format!("読み込みエラー: {}", e))?;
-        let data_size = u32::from_be_bytes(buf4);
+    /// チャンネルを順に連結してzlib圧縮する（ZIP圧縮のImage Data）
+    fn zlib(channels: &[Vec<u8>]) -> Vec<u8> {
+        use flate2::{write::ZlibEncoder, Compression};
+        use std::io::Write;
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&channels.concat()).unwrap();
+        encoder.finish().unwrap()
+    }
 
-        if resource_id == 1032 {
-            // Grid and guides resource found
-            // Header: version(4) + gridH(4) + gridV(4) + guideCount(4) = 16 bytes
-            if data_size < 16 {
-                return Ok(vec![]);
+    /// 1行分にZIP差分予測を掛ける（undo_zip_prediction の逆）
+    fn apply_zip_prediction(row: &[u8], width: usize, depth: u16) -> Vec<u8> {
+        let byte_deltas = |bytes: &[u8]| -> Vec<u8> {
+            bytes.iter().enumerate().map(|(i, &v)| if i == 0 { v } else { v.wrapping_sub(bytes[i - 1]) }).collect()
+        };
+        match depth {
+            16 => {
+                let samples: Vec<u16> = row.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect();
+                samples.iter().enumerate()
+                    .flat_map(|(i, &v)| if i == 0 { v } else { v.wrapping_sub(samples[i - 1]) }.to_be_bytes())
+                    .collect()
             }
-
-            // Skip grid header (version + horizontal grid + vertical grid)
-            file.seek(SeekFrom::Current(12)).map_err(|e| format!("シークエラー: {}", e))?;
-
-            // Guide count
-            file.read_exact(&mut buf4).map_err(|e| format!("読み込みエラー: {}", e))?;
-            let guide_count = u32::from_be_bytes(buf4);
-
-            let mut guides = Vec::with_capacity(guide_count as usize);
-
-            for _ in 0..guide_count {
-                // Position: 4 bytes (fixed-point 32bit, 実値 = raw / 32.0 でピクセル)
-                file.read_exact(&mut buf4).map_err(|e| format!("読み込みエラー: {}", e))?;
-                let raw_pos = u32::from_be_bytes(buf4);
-                let position = raw_pos as f64 / 32.0;
-
-                // Direction: 1 byte (0 = vertical, 1 = horizontal)
-                let mut dir_buf = [0u8; 1];
-                file.read_exact(&mut dir_buf).map_err(|e| format!("読み込みエラー: {}", e))?;
-                let guide_type = if dir_buf[0] == 0 { "v" } else { "h" };
-
-                guides.push(PsdGuide {
-                    guide_type: guide_type.to_string(),
-                    position,
-                });
+            32 => {
+                let planes: Vec<u8> = (0..4).flat_map(|b| (0..width).map(move |x| row[x * 4 + b])).collect();
+                byte_deltas(&planes)
             }
-
-            return Ok(guides);
+            _ => byte_deltas(row),
         }
+    }
 
-        // Skip this resource (padded to even)
-        let padded_size = if data_size % 2 == 0 { data_size } else { data_size + 1 };
-        file.seek(SeekFrom::Current(padded_size as i64)).map_err(|e| format!("シークエラー: {}", e))?;
+    #[test]
+    fn test_decode_zip() {
+        // 8bit RGB（予測なし）
+        let channels = vec![vec![10u8, 200, 30], vec![40, 50, 250], vec![0, 255, 128]];
+        let header = psd_header(COLOR_MODE_RGB, 8, 3, 3, 1);
+        let img = load_synthetic_psd("rgb_zip", header, &[], 2, &zlib(&channels)).unwrap().to_rgba8();
+        assert_eq!(img.get_pixel(0, 0).0, [10, 40, 0, 255]);
+        assert_eq!(img.get_pixel(1, 0).0, [200, 50, 255, 255]);
+        assert_eq!(img.get_pixel(2, 0).0, [30, 250, 128, 255]);
     }
 
-    // ガイドリソースが見つからなかった場合は空配列
-    Ok(vec![])
+    #[test]
+    fn test_decode_zip_prediction() {
+        // 16bitはサンプル単位の差分（桁あふれを含む）を2行分
+        let (width, height) = (3usize, 2usize);
+        let values = [0x0010u16, 0xFFF0, 0x0005, 0x8000, 0x7FFF, 0x8001];
+        let gray: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+        let row_bytes = psd_row_bytes(width as u32, 16);
+        let predicted: Vec<u8> = gray.chunks(row_bytes).flat_map(|row| apply_zip_prediction(row, width, 16)).collect();
+        let header = psd_header(COLOR_MODE_GRAYSCALE, 16, 1, width as u32, height as u32);
+        let DynamicImage::ImageRgba16(img) = load_synthetic_psd("gray16_zip", header, &[], 3, &zlib(&[predicted])).unwrap() else {
+            panic!("16bitのまま読み込まれていない");
+        };
+        let decoded: Vec<u16> = img.pixels().map(|p| p.0[0]).collect();
+        assert_eq!(decoded, values);
+
+        // 32bitはバイトプレーンに分けた上でのバイト差分
+        let values = [0.25f32, 1.0, 0.0];
+        let gray: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+        let predicted = apply_zip_prediction(&gray, values.len(), 32);
+        assert_ne!(predicted, gray);
+        let header = psd_header(COLOR_MODE_GRAYSCALE, 32, 1, values.len() as u32, 1);
+        let DynamicImage::ImageRgba32F(img) = load_synthetic_psd("gray32_zip", header, &[], 3, &zlib(&[predicted])).unwrap() else {
+            panic!("32bitのまま読み込まれていない");
+        };
+        let decoded: Vec<f32> = img.pixels().map(|p| p.0[0]).collect();
+        let expected: Vec<f32> = values.iter().map(|&v| linear_to_srgb(v)).collect();
+        assert_eq!(decoded, expected);
+
+        // undo_zip_prediction 単体でも元の行に戻る
+        for (row, depth) in [(gray.clone(), 32), (vec![1u8, 255, 3, 0], 8)] {
+            let width = row.len() * 8 / depth as usize;
+            let mut restored = apply_zip_prediction(&row, width, depth);
+            undo_zip_prediction(&mut restored, width, depth);
+            assert_eq!(restored, row);
+        }
+    }
 }
@@ -1,20 +1,140 @@
 //! タチミ - 画像処理モジュール
 //! クロップ、タチキリ処理、ノンブル追加などの画像処理機能
 
-use ::image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
-use ::image::imageops::FilterType;
-use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
+use ::image::{DynamicImage, GenericImageView, GrayImage, ImageBuffer, Luma, Pixel, Primitive, Rgba};
+use ::image::imageops::{self, FilterType};
+use ab_glyph::{Font, PxScale, ScaleFont};
+use imageproc::definitions::Clamp;
 use imageproc::drawing::{draw_text_mut, draw_filled_rect_mut};
 use imageproc::rect::Rect;
 use std::path::Path;
 
 use super::types::{
-    ProcessOptions, color_to_rgba, color_to_rgb, get_nombre_font_size,
-    TARGET_RESIZE_WIDTH, TARGET_RESIZE_HEIGHT,
+    FrameRect, FrameStyle, NombreOptions, PageReport, PageSideOptions, ProcessOptions, TrimMarkOptions, color_to_rgba, color_to_rgb,
+    get_nombre_font_size, TARGET_RESIZE_WIDTH, TARGET_RESIZE_HEIGHT,
 };
-use super::cache::get_cached_font_data;
-use super::image_loader::load_image;
-use super::jpeg::{write_jpeg_mozjpeg_to_file, JPEG_QUALITY};
+use super::fonts::{load_font, FontRole};
+use super::color::{convert_to_grayscale, convert_to_output_profile, is_cmyk_profile, should_output_grayscale};
+use super::image_loader::{extract_psd_guides, extract_psd_icc_profile, is_psd_file, load_image_with_profile};
+use super::guide_crop::{crop_from_guides, CROP_SOURCE_AUTO, CROP_SOURCE_GLOBAL, CROP_SOURCE_GUIDES};
+use super::crop_detection::detect_crop;
+use super::alignment::align_page;
+use super::nombre::{nombre_label, nombre_placement_edge, NOMBRE_VERTICAL_TOP};
+use super::page_side::{page_side, PageSide};
+use super::psd_layers::render_psd_layers;
+use super::image_writer::write_output_image;
+use super::resolution::read_image_dpi;
+use super::trim_marks::{trim_mark_margin_mm, trim_mark_segments};
+
+/// 作業バッファ（RGBA / グレースケール、8bit / 16bit共通）
+pub type WorkBuffer<P> = ImageBuffer<P, Vec<<P as Pixel>::Subpixel>>;
+
+/// 作業バッファのサブピクセル型
+/// 8bitソースはu8、16bit/32bitソースはu16で処理し、精度を書き出し直前まで保持する
+pub trait WorkSample: Primitive + Into<f32> + Clamp<f32> + 'static {}
+
+impl WorkSample for u8 {}
+impl WorkSample for u16 {}
+
+/// 作業バッファのピクセル型
+/// カラーはRGBA、グレースケール出力はLuma（1チャンネル）で処理する
+pub trait WorkPixel: Pixel + 'static {
+    fn buffer_from(img: &DynamicImage) -> WorkBuffer<Self>;
+    fn into_dynamic(buf: WorkBuffer<Self>) -> DynamicImage;
+    /// 8bitのRGBA色をこのピクセル型に変換
+    fn from_rgba8(color: Rgba<u8>) -> Self;
+}
+
+impl WorkPixel for Rgba<u8> {
+    fn buffer_from(img: &DynamicImage) -> WorkBuffer<Self> {
+        img.to_rgba8()
+    }
+    fn into_dynamic(buf: WorkBuffer<Self>) -> DynamicImage {
+        DynamicImage::ImageRgba8(buf)
+    }
+    fn from_rgba8(color: Rgba<u8>) -> Self {
+        color
+    }
+}
+
+impl WorkPixel for Rgba<u16> {
+    fn buffer_from(img: &DynamicImage) -> WorkBuffer<Self> {
+        img.to_rgba16()
+    }
+    fn into_dynamic(buf: WorkBuffer<Self>) -> DynamicImage {
+        DynamicImage::ImageRgba16(buf)
+    }
+    fn from_rgba8(color: Rgba<u8>) -> Self {
+        Rgba(scale_channels::<u16, 4>(color.0))
+    }
+}
+
+impl WorkPixel for Luma<u8> {
+    fn buffer_from(img: &DynamicImage) -> WorkBuffer<Self> {
+        img.to_luma8()
+    }
+    fn into_dynamic(buf: WorkBuffer<Self>) -> DynamicImage {
+        DynamicImage::ImageLuma8(buf)
+    }
+    fn from_rgba8(color: Rgba<u8>) -> Self {
+        color.to_luma()
+    }
+}
+
+impl WorkPixel for Luma<u16> {
+    fn buffer_from(img: &DynamicImage) -> WorkBuffer<Self> {
+        img.to_luma16()
+    }
+    fn into_dynamic(buf: WorkBuffer<Self>) -> DynamicImage {
+        DynamicImage::ImageLuma16(buf)
+    }
+    fn from_rgba8(color: Rgba<u8>) -> Self {
+        Luma(scale_channels::<u16, 1>(color.to_luma().0))
+    }
+}
+
+/// 8bitを超えるビット深度の画像かどうか
+pub fn is_high_bit_depth(img: &DynamicImage) -> bool {
+    !matches!(
+        img,
+        DynamicImage::ImageLuma8(_)
+            | DynamicImage::ImageLumaA8(_)
+            | DynamicImage::ImageRgb8(_)
+            | DynamicImage::ImageRgba8(_)
+    )
+}
+
+/// 8bit色の各チャンネルを作業バッファのビット深度にスケーリング
+fn scale_channels<S: WorkSample, const N: usize>(channels: [u8; N]) -> [S; N] {
+    let max: f32 = S::DEFAULT_MAX_VALUE.into();
+    channels.map(|c| <S as Clamp<f32>>::clamp(c as f32 / 255.0 * max + 0.5))
+}
+
+/// 色付きの線・塗り（シアンなど）を実際に描画するか
+/// グレースケール出力できるかの判定に使う
+fn uses_color_overlay(options: &ProcessOptions) -> bool {
+    let is_colored = |c: Rgba<u8>| c[0] != c[1] || c[1] != c[2];
+    let (stroke, fill) = match options.tachikiri_type.as_str() {
+        "crop_and_stroke" | "stroke_only" => (true, false),
+        "fill_white" => (false, true),
+        "fill_and_stroke" => (true, true),
+        _ => (false, false),
+    };
+    let stroke_colored = |color: &str| is_colored(color_to_rgb(color));
+    let fill_colored = |color: &str| options.fill_opacity > 0 && is_colored(color_to_rgba(color, 100));
+    let frame_colored = |style: &FrameStyle| {
+        (style.stroke && stroke_colored(style.color.as_deref().unwrap_or(&options.stroke_color)))
+            || (style.fill_outside && fill_colored(style.color.as_deref().unwrap_or(&options.fill_color)))
+    };
+    let nombre = &options.nombre;
+    let nombre_colored = options.add_nombre
+        && (stroke_colored(&nombre.text_color)
+            || (nombre.show_box && !nombre.hidden && nombre.box_opacity > 0 && stroke_colored(&nombre.box_color)));
+    (stroke && stroke_colored(&options.stroke_color))
+        || (fill && fill_colored(&options.fill_color))
+        || [&options.trim_style, &options.bleed_style, &options.safe_style].into_iter().any(frame_colored)
+        || nombre_colored
+}
 
 /// 単一画像を処理
 pub fn process_single_image(
@@ -22,57 +142,189 @@ pub fn process_single_image(
     output_path: &Path,
     options: &ProcessOptions,
     page_number: u32,
-) -> Result<(), String> {
-    let img = load_image(input_path)?;
-    let (orig_width, orig_height) = img.dimensions();
+) -> Result<PageReport, String> {
+    let (img, icc_profile) = match &options.layer_filter {
+        Some(filter) if is_psd_file(input_path) => {
+            // レイヤー合成のCMYKは簡易変換のため、CMYKプロファイルは引き継がない
+            let img = render_psd_layers(input_path, filter)?;
+            (img, extract_psd_icc_profile(input_path).filter(|icc| !is_cmyk_profile(icc)))
+        }
+        _ => load_image_with_profile(input_path)?,
+    };
 
-    // タチキリタイプが "none" なら何もせずコピー
-    if options.tachikiri_type == "none" {
-        let mut result = img.to_rgba8();
+    // 出力プロファイルへ変換（"keep"なら元のプロファイルを引き継ぐ）
+    let (img, icc_profile) = convert_to_output_profile(img, icc_profile, &options.color_profile)?;
 
-        // ノンブル追加
-        if options.add_nombre {
-            let scale_y = if options.reference_height > 0 {
-                orig_height as f64 / options.reference_height as f64
-            } else {
-                1.0
-            };
-            let scaled_bottom = (options.crop_bottom as f64 * scale_y).round() as u32;
-            let crop_bottom_y = scaled_bottom.min(orig_height);
-            let nombre_margin = orig_height.saturating_sub(crop_bottom_y);
-            add_nombre_to_image(&mut result, page_number, &options.nombre_size, nombre_margin);
-        }
+    // モノクロ原稿は1チャンネルのまま処理する（色付きの線・塗りを使う場合のみRGB）
+    let grayscale = should_output_grayscale(&img, &options.color_mode, &options.color_profile)
+        && !uses_color_overlay(options);
+    let (img, icc_profile) = if grayscale {
+        convert_to_grayscale(img, icc_profile)?
+    } else {
+        (img, icc_profile)
+    };
+
+    // 解像度: 上書き指定 → 元画像の解像度情報 → JPEG設定の既定値（トンボのmm換算にも使う）
+    let source_dpi = options.dpi_override
+        .filter(|dpi| *dpi > 0.0)
+        .or_else(|| read_image_dpi(input_path));
+    let render_dpi = source_dpi.unwrap_or(options.jpeg.dpi.max(1) as f32);
+
+    // 左右ページ（ページ番号の奇偶と綴じ方向）で仕上がり枠のずらし量・ノンブル位置を切り替える
+    let side = page_side(page_number, &options.binding);
+
+    // 傾き補正・位置合わせ（クロップ・塗り・ノンブルより前）
+    let (img, skew_degrees, registration_offset) = if options.align.deskew || options.align.register {
+        let aligned = match (grayscale, is_high_bit_depth(&img)) {
+            (true, true) => align_page::<Luma<u16>>(&img, options, side),
+            (true, false) => align_page::<Luma<u8>>(&img, options, side),
+            (false, true) => align_page::<Rgba<u16>>(&img, options, side),
+            (false, false) => align_page::<Rgba<u8>>(&img, options, side),
+        };
+        (aligned.image, aligned.skew_degrees, aligned.offset)
+    } else {
+        (img, None, None)
+    };
+
+    let (crop, crop_source, crop_confidence) = resolve_crop(input_path, &img, options, side);
+
+    // 16bit/32bitソースは16bitバッファで処理し、JPEG書き出し時にのみ8bitへ量子化する
+    let result = match (grayscale, is_high_bit_depth(&img)) {
+        (true, true) => render_page::<Luma<u16>>(&img, options, side, crop, page_number, render_dpi)?,
+        (true, false) => render_page::<Luma<u8>>(&img, options, side, crop, page_number, render_dpi)?,
+        (false, true) => render_page::<Rgba<u16>>(&img, options, side, crop, page_number, render_dpi)?,
+        (false, false) => render_page::<Rgba<u8>>(&img, options, side, crop, page_number, render_dpi)?,
+    };
+    drop(img);
+
+    // リサイズ処理
+    let rendered_width = result.width();
+    let final_image = apply_resize(result, options);
+
+    // リサイズ分だけ解像度を変えて物理サイズを保つ
+    let dpi = match source_dpi {
+        Some(dpi) => (dpi * final_image.width() as f32 / rendered_width.max(1) as f32).round().clamp(1.0, u16::MAX as f32) as u16,
+        None => options.jpeg.dpi,
+    };
 
-        // リサイズ処理
-        let final_image = apply_resize(DynamicImage::ImageRgba8(result), options);
+    // 出力形式に従って保存（グレースケールは1チャンネル、プロファイルは色空間が一致する場合のみ埋め込む）
+    write_output_image(&final_image, icc_profile.as_deref(), options, dpi, output_path)?;
 
-        // MozJPEGで保存
-        let rgb_image = final_image.to_rgb8();
-        write_jpeg_mozjpeg_to_file(rgb_image.as_raw(), rgb_image.width(), rgb_image.height(), JPEG_QUALITY, output_path)?;
-        return Ok(());
+    Ok(PageReport {
+        file: input_path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+        crop_source: crop_source.to_string(),
+        crop_confidence,
+        skew_degrees,
+        registration_offset,
+    })
+}
+
+/// ページの仕上がり枠と取得元、自動検出の信頼度を決める
+/// "guides" はPSDのガイド、"auto" は自動検出（信頼度が閾値以上の場合のみ）から求め、求められなければ共通のクロップ範囲
+fn resolve_crop(
+    input_path: &Path,
+    img: &DynamicImage,
+    options: &ProcessOptions,
+    side: PageSide,
+) -> (PixelRect, &'static str, Option<f32>) {
+    let (width, height) = img.dimensions();
+    let global_crop = scaled_crop_rect(options, side, width, height);
+
+    match options.crop_source.as_str() {
+        CROP_SOURCE_GUIDES if is_psd_file(input_path) => {
+            let guide_crop = extract_psd_guides(input_path)
+                .ok()
+                .and_then(|guides| crop_from_guides(&guides, width, height, &options.guide_rule, global_crop));
+            if let Some(crop) = guide_crop {
+                return (crop, CROP_SOURCE_GUIDES, None);
+            }
+        }
+        CROP_SOURCE_AUTO => {
+            if let Some(detected) = detect_crop(img) {
+                if detected.confidence >= options.auto_crop_min_confidence {
+                    return (detected.rect(), CROP_SOURCE_AUTO, Some(detected.confidence));
+                }
+                return (global_crop, CROP_SOURCE_GLOBAL, Some(detected.confidence));
+            }
+        }
+        _ => {}
     }
+    (global_crop, CROP_SOURCE_GLOBAL, None)
+}
 
-    // スケーリング計算
-    let (scale_x, scale_y) = if options.reference_width > 0 && options.reference_height > 0 {
+/// 基準ドキュメントサイズに対するスケール（基準サイズ未指定なら等倍）
+fn reference_scale(options: &ProcessOptions, width: u32, height: u32) -> (f64, f64) {
+    if options.reference_width > 0 && options.reference_height > 0 {
         (
-            orig_width as f64 / options.reference_width as f64,
-            orig_height as f64 / options.reference_height as f64,
+            width as f64 / options.reference_width as f64,
+            height as f64 / options.reference_height as f64,
         )
     } else {
         (1.0, 1.0)
+    }
+}
+
+/// 左右ページの設定
+pub fn page_side_options(options: &ProcessOptions, side: PageSide) -> &PageSideOptions {
+    match side {
+        PageSide::Left => &options.left_page,
+        PageSide::Right => &options.right_page,
+    }
+}
+
+/// 枠を左右ページのずらし量だけ移動（基準ドキュメント座標、0未満は0）
+fn shift_frame(frame: &FrameRect, side_options: &PageSideOptions) -> FrameRect {
+    let shift = |v: u32, d: i32| (v as i64 + d as i64).max(0) as u32;
+    let (dx, dy) = (side_options.crop_offset_x, side_options.crop_offset_y);
+    FrameRect {
+        left: shift(frame.left, dx),
+        top: shift(frame.top, dy),
+        right: shift(frame.right, dx),
+        bottom: shift(frame.bottom, dy),
+    }
+}
+
+/// 共通のクロップ範囲（crop_*、左右ページのずらし量込み）を画像座標にスケーリング
+pub fn scaled_crop_rect(options: &ProcessOptions, side: PageSide, width: u32, height: u32) -> PixelRect {
+    let (scale_x, scale_y) = reference_scale(options, width, height);
+    let trim_frame = FrameRect {
+        left: options.crop_left,
+        top: options.crop_top,
+        right: options.crop_right,
+        bottom: options.crop_bottom,
     };
+    scale_frame(&shift_frame(&trim_frame, page_side_options(options, side)), scale_x, scale_y, width, height)
+}
 
-    // クロップ座標をスケーリング
-    let scaled_left = (options.crop_left as f64 * scale_x).round() as u32;
-    let scaled_top = (options.crop_top as f64 * scale_y).round() as u32;
-    let scaled_right = (options.crop_right as f64 * scale_x).round() as u32;
-    let scaled_bottom = (options.crop_bottom as f64 * scale_y).round() as u32;
+/// クロップ・タチキリ処理・ノンブル追加を作業バッファ上で行う
+/// crop は画像座標の仕上がり枠、dpi はmm指定（トンボなど）のピクセル換算に使う
+fn render_page<P: WorkPixel>(
+    img: &DynamicImage,
+    options: &ProcessOptions,
+    side: PageSide,
+    crop: PixelRect,
+    page_number: u32,
+    dpi: f32,
+) -> Result<DynamicImage, String>
+where
+    P::Subpixel: WorkSample,
+{
+    let (orig_width, orig_height) = img.dimensions();
+    let (crop_left, crop_top, crop_right, crop_bottom) = crop;
+    let side_options = page_side_options(options, side);
+    let edge = nombre_placement_edge(&options.nombre, side, &side_options.nombre_position);
 
-    // クロップ座標の検証
-    let crop_left = scaled_left.min(orig_width);
-    let crop_top = scaled_top.min(orig_height);
-    let crop_right = scaled_right.min(orig_width).max(crop_left);
-    let crop_bottom = scaled_bottom.min(orig_height).max(crop_top);
+    // タチキリタイプが "none" なら何もせずコピー
+    if options.tachikiri_type == "none" {
+        let mut result = P::buffer_from(img);
+
+        // ノンブル追加
+        if options.add_nombre {
+            add_nombre_to_image(&mut result, page_number, &options.nombre_size, &options.nombre, crop, edge, dpi)?;
+        }
+
+        return Ok(P::into_dynamic(result));
+    }
 
     let crop_width = crop_right - crop_left;
     let crop_height = crop_bottom - crop_top;
@@ -81,24 +333,58 @@ pub fn process_single_image(
         return Err("クロップ範囲が無効です".to_string());
     }
 
-    let mut result: RgbaImage;
+    // 裁ち切り枠・内枠（指定がなければ描画しない）
+    let (scale_x, scale_y) = reference_scale(options, orig_width, orig_height);
+    let scale = |frame: &FrameRect| scale_frame(&shift_frame(frame, side_options), scale_x, scale_y, orig_width, orig_height);
+    let bleed_rect = options.bleed_frame.as_ref().map(scale);
+    let safe_rect = options.safe_frame.as_ref().map(scale);
+    let frames = [
+        (Some((crop_left, crop_top, crop_right, crop_bottom)), &options.trim_style),
+        (bleed_rect, &options.bleed_style),
+        (safe_rect, &options.safe_style),
+    ];
+
+    // トンボ: キャンバスが広がるため、ノンブルは仕上がり枠の上下の余白に描いてからトンボを重ねる
+    if options.tachikiri_type == "trim_marks" {
+        let (mut result, trim_origin) =
+            crop_with_bleed::<P>(img, crop_left, crop_top, crop_width, crop_height, &options.trim_marks, dpi);
+        let origin = (crop_left as i64 - trim_origin as i64, crop_top as i64 - trim_origin as i64);
+        draw_frames(&mut result, &frames, origin, options);
+        if options.add_nombre {
+            let trim = (trim_origin, trim_origin, trim_origin + crop_width, trim_origin + crop_height);
+            add_nombre_to_image(&mut result, page_number, &options.nombre_size, &options.nombre, trim, edge, dpi)?;
+        }
+        draw_trim_marks(&mut result, trim_origin, crop_width, crop_height, &options.trim_marks, dpi);
+        return Ok(P::into_dynamic(result));
+    }
+
+    // クロップ系で切り出す範囲（裁ち切り枠指定時は裁ち落とし込みで切り出せる）
+    let (cut_left, cut_top, cut_right, cut_bottom) = match bleed_rect {
+        Some((l, t, r, b)) if options.crop_frame == "bleed" && r > l && b > t => (l, t, r, b),
+        _ => (crop_left, crop_top, crop_right, crop_bottom),
+    };
+
+    let mut result: WorkBuffer<P>;
+    let mut origin = (0, 0);
 
     match options.tachikiri_type.as_str() {
         "crop" | "crop_only" => {
-            let cropped = img.crop_imm(crop_left, crop_top, crop_width, crop_height);
-            result = cropped.to_rgba8();
+            let cropped = img.crop_imm(cut_left, cut_top, cut_right - cut_left, cut_bottom - cut_top);
+            result = P::buffer_from(&cropped);
+            origin = (cut_left as i64, cut_top as i64);
         }
         "crop_and_stroke" => {
-            let cropped = img.crop_imm(crop_left, crop_top, crop_width, crop_height);
-            result = cropped.to_rgba8();
+            let cropped = img.crop_imm(cut_left, cut_top, cut_right - cut_left, cut_bottom - cut_top);
+            result = P::buffer_from(&cropped);
+            origin = (cut_left as i64, cut_top as i64);
             draw_stroke(&mut result, &options.stroke_color);
         }
         "stroke_only" => {
-            result = img.to_rgba8();
+            result = P::buffer_from(img);
             draw_stroke_at_crop(&mut result, crop_left, crop_top, crop_right, crop_bottom, &options.stroke_color);
         }
         "fill_white" | "fill_and_stroke" => {
-            result = img.to_rgba8();
+            result = P::buffer_from(img);
             fill_outside_crop(&mut result, crop_left, crop_top, crop_right, crop_bottom, &options.fill_color, options.fill_opacity);
 
             if options.tachikiri_type == "fill_and_stroke" {
@@ -106,27 +392,82 @@ pub fn process_single_image(
             }
         }
         _ => {
-            result = img.to_rgba8();
+            result = P::buffer_from(img);
         }
     }
 
-    // ノンブル追加
+    draw_frames(&mut result, &frames, origin, options);
+
+    // ノンブル追加（仕上がり枠の上下の余白、なければ枠内に描く）
     if options.add_nombre {
-        let nombre_margin = match options.tachikiri_type.as_str() {
-            "crop" | "crop_only" | "crop_and_stroke" => 0,
-            _ => orig_height.saturating_sub(crop_bottom),
-        };
-        add_nombre_to_image(&mut result, page_number, &options.nombre_size, nombre_margin);
+        let to_result = |v: u32, origin: i64, size: u32| (v as i64 - origin).clamp(0, size as i64) as u32;
+        let (width, height) = result.dimensions();
+        let trim = (
+            to_result(crop_left, origin.0, width),
+            to_result(crop_top, origin.1, height),
+            to_result(crop_right, origin.0, width),
+            to_result(crop_bottom, origin.1, height),
+        );
+        add_nombre_to_image(&mut result, page_number, &options.nombre_size, &options.nombre, trim, edge, dpi)?;
     }
 
-    // リサイズ処理
-    let final_image = apply_resize(DynamicImage::ImageRgba8(result), options);
+    Ok(P::into_dynamic(result))
+}
 
-    // MozJPEGで保存
-    let rgb_image = final_image.to_rgb8();
-    write_jpeg_mozjpeg_to_file(rgb_image.as_raw(), rgb_image.width(), rgb_image.height(), JPEG_QUALITY, output_path)?;
+/// 画像座標の矩形（left, top, right, bottom）
+pub type PixelRect = (u32, u32, u32, u32);
 
-    Ok(())
+/// 基準ドキュメント座標の枠を画像座標にスケーリングし、画像内に収める
+pub fn scale_frame(frame: &FrameRect, scale_x: f64, scale_y: f64, width: u32, height: u32) -> PixelRect {
+    let left = ((frame.left as f64 * scale_x).round() as u32).min(width);
+    let top = ((frame.top as f64 * scale_y).round() as u32).min(height);
+    let right = ((frame.right as f64 * scale_x).round() as u32).min(width).max(left);
+    let bottom = ((frame.bottom as f64 * scale_y).round() as u32).min(height).max(top);
+    (left, top, right, bottom)
+}
+
+/// 仕上がり枠・裁ち切り枠・内枠の塗りと線を描く（塗りをすべて描いてから線を重ねる）
+/// origin: 出力画像の左上に当たる元画像の座標（クロップ・キャンバス拡張の分ずらす）
+/// 出力画像からはみ出す枠の線は描かない
+fn draw_frames<P: WorkPixel>(
+    img: &mut WorkBuffer<P>,
+    frames: &[(Option<PixelRect>, &FrameStyle)],
+    origin: (i64, i64),
+    options: &ProcessOptions,
+) where
+    P::Subpixel: WorkSample,
+{
+    let (width, height) = (img.width() as i64, img.height() as i64);
+    let mapped: Vec<_> = frames
+        .iter()
+        .filter_map(|(rect, style)| {
+            let (l, t, r, b) = (*rect)?;
+            Some((
+                (l as i64 - origin.0, t as i64 - origin.1, r as i64 - origin.0, b as i64 - origin.1),
+                *style,
+            ))
+        })
+        .collect();
+    let clamp = |v: i64, max: i64| v.clamp(0, max) as u32;
+
+    for ((l, t, r, b), style) in &mapped {
+        if style.fill_outside {
+            let color = style.color.as_deref().unwrap_or(&options.fill_color);
+            fill_outside_crop(
+                img,
+                clamp(*l, width), clamp(*t, height), clamp(*r, width), clamp(*b, height),
+                color,
+                options.fill_opacity,
+            );
+        }
+    }
+    for ((l, t, r, b), style) in &mapped {
+        let inside = *l >= 0 && *t >= 0 && *r <= width && *b <= height && r > l && b > t;
+        if style.stroke && inside {
+            let color = style.color.as_deref().unwrap_or(&options.stroke_color);
+            draw_stroke_at_crop(img, *l as u32, *t as u32, *r as u32, *b as u32, color);
+        }
+    }
 }
 
 /// リサイズ処理を適用
@@ -152,9 +493,9 @@ fn apply_resize(img: DynamicImage, options: &ProcessOptions) -> DynamicImage {
 }
 
 /// 画像の境界に線を描画
-pub fn draw_stroke(img: &mut RgbaImage, color: &str) {
+pub fn draw_stroke<P: WorkPixel>(img: &mut WorkBuffer<P>, color: &str) {
     let (width, height) = img.dimensions();
-    let stroke_color = color_to_rgb(color);
+    let stroke_color = P::from_rgba8(color_to_rgb(color));
 
     for x in 0..width {
         img.put_pixel(x, 0, stroke_color);
@@ -166,16 +507,74 @@ pub fn draw_stroke(img: &mut RgbaImage, color: &str) {
     }
 }
 
+/// クロップ範囲を仕上がりとして裁ち落とし込みで切り出し、トンボ分の余白を付けた白いキャンバスに配置
+/// 元画像の外にはみ出す裁ち落とし部分は白のまま。戻り値の2つ目はキャンバス上の仕上がり枠の左上（上下左右同じ）
+pub fn crop_with_bleed<P: WorkPixel>(
+    img: &DynamicImage,
+    crop_left: u32,
+    crop_top: u32,
+    crop_width: u32,
+    crop_height: u32,
+    marks: &TrimMarkOptions,
+    dpi: f32,
+) -> (WorkBuffer<P>, u32) {
+    let px_per_mm = dpi / 25.4;
+    let bleed = (marks.bleed_mm.max(0.0) * px_per_mm).round() as u32;
+    let trim_origin = (trim_mark_margin_mm(marks).max(0.0) * px_per_mm).round() as u32;
+    let (width, height) = (crop_width + trim_origin * 2, crop_height + trim_origin * 2);
+    let mut canvas = ImageBuffer::from_pixel(width, height, P::from_rgba8(Rgba([255, 255, 255, 255])));
+
+    // 裁ち落とし込みの絵柄（元画像の範囲内のみ）
+    let src_left = crop_left.saturating_sub(bleed);
+    let src_top = crop_top.saturating_sub(bleed);
+    let src_right = (crop_left + crop_width + bleed).min(img.width());
+    let src_bottom = (crop_top + crop_height + bleed).min(img.height());
+    let art = P::buffer_from(&img.crop_imm(src_left, src_top, src_right - src_left, src_bottom - src_top));
+    let x = trim_origin as i64 - (crop_left - src_left) as i64;
+    let y = trim_origin as i64 - (crop_top - src_top) as i64;
+    imageops::replace(&mut canvas, &art, x, y);
+
+    (canvas, trim_origin)
+}
+
+/// 仕上がり枠の外側にトンボ（角・センター、ダブルトンボ）を黒で描画
+pub fn draw_trim_marks<P: WorkPixel>(
+    img: &mut WorkBuffer<P>,
+    trim_origin: u32,
+    trim_width: u32,
+    trim_height: u32,
+    marks: &TrimMarkOptions,
+    dpi: f32,
+) {
+    let px_per_mm = dpi / 25.4;
+    let thickness = ((marks.line_width_mm * px_per_mm).round() as i32).max(1);
+    let color = P::from_rgba8(Rgba([0, 0, 0, 255]));
+    let to_px = |mm: f32| trim_origin as i32 + (mm * px_per_mm).round() as i32;
+
+    let segments = trim_mark_segments(trim_width as f32 / px_per_mm, trim_height as f32 / px_per_mm, marks);
+    for segment in segments {
+        let (x1, x2) = (to_px(segment.x1.min(segment.x2)), to_px(segment.x1.max(segment.x2)));
+        let (y1, y2) = (to_px(segment.y1.min(segment.y2)), to_px(segment.y1.max(segment.y2)));
+        // 線の中心が座標に来るよう太さの半分ずらす
+        let rect = if y1 == y2 {
+            Rect::at(x1, y1 - thickness / 2).of_size((x2 - x1).max(1) as u32, thickness as u32)
+        } else {
+            Rect::at(x1 - thickness / 2, y1).of_size(thickness as u32, (y2 - y1).max(1) as u32)
+        };
+        draw_filled_rect_mut(img, rect, color);
+    }
+}
+
 /// 指定座標に線を描画
-pub fn draw_stroke_at_crop(
-    img: &mut RgbaImage,
+pub fn draw_stroke_at_crop<P: WorkPixel>(
+    img: &mut WorkBuffer<P>,
     left: u32,
     top: u32,
     right: u32,
     bottom: u32,
     color: &str,
 ) {
-    let stroke_color = color_to_rgb(color);
+    let stroke_color = P::from_rgba8(color_to_rgb(color));
 
     for x in left..right {
         if top < img.height() {
@@ -196,29 +595,41 @@ pub fn draw_stroke_at_crop(
 }
 
 /// クロップ範囲外を塗りつぶす
-pub fn fill_outside_crop(
-    img: &mut RgbaImage,
+pub fn fill_outside_crop<P: WorkPixel>(
+    img: &mut WorkBuffer<P>,
     left: u32,
     top: u32,
     right: u32,
     bottom: u32,
     color: &str,
     opacity: u8,
-) {
+) where
+    P::Subpixel: WorkSample,
+{
     let (width, height) = img.dimensions();
     let fill_color = color_to_rgba(color, opacity);
-    let raw: &mut [u8] = img.as_mut();
-    let bytes_per_pixel = 4usize;
+    let fill_pixel = P::from_rgba8(Rgba([fill_color[0], fill_color[1], fill_color[2], 255]));
+    let fill_channels = fill_pixel.channels();
+    let raw: &mut [P::Subpixel] = img.as_mut();
+    let bytes_per_pixel = P::CHANNEL_COUNT as usize;
+    let color_channels = if P::HAS_ALPHA { bytes_per_pixel - 1 } else { bytes_per_pixel };
     let stride = width as usize * bytes_per_pixel;
 
     let alpha = fill_color[3] as f32 / 255.0;
     let inv_alpha = 1.0 - alpha;
 
-    let blend_pixel = |base: &mut [u8]| {
-        base[0] = (base[0] as f32 * inv_alpha + fill_color[0] as f32 * alpha) as u8;
-        base[1] = (base[1] as f32 * inv_alpha + fill_color[1] as f32 * alpha) as u8;
-        base[2] = (base[2] as f32 * inv_alpha + fill_color[2] as f32 * alpha) as u8;
-        base[3] = 255;
+    let blend = |base: P::Subpixel, fill: P::Subpixel| -> P::Subpixel {
+        let base: f32 = base.into();
+        let fill: f32 = fill.into();
+        <P::Subpixel as Clamp<f32>>::clamp(base * inv_alpha + fill * alpha)
+    };
+    let blend_pixel = |base: &mut [P::Subpixel]| {
+        for (b, f) in base[..color_channels].iter_mut().zip(fill_channels) {
+            *b = blend(*b, *f);
+        }
+        if P::HAS_ALPHA {
+            base[color_channels] = P::Subpixel::DEFAULT_MAX_VALUE;
+        }
     };
 
     // 上部領域
@@ -226,7 +637,7 @@ pub fn fill_outside_crop(
         let row_start = y * stride;
         for x in 0..width as usize {
             let pixel_start = row_start + x * bytes_per_pixel;
-            blend_pixel(&mut raw[pixel_start..pixel_start + 4]);
+            blend_pixel(&mut raw[pixel_start..pixel_start + bytes_per_pixel]);
         }
     }
 
@@ -235,7 +646,7 @@ pub fn fill_outside_crop(
         let row_start = y * stride;
         for x in 0..width as usize {
             let pixel_start = row_start + x * bytes_per_pixel;
-            blend_pixel(&mut raw[pixel_start..pixel_start + 4]);
+            blend_pixel(&mut raw[pixel_start..pixel_start + bytes_per_pixel]);
         }
     }
 
@@ -244,7 +655,7 @@ pub fn fill_outside_crop(
         let row_start = y * stride;
         for x in 0..left as usize {
             let pixel_start = row_start + x * bytes_per_pixel;
-            blend_pixel(&mut raw[pixel_start..pixel_start + 4]);
+            blend_pixel(&mut raw[pixel_start..pixel_start + bytes_per_pixel]);
         }
     }
 
@@ -253,7 +664,7 @@ pub fn fill_outside_crop(
         let row_start = y * stride;
         for x in right as usize..width as usize {
             let pixel_start = row_start + x * bytes_per_pixel;
-            blend_pixel(&mut raw[pixel_start..pixel_start + 4]);
+            blend_pixel(&mut raw[pixel_start..pixel_start + bytes_per_pixel]);
         }
     }
 }
@@ -271,20 +682,80 @@ pub fn blend_pixels(base: Rgba<u8>, overlay: Rgba<u8>) -> Rgba<u8> {
     ])
 }
 
-/// 画像にノンブル（ページ番号）を追加
-pub fn add_nombre_to_image(img: &mut RgbaImage, page_num: u32, size_key: &str, crop_bottom: u32) {
-    let font_size = get_nombre_font_size(size_key);
-    let text = page_num.to_string();
+/// マスクの濃さを不透明度として色を重ねる（文字の描画用、画像外にはみ出した部分は無視）
+/// 16bitの作業バッファにも描けるよう、文字は8bitのマスクに描いてから合成する
+fn blend_mask<P: WorkPixel>(img: &mut WorkBuffer<P>, mask: &GrayImage, x: i32, y: i32, color: Rgba<u8>)
+where
+    P::Subpixel: WorkSample,
+{
+    let fill = P::from_rgba8(Rgba([color[0], color[1], color[2], 255]));
+    let color_channels = if P::HAS_ALPHA { P::CHANNEL_COUNT - 1 } else { P::CHANNEL_COUNT } as usize;
+    let (width, height) = (img.width() as i32, img.height() as i32);
+
+    for (mx, my, coverage) in mask.enumerate_pixels() {
+        let (px, py) = (x + mx as i32, y + my as i32);
+        if coverage[0] == 0 || px < 0 || py < 0 || px >= width || py >= height {
+            continue;
+        }
+        let alpha = coverage[0] as f32 / 255.0;
+        let pixel = img.get_pixel_mut(px as u32, py as u32);
+        for (base, over) in pixel.channels_mut()[..color_channels].iter_mut().zip(fill.channels()) {
+            let (b, o): (f32, f32) = ((*base).into(), (*over).into());
+            *base = <P::Subpixel as Clamp<f32>>::clamp(b * (1.0 - alpha) + o * alpha);
+        }
+    }
+}
 
-    let font_data = match get_cached_font_data() {
-        Some(data) => data,
-        None => return,
-    };
+/// 矩形を色でアルファブレンドして塗る（画像外にはみ出した部分は無視）
+fn blend_rect<P: WorkPixel>(img: &mut WorkBuffer<P>, rect: Rect, color: Rgba<u8>)
+where
+    P::Subpixel: WorkSample,
+{
+    let alpha = color[3] as f32 / 255.0;
+    let fill = P::from_rgba8(Rgba([color[0], color[1], color[2], 255]));
+    let color_channels = if P::HAS_ALPHA { P::CHANNEL_COUNT - 1 } else { P::CHANNEL_COUNT } as usize;
+    let (width, height) = img.dimensions();
+    let clamp_x = |x: i32| x.clamp(0, width as i32) as u32;
+    let clamp_y = |y: i32| y.clamp(0, height as i32) as u32;
+
+    for y in clamp_y(rect.top())..clamp_y(rect.bottom() + 1) {
+        for x in clamp_x(rect.left())..clamp_x(rect.right() + 1) {
+            let pixel = img.get_pixel_mut(x, y);
+            for (base, over) in pixel.channels_mut()[..color_channels].iter_mut().zip(fill.channels()) {
+                let (b, o): (f32, f32) = ((*base).into(), (*over).into());
+                *base = <P::Subpixel as Clamp<f32>>::clamp(b * (1.0 - alpha) + o * alpha);
+            }
+        }
+    }
+}
 
-    let font = match FontRef::try_from_slice(font_data) {
-        Ok(f) => f,
-        Err(_) => return,
+/// 画像にノンブル（ページ番号）を追加
+/// trim: 仕上がり枠（出力画像の座標）、edge: 寄せる側（None は画像の中央）、dpi: mm・pt指定のピクセル換算に使う
+/// 隠しノンブルは仕上がり枠の内側、寄せる側（ノド）の角に小さく描く
+/// フォントが見つからない・読み込めない場合はエラー
+pub fn add_nombre_to_image<P: WorkPixel>(
+    img: &mut WorkBuffer<P>,
+    page_num: u32,
+    size_key: &str,
+    nombre: &NombreOptions,
+    trim: PixelRect,
+    edge: Option<PageSide>,
+    dpi: f32,
+) -> Result<(), String>
+where
+    P::Subpixel: WorkSample,
+{
+    let font_size = if nombre.hidden {
+        (nombre.hidden_size_pt.max(1.0) / 72.0 * dpi).round()
+    } else {
+        get_nombre_font_size(size_key)
     };
+    let text = nombre_label(page_num, nombre);
+
+    // 漢数字など欧文フォントにない文字を含む場合は和文フォントで描く
+    let role = if text.is_ascii() { FontRole::Latin } else { FontRole::Japanese };
+    let loaded = load_font(nombre.font.as_ref(), role)?;
+    let font = loaded.font_ref()?;
 
     let scale = PxScale::from(font_size);
     let scaled_font = font.as_scaled(scale);
@@ -302,37 +773,68 @@ pub fn add_nombre_to_image(img: &mut RgbaImage, page_num: u32, size_key: &str, c
         prev_glyph = Some(glyph_id);
     }
 
-    let padding_x = (font_size * 0.5) as i32;
-    let padding_y = (font_size * 0.3) as i32;
-    let box_width = text_width as i32 + padding_x * 2;
-    let box_height = font_size as i32 + padding_y * 2;
-    let bottom_margin = (font_size * 0.4) as i32;
-
-    let box_x = (img_width as i32 - box_width) / 2;
-    let box_y = if crop_bottom > 0 && crop_bottom < img_height / 2 {
-        let crop_area_top = img_height as i32 - crop_bottom as i32;
-        let center_y = crop_area_top + (crop_bottom as i32 - box_height) / 2;
-        center_y.max(crop_area_top + 5).min(img_height as i32 - box_height - 5)
+    let (trim_left, trim_top, trim_right, trim_bottom) = (trim.0 as i32, trim.1 as i32, trim.2 as i32, trim.3 as i32);
+    let top = nombre.vertical == NOMBRE_VERTICAL_TOP;
+
+    let (box_x, box_y, box_width, box_height) = if nombre.hidden {
+        // 仕上がり線から文字半分内側
+        let margin = (font_size * 0.5) as i32;
+        let (width, height) = (text_width as i32, font_size as i32);
+        let x = match edge {
+            None => (trim_left + trim_right - width) / 2,
+            Some(PageSide::Left) => trim_left + margin,
+            Some(PageSide::Right) => trim_right - margin - width,
+        };
+        let y = if top { trim_top + margin } else { trim_bottom - margin - height };
+        (x, y, width, height)
     } else {
-        img_height as i32 - bottom_margin - box_height
+        let padding_x = (font_size * 0.5) as i32;
+        let padding_y = (font_size * 0.3) as i32;
+        let box_width = text_width as i32 + padding_x * 2;
+        let box_height = font_size as i32 + padding_y * 2;
+        let edge_margin = (font_size * 0.4) as i32;
+
+        // 小口・ノド側に寄せる場合は仕上がり枠の端から文字1つ分内側
+        let side_margin = font_size as i32;
+        let box_x = match edge {
+            None => (img_width as i32 - box_width) / 2,
+            Some(PageSide::Left) => (trim_left + side_margin).min(img_width as i32 - box_width),
+            Some(PageSide::Right) => (trim_right - side_margin - box_width).max(0),
+        };
+
+        // 仕上がり枠の外側に余白があればその中央、なければ画像の端から少し内側
+        let margin = if top { trim_top } else { img_height as i32 - trim_bottom };
+        let in_margin = margin > 0 && margin < img_height as i32 / 2;
+        let box_y = match (top, in_margin) {
+            (false, true) => {
+                let crop_area_top = img_height as i32 - margin;
+                let center_y = crop_area_top + (margin - box_height) / 2;
+                center_y.max(crop_area_top + 5).min(img_height as i32 - box_height - 5)
+            }
+            (false, false) => img_height as i32 - edge_margin - box_height,
+            (true, true) => ((margin - box_height) / 2).min(margin - box_height - 5).max(5),
+            (true, false) => edge_margin,
+        };
+        (box_x, box_y, box_width, box_height)
     };
 
+    // ずらし量（mm）
+    let px_per_mm = dpi / 25.4;
+    let box_x = box_x + (nombre.offset_x_mm * px_per_mm).round() as i32;
+    let box_y = box_y + (nombre.offset_y_mm * px_per_mm).round() as i32;
+
     // 背景ボックス
-    let bg_color = Rgba([255, 255, 255, 210]);
-    let rect = Rect::at(box_x, box_y).of_size(box_width as u32, box_height as u32);
-    draw_filled_rect_mut(img, rect, bg_color);
+    if nombre.show_box && !nombre.hidden && box_width > 0 && box_height > 0 {
+        let rect = Rect::at(box_x, box_y).of_size(box_width as u32, box_height as u32);
+        blend_rect(img, rect, color_to_rgba(&nombre.box_color, nombre.box_opacity));
+    }
 
     // テキスト
     let text_x = box_x + (box_width - text_width as i32) / 2;
     let text_y = box_y + (box_height - font_size as i32) / 2;
 
-    draw_text_mut(
-        img,
-        Rgba([60, 60, 60, 255]),
-        text_x,
-        text_y,
-        scale,
-        &font,
-        &text,
-    );
+    let mut mask = GrayImage::new(text_width.ceil().max(1.0) as u32, scaled_font.height().ceil().max(1.0) as u32);
+    draw_text_mut(&mut mask, Luma([255]), 0, 0, scale, &font, &text);
+    blend_mask(img, &mask, text_x, text_y, color_to_rgb(&nombre.text_color));
+    Ok(())
 }
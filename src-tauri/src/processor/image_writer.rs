@@ -0,0 +1,535 @@
+//! タチミ - 画像書き出しモジュール
+//! JPEG / PNG / TIFF (LZW・ZIP・G4) / WebP / PSD 形式での保存
+
+use ::image::codecs::webp::WebPEncoder;
+use ::image::{DynamicImage, ExtendedColorType, ImageEncoder};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
+use tiff::encoder::colortype::{self, ColorType};
+use tiff::encoder::compression::DeflateLevel;
+use tiff::encoder::{Compression as TiffCompression, Rational, TiffEncoder, TiffValue};
+use tiff::tags::{CompressionMethod, PhotometricInterpretation, Predictor, ResolutionUnit, Tag, Type};
+
+use super::color::{is_gray_profile, is_rgb_profile};
+use super::image_loader::{COLOR_MODE_GRAYSCALE, COLOR_MODE_RGB};
+use super::image_processing::is_high_bit_depth;
+use super::jpeg::write_image_jpeg_to_file;
+use super::types::{JpegOptions, PngOptions, ProcessOptions, TiffOptions};
+
+/// 出力形式: JPEG（MozJPEG）
+pub const OUTPUT_FORMAT_JPEG: &str = "jpeg";
+/// 出力形式: PNG
+pub const OUTPUT_FORMAT_PNG: &str = "png";
+/// 出力形式: TIFF
+pub const OUTPUT_FORMAT_TIFF: &str = "tiff";
+/// 出力形式: WebP（ロスレス）
+pub const OUTPUT_FORMAT_WEBP: &str = "webp";
+/// 出力形式: PSD
+pub const OUTPUT_FORMAT_PSD: &str = "psd";
+
+/// PSDの最大幅・高さ（これを超える場合はPSBが必要）
+const PSD_MAX_DIMENSION: u32 = 30000;
+
+/// 出力形式の拡張子（出力サブフォルダ名にも使う）
+pub fn output_extension(format: &str) -> &'static str {
+    match format {
+        OUTPUT_FORMAT_PNG => "png",
+        OUTPUT_FORMAT_TIFF => "tif",
+        OUTPUT_FORMAT_WEBP => "webp",
+        OUTPUT_FORMAT_PSD => "psd",
+        _ => "jpg",
+    }
+}
+
+/// 出力形式の拡張子名のサブフォルダ（既存の場合は連番: jpg → jpg(1) → jpg(2) ...）
+pub fn output_subfolder(base: &Path, format: &str) -> PathBuf {
+    let extension = output_extension(format);
+    let format_path = base.join(extension);
+    if !format_path.exists() {
+        return format_path;
+    }
+    (1u32..)
+        .map(|counter| base.join(format!("{}({})", extension, counter)))
+        .find(|path| !path.exists())
+        .unwrap_or(format_path)
+}
+
+/// 処理済み画像を出力形式に従って保存
+/// ICCプロファイルは画像の色空間と一致する場合のみ埋め込む
+/// dpi は各形式の解像度メタデータ（JFIF / pHYs / XResolution / ResolutionInfo）に書き込む
+pub fn write_output_image(
+    img: &DynamicImage,
+    icc_profile: Option<&[u8]>,
+    options: &ProcessOptions,
+    dpi: u16,
+    path: &Path,
+) -> Result<(), String> {
+    let icc_profile = icc_profile.filter(|icc| {
+        if img.color().has_color() { is_rgb_profile(icc) } else { is_gray_profile(icc) }
+    });
+
+    match options.output_format.as_str() {
+        OUTPUT_FORMAT_PNG => write_png(img, icc_profile, &options.png, dpi, path),
+        OUTPUT_FORMAT_TIFF => write_tiff(img, icc_profile, &options.tiff, dpi, path),
+        OUTPUT_FORMAT_WEBP => write_webp(img, icc_profile, path),
+        OUTPUT_FORMAT_PSD => write_psd(img, icc_profile, dpi, path),
+        _ => write_image_jpeg_to_file(img, &JpegOptions { dpi, ..options.jpeg.clone() }, icc_profile, path),
+    }
+}
+
+/// 書き出し用にファイルを作成
+fn create_output_file(path: &Path) -> Result<BufWriter<File>, String> {
+    File::create(path)
+        .map(BufWriter::new)
+        .map_err(|e| format!("ファイル作成に失敗: {}", e))
+}
+
+/// 16bitサンプルをビッグエンディアンのバイト列に変換
+fn u16_to_be_bytes(samples: &[u16]) -> Vec<u8> {
+    samples.iter().flat_map(|v| v.to_be_bytes()).collect()
+}
+
+/// PNGで保存（16bitソースは16bitのまま、pHYsに解像度を書き込む）
+pub fn write_png(
+    img: &DynamicImage,
+    icc_profile: Option<&[u8]>,
+    options: &PngOptions,
+    dpi: u16,
+    path: &Path,
+) -> Result<(), String> {
+    let (width, height) = (img.width(), img.height());
+    let (color_type, bit_depth, data) = match (img.color().has_color(), is_high_bit_depth(img)) {
+        (true, true) => (png::ColorType::Rgb, png::BitDepth::Sixteen, u16_to_be_bytes(img.to_rgb16().as_raw())),
+        (true, false) => (png::ColorType::Rgb, png::BitDepth::Eight, img.to_rgb8().into_raw()),
+        (false, true) => (png::ColorType::Grayscale, png::BitDepth::Sixteen, u16_to_be_bytes(img.to_luma16().as_raw())),
+        (false, false) => (png::ColorType::Grayscale, png::BitDepth::Eight, img.to_luma8().into_raw()),
+    };
+
+    let mut info = png::Info::with_size(width, height);
+    info.color_type = color_type;
+    info.bit_depth = bit_depth;
+    info.icc_profile = icc_profile.map(Cow::Borrowed);
+    if dpi > 0 {
+        // pHYsはメートルあたりのピクセル数
+        let ppm = (dpi as f64 / 0.0254).round() as u32;
+        info.pixel_dims = Some(png::PixelDimensions { xppu: ppm, yppu: ppm, unit: png::Unit::Meter });
+    }
+
+    let mut encoder = png::Encoder::with_info(create_output_file(path)?, info)
+        .map_err(|e| format!("PNGエンコーダーの作成に失敗: {}", e))?;
+    encoder.set_compression(match options.compression.as_str() {
+        "fast" => png::Compression::Fast,
+        "best" => png::Compression::High,
+        _ => png::Compression::Balanced,
+    });
+
+    let mut writer = encoder.write_header()
+        .map_err(|e| format!("PNGヘッダーの書き込みに失敗: {}", e))?;
+    writer.write_image_data(&data)
+        .map_err(|e| format!("PNGの書き込みに失敗: {}", e))?;
+    writer.finish()
+        .map_err(|e| format!("PNGの書き込みに失敗: {}", e))?;
+    Ok(())
+}
+
+/// ロスレスWebPで保存（8bitのみ対応）
+pub fn write_webp(img: &DynamicImage, icc_profile: Option<&[u8]>, path: &Path) -> Result<(), String> {
+    let (width, height) = (img.width(), img.height());
+    let (color_type, data) = if img.color().has_color() {
+        (ExtendedColorType::Rgb8, img.to_rgb8().into_raw())
+    } else {
+        (ExtendedColorType::L8, img.to_luma8().into_raw())
+    };
+
+    let mut encoder = WebPEncoder::new_lossless(create_output_file(path)?);
+    if let Some(icc) = icc_profile {
+        encoder.set_icc_profile(icc.to_vec())
+            .map_err(|e| format!("WebPへのICCプロファイル埋め込みに失敗: {}", e))?;
+    }
+    encoder.write_image(&data, width, height, color_type)
+        .map_err(|e| format!("WebPの書き込みに失敗: {}", e))
+}
+
+/// TIFFのICCProfileタグ（UNDEFINED型で書き込む）
+struct TiffIccProfile<'a>(&'a [u8]);
+
+impl TiffValue for TiffIccProfile<'_> {
+    const BYTE_LEN: u8 = 1;
+    const FIELD_TYPE: Type = Type::UNDEFINED;
+
+    fn count(&self) -> usize {
+        self.0.len()
+    }
+
+    fn data(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.0)
+    }
+}
+
+/// TIFFで保存
+/// compression: "none", "lzw", "zip", "g4"（G4は閾値で2値化した1bit画像）
+pub fn write_tiff(
+    img: &DynamicImage,
+    icc_profile: Option<&[u8]>,
+    options: &TiffOptions,
+    dpi: u16,
+    path: &Path,
+) -> Result<(), String> {
+    let file = create_output_file(path)?;
+    if options.compression == "g4" {
+        return write_tiff_g4(img, options.threshold, dpi, file);
+    }
+
+    let (compression, predictor) = match options.compression.as_str() {
+        "none" => (TiffCompression::Uncompressed, Predictor::None),
+        "zip" => (TiffCompression::Deflate(DeflateLevel::Balanced), Predictor::Horizontal),
+        _ => (TiffCompression::Lzw, Predictor::Horizontal),
+    };
+    let mut encoder = TiffEncoder::new(file)
+        .map_err(|e| format!("TIFFエンコーダーの作成に失敗: {}", e))?
+        .with_compression(compression)
+        .with_predictor(predictor);
+
+    let (width, height) = (img.width(), img.height());
+    match (img.color().has_color(), is_high_bit_depth(img)) {
+        (true, true) => write_tiff_image::<colortype::RGB16, _>(&mut encoder, width, height, img.to_rgb16().as_raw(), icc_profile, dpi),
+        (true, false) => write_tiff_image::<colortype::RGB8, _>(&mut encoder, width, height, img.to_rgb8().as_raw(), icc_profile, dpi),
+        (false, true) => write_tiff_image::<colortype::Gray16, _>(&mut encoder, width, height, img.to_luma16().as_raw(), icc_profile, dpi),
+        (false, false) => write_tiff_image::<colortype::Gray8, _>(&mut encoder, width, height, img.to_luma8().as_raw(), icc_profile, dpi),
+    }
+}
+
+/// TIFFに1ページ分の画像を書き込む（解像度・ICCプロファイル付き）
+fn write_tiff_image<C: ColorType, W: Write + Seek>(
+    encoder: &mut TiffEncoder<W>,
+    width: u32,
+    height: u32,
+    data: &[C::Inner],
+    icc_profile: Option<&[u8]>,
+    dpi: u16,
+) -> Result<(), String>
+where
+    [C::Inner]: TiffValue,
+{
+    let mut image = encoder.new_image::<C>(width, height)
+        .map_err(|e| format!("TIFFの書き込みに失敗: {}", e))?;
+    if dpi > 0 {
+        image.resolution(ResolutionUnit::Inch, Rational { n: dpi as u32, d: 1 });
+    }
+    if let Some(icc) = icc_profile {
+        image.encoder().write_tag(Tag::IccProfile, TiffIccProfile(icc))
+            .map_err(|e| format!("TIFFへのICCプロファイル埋め込みに失敗: {}", e))?;
+    }
+    image.write_data(data)
+        .map_err(|e| format!("TIFFの書き込みに失敗: {}", e))
+}
+
+/// 閾値で2値化し、CCITT G4圧縮の1bit TIFFで保存（線画の入稿用）
+fn write_tiff_g4<W: Write + Seek>(img: &DynamicImage, threshold: u8, dpi: u16, writer: W) -> Result<(), String> {
+    let gray = img.to_luma8();
+    let (width, height) = gray.dimensions();
+    let line_width = u16::try_from(width)
+        .map_err(|_| format!("G4圧縮できる幅は{}pxまでです", u16::MAX))?;
+
+    // 閾値未満を黒として1行ずつ符号化
+    let mut g4 = fax::encoder::Encoder::new(fax::VecWriter::new());
+    for row in gray.rows() {
+        let pels = row.map(|p| if p[0] < threshold { fax::Color::Black } else { fax::Color::White });
+        g4.encode_line(pels, line_width)
+            .map_err(|e| format!("TIFFの書き込みに失敗: {}", e))?;
+    }
+    let data = g4.finish()
+        .map_err(|e| format!("TIFFの書き込みに失敗: {}", e))?
+        .finish();
+
+    let mut encoder = TiffEncoder::new(writer)
+        .map_err(|e| format!("TIFFエンコーダーの作成に失敗: {}", e))?;
+    let mut dir = encoder.image_directory()
+        .map_err(|e| format!("TIFFの書き込みに失敗: {}", e))?;
+    let result = (|| {
+        let strip_offset = dir.write_data(data.as_slice())?;
+        dir.write_tag(Tag::ImageWidth, width)?;
+        dir.write_tag(Tag::ImageLength, height)?;
+        dir.write_tag(Tag::BitsPerSample, 1u16)?;
+        dir.write_tag(Tag::Compression, CompressionMethod::Fax4.to_u16())?;
+        dir.write_tag(Tag::PhotometricInterpretation, PhotometricInterpretation::WhiteIsZero.to_u16())?;
+        dir.write_tag(Tag::StripOffsets, strip_offset as u32)?;
+        dir.write_tag(Tag::SamplesPerPixel, 1u16)?;
+        dir.write_tag(Tag::RowsPerStrip, height)?;
+        dir.write_tag(Tag::StripByteCounts, data.len() as u32)?;
+        if dpi > 0 {
+            dir.write_tag(Tag::XResolution, Rational { n: dpi as u32, d: 1 })?;
+            dir.write_tag(Tag::YResolution, Rational { n: dpi as u32, d: 1 })?;
+            dir.write_tag(Tag::ResolutionUnit, ResolutionUnit::Inch.to_u16())?;
+        }
+        dir.finish()
+    })();
+    result.map_err(|e| format!("TIFFの書き込みに失敗: {}", e))
+}
+
+/// PSDで保存（レイヤーなし・統合画像のみ、RLE圧縮）
+/// ResolutionInfo（1005）とICCプロファイル（1039）のリソースを書き込む
+pub fn write_psd(img: &DynamicImage, icc_profile: Option<&[u8]>, dpi: u16, path: &Path) -> Result<(), String> {
+    let (width, height) = (img.width(), img.height());
+    if width > PSD_MAX_DIMENSION || height > PSD_MAX_DIMENSION {
+        return Err(format!("PSDの最大サイズ（{}px）を超えています", PSD_MAX_DIMENSION));
+    }
+
+    let has_color = img.color().has_color();
+    let high_bit = is_high_bit_depth(img);
+    let (mode, channels) = if has_color { (COLOR_MODE_RGB, 3usize) } else { (COLOR_MODE_GRAYSCALE, 1usize) };
+    let depth: u16 = if high_bit { 16 } else { 8 };
+
+    // チャンネルごとのプレーン（ビッグエンディアンのバイト列）
+    let samples: Vec<u8> = match (has_color, high_bit) {
+        (true, true) => u16_to_be_bytes(img.to_rgb16().as_raw()),
+        (true, false) => img.to_rgb8().into_raw(),
+        (false, true) => u16_to_be_bytes(img.to_luma16().as_raw()),
+        (false, false) => img.to_luma8().into_raw(),
+    };
+    let bytes_per_sample = depth as usize / 8;
+    let pixel_bytes = channels * bytes_per_sample;
+    let row_bytes = width as usize * bytes_per_sample;
+
+    let mut out = Vec::new();
+
+    // ヘッダー
+    out.extend_from_slice(b"8BPS");
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&[0u8; 6]);
+    out.extend_from_slice(&(channels as u16).to_be_bytes());
+    out.extend_from_slice(&height.to_be_bytes());
+    out.extend_from_slice(&width.to_be_bytes());
+    out.extend_from_slice(&depth.to_be_bytes());
+    out.extend_from_slice(&mode.to_be_bytes());
+
+    // カラーモードデータ（なし）
+    out.extend_from_slice(&0u32.to_be_bytes());
+
+    // イメージリソース
+    let mut resources = Vec::new();
+    if dpi > 0 {
+        // hRes(16.16固定小数) + hResUnit(1=ppi) + widthUnit(1=inch) + vRes + vResUnit + heightUnit
+        let mut info = Vec::with_capacity(16);
+        for _ in 0..2 {
+            info.extend_from_slice(&((dpi as u32) << 16).to_be_bytes());
+            info.extend_from_slice(&1u16.to_be_bytes());
+            info.extend_from_slice(&1u16.to_be_bytes());
+        }
+        push_image_resource(&mut resources, 1005, &info);
+    }
+    if let Some(icc) = icc_profile {
+        push_image_resource(&mut resources, 1039, icc);
+    }
+    out.extend_from_slice(&(resources.len() as u32).to_be_bytes());
+    out.extend_from_slice(&resources);
+
+    // レイヤーとマスク情報（なし）
+    out.extend_from_slice(&0u32.to_be_bytes());
+
+    // 画像データ: RLE、全チャンネルの行バイト数 → チャンネルごとの圧縮データ
+    let mut row_lengths = Vec::with_capacity(channels * height as usize);
+    let mut compressed = Vec::new();
+    let mut plane_row = vec![0u8; row_bytes];
+    for ch in 0..channels {
+        let start = ch * bytes_per_sample;
+        for row in samples.chunks_exact(width as usize * pixel_bytes) {
+            for (dst, px) in plane_row.chunks_exact_mut(bytes_per_sample).zip(row.chunks_exact(pixel_bytes)) {
+                dst.copy_from_slice(&px[start..start + bytes_per_sample]);
+            }
+            let before = compressed.len();
+            encode_packbits(&plane_row, &mut compressed);
+            row_lengths.push((compressed.len() - before) as u16);
+        }
+    }
+    out.extend_from_slice(&1u16.to_be_bytes());
+    for len in &row_lengths {
+        out.extend_from_slice(&len.to_be_bytes());
+    }
+    out.extend_from_slice(&compressed);
+
+    let mut file = create_output_file(path)?;
+    file.write_all(&out)
+        .and_then(|_| file.flush())
+        .map_err(|e| format!("ファイル書き込みに失敗: {}", e))
+}
+
+/// イメージリソースブロックを追加（名前なし、データは偶数長にパディング）
+fn push_image_resource(resources: &mut Vec<u8>, id: u16, data: &[u8]) {
+    resources.extend_from_slice(b"8BIM");
+    resources.extend_from_slice(&id.to_be_bytes());
+    resources.extend_from_slice(&[0, 0]);
+    resources.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    resources.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        resources.push(0);
+    }
+}
+
+/// PackBits RLEエンコード
+pub fn encode_packbits(input: &[u8], output: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < input.len() {
+        // 同じ値の連続（最大128バイト）
+        let mut run = 1;
+        while i + run < input.len() && run < 128 && input[i + run] == input[i] {
+            run += 1;
+        }
+
+        if run >= 2 {
+            // 繰り返し: -(run-1) + 値
+            output.push((1 - run as i16) as u8);
+            output.push(input[i]);
+            i += run;
+        } else {
+            // リテラル: 3バイト以上の連続が始まるまで（最大128バイト）
+            let start = i;
+            while i < input.len() && i - start < 128 {
+                if i + 2 < input.len() && input[i] == input[i + 1] && input[i] == input[i + 2] {
+                    break;
+                }
+                i += 1;
+            }
+            output.push((i - start - 1) as u8);
+            output.extend_from_slice(&input[start..i]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::image_loader::{decode_packbits, load_image_with_profile};
+    use ::image::{ImageBuffer, Luma, Rgb};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("tachimi_writer_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_output_extension() {
+        assert_eq!(output_extension(OUTPUT_FORMAT_JPEG), "jpg");
+        assert_eq!(output_extension(OUTPUT_FORMAT_TIFF), "tif");
+        assert_eq!(output_extension(OUTPUT_FORMAT_PSD), "psd");
+        assert_eq!(output_extension("unknown"), "jpg");
+    }
+
+    #[test]
+    fn test_packbits_round_trip() {
+        let mut input = vec![7u8; 200];
+        input.extend((0..=255u8).cycle().take(300));
+        input.extend([1, 1, 2, 2, 2, 3]);
+
+        let mut encoded = Vec::new();
+        encode_packbits(&input, &mut encoded);
+        let mut decoded = vec![0u8; input.len()];
+        decode_packbits(&encoded, &mut decoded);
+        assert_eq!(decoded, input);
+    }
+
+    #[test]
+    fn test_psd_round_trip() {
+        let img = DynamicImage::ImageRgb8(ImageBuffer::from_fn(40, 30, |x, y| {
+            Rgb([(x * 6) as u8, (y * 8) as u8, if x < 20 { 0 } else { 255 }])
+        }));
+        let path = temp_path("round_trip.psd");
+        write_psd(&img, None, 350, &path).unwrap();
+
+        let (loaded, _) = load_image_with_profile(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(loaded.to_rgb8().as_raw(), img.to_rgb8().as_raw());
+    }
+
+    fn read_tiff(path: &Path) -> (tiff::decoder::DecodingResult, tiff::ColorType) {
+        let mut decoder = tiff::decoder::Decoder::new(File::open(path).unwrap()).unwrap();
+        let color_type = decoder.colortype().unwrap();
+        (decoder.read_image().unwrap(), color_type)
+    }
+
+    #[test]
+    fn test_tiff_lzw_zip_round_trip() {
+        let rgb = DynamicImage::ImageRgb8(ImageBuffer::from_fn(40, 30, |x, y| {
+            Rgb([(x * 6) as u8, (y * 8) as u8, ((x + y) % 3 * 100) as u8])
+        }));
+        let gray = DynamicImage::ImageLuma16(ImageBuffer::from_fn(40, 30, |x, y| Luma([(x * 1500 + y * 7) as u16])));
+
+        for compression in ["lzw", "zip"] {
+            let options = TiffOptions { compression: compression.to_string(), ..Default::default() };
+
+            let path = temp_path(&format!("rgb8_{}.tif", compression));
+            write_tiff(&rgb, None, &options, 350, &path).unwrap();
+            let (data, color_type) = read_tiff(&path);
+            std::fs::remove_file(&path).ok();
+            assert_eq!(color_type, tiff::ColorType::RGB(8));
+            match data {
+                tiff::decoder::DecodingResult::U8(data) => assert_eq!(data, *rgb.to_rgb8().as_raw()),
+                _ => panic!("8bitで読み込めません"),
+            }
+
+            let path = temp_path(&format!("gray16_{}.tif", compression));
+            write_tiff(&gray, None, &options, 350, &path).unwrap();
+            let (data, color_type) = read_tiff(&path);
+            std::fs::remove_file(&path).ok();
+            assert_eq!(color_type, tiff::ColorType::Gray(16));
+            match data {
+                tiff::decoder::DecodingResult::U16(data) => assert_eq!(data, *gray.to_luma16().as_raw()),
+                _ => panic!("16bitで読み込めません"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_tiff_g4_round_trip() {
+        let img = DynamicImage::ImageLuma8(ImageBuffer::from_fn(50, 20, |x, y| {
+            Luma([if (x / 5 + y / 4) % 2 == 0 { 30 } else { 220 }])
+        }));
+        let options = TiffOptions { compression: "g4".to_string(), threshold: 128 };
+        let path = temp_path("g4.tif");
+        write_tiff(&img, None, &options, 600, &path).unwrap();
+
+        // G4のストリップを取り出して復号する
+        let mut decoder = tiff::decoder::Decoder::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(decoder.get_tag_u32(Tag::Compression).unwrap(), CompressionMethod::Fax4.to_u16() as u32);
+        assert_eq!(decoder.get_tag_u32(Tag::BitsPerSample).unwrap(), 1);
+        let offset = decoder.get_tag_u32(Tag::StripOffsets).unwrap() as usize;
+        let length = decoder.get_tag_u32(Tag::StripByteCounts).unwrap() as usize;
+        let file = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let mut decoded = Vec::new();
+        fax::decoder::decode_g4(file[offset..offset + length].iter().copied(), 50, Some(20), |transitions| {
+            decoded.extend(fax::decoder::pels(transitions, 50).map(|c| c == fax::Color::Black));
+        })
+        .unwrap();
+        let expected: Vec<bool> = img.to_luma8().pixels().map(|p| p[0] < 128).collect();
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn test_output_subfolder_numbering() {
+        let base = temp_path("subfolder");
+        std::fs::create_dir_all(&base).unwrap();
+
+        assert_eq!(output_subfolder(&base, OUTPUT_FORMAT_JPEG), base.join("jpg"));
+        std::fs::create_dir(base.join("jpg")).unwrap();
+        assert_eq!(output_subfolder(&base, OUTPUT_FORMAT_JPEG), base.join("jpg(1)"));
+        std::fs::create_dir(base.join("jpg(1)")).unwrap();
+        assert_eq!(output_subfolder(&base, OUTPUT_FORMAT_JPEG), base.join("jpg(2)"));
+        assert_eq!(output_subfolder(&base, OUTPUT_FORMAT_TIFF), base.join("tif"));
+
+        std::fs::remove_dir_all(&base).ok();
+    }
+
+    #[test]
+    fn test_png_keeps_16bit_grayscale() {
+        let img = DynamicImage::ImageLuma16(ImageBuffer::from_fn(16, 16, |x, y| Luma([(x * 4000 + y) as u16])));
+        let path = temp_path("gray16.png");
+        write_png(&img, None, &PngOptions::default(), 600, &path).unwrap();
+
+        let (loaded, _) = load_image_with_profile(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(loaded.as_luma16().map(|b| b.as_raw()), img.as_luma16().map(|b| b.as_raw()));
+    }
+}
@@ -1,45 +1,153 @@
 //! タチミ - JPEGユーティリティモジュール
 //! JPEG エンコード/デコード関連の機能
 
-use mozjpeg::{Compress, ColorSpace as MozColorSpace};
+use ::image::DynamicImage;
+use mozjpeg::{Compress, ColorSpace as MozColorSpace, Marker, PixelDensity, PixelDensityUnit};
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 
-/// デフォルトJPEG品質
-pub const JPEG_QUALITY: f32 = 95.0;
+use super::color::{is_gray_profile, is_rgb_profile};
+use super::types::JpegOptions;
 
-/// MozJPEGでRGB画像をエンコード（高効率圧縮）
-pub fn encode_jpeg_mozjpeg(rgb_data: &[u8], width: u32, height: u32, quality: f32) -> Option<Vec<u8>> {
+/// ICCプロファイルをAPP2マーカー用のセグメントに分割
+/// 連番は1始まり（mozjpegのwrite_icc_profileは0始まりで書くため、他のデコーダーで読めない）
+fn icc_profile_segments(icc: &[u8]) -> Vec<Vec<u8>> {
+    const MAX_DATA_BYTES: usize = 65533 - 14;
+    let count = icc.len().div_ceil(MAX_DATA_BYTES);
+    icc.chunks(MAX_DATA_BYTES)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut segment = Vec::with_capacity(chunk.len() + 14);
+            segment.extend_from_slice(b"ICC_PROFILE\0");
+            segment.extend([(i + 1) as u8, count as u8]);
+            segment.extend_from_slice(chunk);
+            segment
+        })
+        .collect()
+}
+
+/// JPEG設定をエンコーダーに反映
+fn apply_jpeg_options(comp: &mut Compress, color_space: MozColorSpace, options: &JpegOptions) {
+    // mozjpegの既定（最大圧縮プロファイル）はプログレッシブ＋トレリス量子化
+    // ベースラインまたはトレリスなしの場合は高速プロファイルから組み立て直す
+    if !options.progressive || !options.trellis {
+        comp.set_fastest_defaults();
+        if options.progressive {
+            comp.set_progressive_mode();
+        }
+    }
+    comp.set_optimize_coding(options.optimize);
+    if options.progressive {
+        comp.set_optimize_scans(options.optimize);
+    }
+
+    comp.set_quality(options.quality.clamp(1, 100) as f32);
+
+    if color_space != MozColorSpace::JCS_GRAYSCALE {
+        match options.subsampling.as_str() {
+            "444" => comp.set_chroma_sampling_pixel_sizes((1, 1), (1, 1)),
+            _ => comp.set_chroma_sampling_pixel_sizes((2, 2), (2, 2)),
+        }
+    }
+
+    if options.dpi > 0 {
+        comp.set_pixel_density(PixelDensity {
+            unit: PixelDensityUnit::Inches,
+            x: options.dpi,
+            y: options.dpi,
+        });
+    }
+}
+
+/// MozJPEGでエンコード（高効率圧縮）
+/// icc_profileを指定するとAPP2マーカーとして埋め込む
+fn encode_jpeg_with_color_space(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    color_space: MozColorSpace,
+    options: &JpegOptions,
+    icc_profile: Option<&[u8]>,
+) -> Option<Vec<u8>> {
     std::panic::catch_unwind(|| {
-        let mut comp = Compress::new(MozColorSpace::JCS_RGB);
+        let mut comp = Compress::new(color_space);
         comp.set_size(width as usize, height as usize);
-        comp.set_quality(quality);
+        apply_jpeg_options(&mut comp, color_space, options);
 
         // 圧縮開始（Vec<u8>に出力）
         let mut writer = comp.start_compress(Vec::new()).ok()?;
 
+        // ICCプロファイル（64KBを超える場合は複数のAPP2に分割）
+        if let Some(icc) = icc_profile {
+            for segment in icc_profile_segments(icc) {
+                writer.write_marker(Marker::APP(2), &segment);
+            }
+        }
+
         // 全スキャンラインを書き込み
-        writer.write_scanlines(rgb_data).ok()?;
+        writer.write_scanlines(pixels).ok()?;
 
         writer.finish().ok()
     }).ok().flatten()
 }
 
+/// MozJPEGでRGB画像をエンコード（高効率圧縮）
+/// icc_profileを指定するとAPP2マーカーとして埋め込む
+pub fn encode_jpeg_mozjpeg(rgb_data: &[u8], width: u32, height: u32, options: &JpegOptions, icc_profile: Option<&[u8]>) -> Option<Vec<u8>> {
+    encode_jpeg_with_color_space(rgb_data, width, height, MozColorSpace::JCS_RGB, options, icc_profile)
+}
+
+/// MozJPEGでグレースケール画像を1チャンネルJPEGにエンコード
+pub fn encode_jpeg_mozjpeg_gray(gray_data: &[u8], width: u32, height: u32, options: &JpegOptions, icc_profile: Option<&[u8]>) -> Option<Vec<u8>> {
+    encode_jpeg_with_color_space(gray_data, width, height, MozColorSpace::JCS_GRAYSCALE, options, icc_profile)
+}
+
+/// 画像をJPEGにエンコード（グレースケール画像は1チャンネル、それ以外はRGB）
+/// ICCプロファイルは画像の色空間と一致する場合のみ埋め込む
+pub fn encode_image_jpeg(img: &DynamicImage, options: &JpegOptions, icc_profile: Option<&[u8]>) -> Option<Vec<u8>> {
+    if img.color().has_color() {
+        let rgb = img.to_rgb8();
+        let icc_profile = icc_profile.filter(|icc| is_rgb_profile(icc));
+        encode_jpeg_mozjpeg(rgb.as_raw(), rgb.width(), rgb.height(), options, icc_profile)
+    } else {
+        let gray = img.to_luma8();
+        let icc_profile = icc_profile.filter(|icc| is_gray_profile(icc));
+        encode_jpeg_mozjpeg_gray(gray.as_raw(), gray.width(), gray.height(), options, icc_profile)
+    }
+}
+
+/// JPEGデータをファイルに書き出し
+fn write_jpeg_data<P: AsRef<Path>>(jpeg_data: &[u8], path: P) -> Result<(), String> {
+    let mut file = File::create(path).map_err(|e| format!("ファイル作成に失敗: {}", e))?;
+    file.write_all(jpeg_data).map_err(|e| format!("ファイル書き込みに失敗: {}", e))?;
+    Ok(())
+}
+
 /// MozJPEGでRGB画像をファイルに書き出し
 pub fn write_jpeg_mozjpeg_to_file<P: AsRef<Path>>(
     rgb_data: &[u8],
     width: u32,
     height: u32,
-    quality: f32,
+    options: &JpegOptions,
+    icc_profile: Option<&[u8]>,
     path: P
 ) -> Result<(), String> {
-    let jpeg_data = encode_jpeg_mozjpeg(rgb_data, width, height, quality)
+    let jpeg_data = encode_jpeg_mozjpeg(rgb_data, width, height, options, icc_profile)
         .ok_or("MozJPEGエンコードに失敗")?;
+    write_jpeg_data(&jpeg_data, path)
+}
 
-    let mut file = File::create(path).map_err(|e| format!("ファイル作成に失敗: {}", e))?;
-    file.write_all(&jpeg_data).map_err(|e| format!("ファイル書き込みに失敗: {}", e))?;
-    Ok(())
+/// 画像をJPEGファイルに書き出し（グレースケール画像は1チャンネルJPEG）
+pub fn write_image_jpeg_to_file<P: AsRef<Path>>(
+    img: &DynamicImage,
+    options: &JpegOptions,
+    icc_profile: Option<&[u8]>,
+    path: P
+) -> Result<(), String> {
+    let jpeg_data = encode_image_jpeg(img, options, icc_profile)
+        .ok_or("MozJPEGエンコードに失敗")?;
+    write_jpeg_data(&jpeg_data, path)
 }
 
 /// ファイルがJPEGかどうか判定
@@ -53,6 +161,11 @@ pub fn is_jpeg_file(path: &Path) -> bool {
 
 /// JPEGファイルのサイズを高速取得（デコード不要）
 pub fn get_jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    get_jpeg_frame_info(data).map(|(width, height, _)| (width, height))
+}
+
+/// JPEGファイルのサイズと色成分数（1=グレー, 3=YCbCr/RGB, 4=CMYK）を高速取得
+pub fn get_jpeg_frame_info(data: &[u8]) -> Option<(u32, u32, u8)> {
     // JPEGシグネチャチェック
     if data.len() < 2 || data[0] != 0xFF || data[1] != 0xD8 {
         return None;
@@ -72,10 +185,11 @@ pub fn get_jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
         // SOF0-SOF3, SOF5-SOF7, SOF9-SOF11, SOF13-SOF15
         if matches!(marker, 0xC0..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF) {
             if i + 9 < data.len() {
-                // SOFセグメント: FF Cn [length 2bytes] [precision 1byte] [height 2bytes] [width 2bytes]
+                // SOFセグメント: FF Cn [length 2bytes] [precision 1byte] [height 2bytes] [width 2bytes] [components 1byte]
                 let height = ((data[i + 5] as u32) << 8) | (data[i + 6] as u32);
                 let width = ((data[i + 7] as u32) << 8) | (data[i + 8] as u32);
-                return Some((width, height));
+                let components = data[i + 9];
+                return Some((width, height, components));
             }
         }
 
@@ -108,4 +222,94 @@ mod tests {
         assert!(!is_jpeg_file(Path::new("test.png")));
         assert!(!is_jpeg_file(Path::new("test.psd")));
     }
+
+    /// 指定マーカーのセグメント本体を取得（SOSまで探索）
+    fn find_segment(data: &[u8], marker: u8) -> Option<&[u8]> {
+        let mut i = 2;
+        while i + 4 <= data.len() && data[i] == 0xFF {
+            let len = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+            if data[i + 1] == marker {
+                return data.get(i + 4..i + 2 + len);
+            }
+            if data[i + 1] == 0xDA {
+                break;
+            }
+            i += 2 + len;
+        }
+        None
+    }
+
+    fn test_image() -> DynamicImage {
+        DynamicImage::ImageRgb8(::image::RgbImage::from_fn(64, 48, |x, y| {
+            ::image::Rgb([(x * 4) as u8, (y * 5) as u8, ((x * y) % 256) as u8])
+        }))
+    }
+
+    #[test]
+    fn test_grayscale_image_encodes_single_component() {
+        let options = JpegOptions::default();
+        let gray = DynamicImage::ImageLuma8(::image::GrayImage::from_pixel(16, 8, ::image::Luma([128])));
+        let data = encode_image_jpeg(&gray, &options, None).unwrap();
+        assert_eq!(get_jpeg_frame_info(&data), Some((16, 8, 1)));
+
+        let rgb = DynamicImage::ImageRgb8(::image::RgbImage::from_pixel(16, 8, ::image::Rgb([0, 255, 255])));
+        let data = encode_image_jpeg(&rgb, &options, None).unwrap();
+        assert_eq!(get_jpeg_frame_info(&data), Some((16, 8, 3)));
+    }
+
+    #[test]
+    fn test_default_options_write_density_and_progressive_420() {
+        let data = encode_image_jpeg(&test_image(), &JpegOptions::default(), None).unwrap();
+
+        // APP0: "JFIF\0" + バージョン(2) + 単位(1) + X密度(2) + Y密度(2)
+        let jfif = find_segment(&data, 0xE0).unwrap();
+        assert_eq!(&jfif[..5], b"JFIF\0");
+        assert_eq!(jfif[7], 1);
+        assert_eq!(u16::from_be_bytes([jfif[8], jfif[9]]), 350);
+        assert_eq!(u16::from_be_bytes([jfif[10], jfif[11]]), 350);
+
+        // SOF2（プログレッシブ）、輝度成分のサンプリング係数 2x2 = 4:2:0
+        let sof = find_segment(&data, 0xC2).unwrap();
+        assert!(find_segment(&data, 0xC0).is_none());
+        assert_eq!(sof[5], 3);
+        assert_eq!(sof[7], 0x22);
+        assert_eq!(sof[10], 0x11);
+    }
+
+    #[test]
+    fn test_baseline_444_with_custom_dpi() {
+        let options = JpegOptions {
+            subsampling: "444".to_string(),
+            progressive: false,
+            dpi: 600,
+            ..JpegOptions::default()
+        };
+        let data = encode_image_jpeg(&test_image(), &options, None).unwrap();
+
+        let jfif = find_segment(&data, 0xE0).unwrap();
+        assert_eq!(u16::from_be_bytes([jfif[8], jfif[9]]), 600);
+
+        // SOF0（ベースライン）、全成分 1x1 = 4:4:4
+        let sof = find_segment(&data, 0xC0).unwrap();
+        assert!(find_segment(&data, 0xC2).is_none());
+        assert_eq!([sof[7], sof[10], sof[13]], [0x11, 0x11, 0x11]);
+    }
+
+    #[test]
+    fn test_quality_changes_quantization() {
+        let high = encode_image_jpeg(&test_image(), &JpegOptions { quality: 95, ..JpegOptions::default() }, None).unwrap();
+        let low = encode_image_jpeg(&test_image(), &JpegOptions { quality: 30, ..JpegOptions::default() }, None).unwrap();
+
+        // DQT: 精度/ID(1) + 64係数（精度0なら8bit、1なら16bit）。低品質ほど量子化係数が大きい
+        let luma_table_sum = |data: &[u8]| -> u32 {
+            let dqt = find_segment(data, 0xDB).unwrap();
+            if dqt[0] >> 4 == 0 {
+                dqt[1..65].iter().map(|&v| v as u32).sum()
+            } else {
+                dqt[1..129].chunks_exact(2).map(|v| u16::from_be_bytes([v[0], v[1]]) as u32).sum()
+            }
+        };
+        assert!(luma_table_sum(&low) > luma_table_sum(&high));
+        assert!(low.len() < high.len());
+    }
 }
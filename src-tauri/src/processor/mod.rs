@@ -3,15 +3,30 @@
 
 pub mod types;
 pub mod cache;
+pub mod fonts;
+pub mod text_layout;
 pub mod jpeg;
+pub mod color;
 pub mod image_loader;
+pub mod psd_layers;
 pub mod image_processing;
+pub mod image_writer;
+pub mod naming;
+pub mod colophon;
+pub mod resolution;
+pub mod trim_marks;
+pub mod guide_crop;
+pub mod crop_detection;
+pub mod alignment;
+pub mod page_side;
+pub mod nombre;
 pub mod pdf;
 
 // 型のre-export
 pub use types::{
-    ImageInfo, PreviewFileInfo, ProcessOptions, ProcessResult,
-    PdfOptions,
+    ImageInfo, PageReport, PreviewFileInfo, ProcessOptions, ProcessResult,
+    PdfOptions, PdfResult, LayerFilter, JpegOptions, PngOptions, TiffOptions, TrimMarkOptions, FrameRect, FrameStyle, AlignOptions,
+    PageSideOptions, PdfPageSideOptions, NombreOptions, FontSpec, ColophonInfo, PdfChapter,
 };
 
 // キャッシュ関連のre-export
@@ -26,7 +41,7 @@ use base64::{engine::general_purpose::STANDARD, Engine};
 use std::path::Path;
 
 use jpeg::{encode_jpeg_mozjpeg, write_jpeg_mozjpeg_to_file};
-use pdf::{generate_single_pdf, generate_spread_pdf, DEFAULT_DPI};
+use pdf::{generate_single_pdf, generate_spread_pdf, PdfLayout};
 
 /// 画像のプレビューを取得（Base64）
 pub fn get_image_preview(file_path: &str, max_size: u32) -> Result<ImageInfo, String> {
@@ -37,8 +52,8 @@ pub fn get_image_preview(file_path: &str, max_size: u32) -> Result<ImageInfo, St
         .unwrap_or("")
         .to_lowercase();
 
-    // PSDの場合はキャッシュを使用
-    let (img, orig_width, orig_height) = if ext == "psd" {
+    // PSD/PSBの場合はキャッシュを使用
+    let (img, orig_width, orig_height) = if ext == "psd" || ext == "psb" {
         let img = get_or_cache_psd(path)?;
         let (w, h) = img.dimensions();
         (img, w, h)
@@ -58,7 +73,7 @@ pub fn get_image_preview(file_path: &str, max_size: u32) -> Result<ImageInfo, St
 
     // MozJPEGでエンコード
     let rgb_img = resized.to_rgb8();
-    let jpeg_data = encode_jpeg_mozjpeg(rgb_img.as_raw(), rgb_img.width(), rgb_img.height(), 100.0)
+    let jpeg_data = encode_jpeg_mozjpeg(rgb_img.as_raw(), rgb_img.width(), rgb_img.height(), &JpegOptions { quality: 100, ..JpegOptions::default() }, None)
         .ok_or("MozJPEGエンコードに失敗")?;
 
     let base64_str = STANDARD.encode(&jpeg_data);
@@ -71,7 +86,13 @@ pub fn get_image_preview(file_path: &str, max_size: u32) -> Result<ImageInfo, St
 }
 
 /// 画像のプレビューをファイルに保存（高速化版）
-pub fn get_image_preview_file(file_path: &str, max_size: u32, temp_dir: &str) -> Result<PreviewFileInfo, String> {
+/// layer_filterを指定した場合、PSD/PSBは表示ルールに従ってレイヤーを合成する（キャッシュは使わない）
+pub fn get_image_preview_file(
+    file_path: &str,
+    max_size: u32,
+    temp_dir: &str,
+    layer_filter: Option<&LayerFilter>,
+) -> Result<PreviewFileInfo, String> {
     let path = Path::new(file_path);
     let ext = path
         .extension()
@@ -79,15 +100,23 @@ pub fn get_image_preview_file(file_path: &str, max_size: u32, temp_dir: &str) ->
         .unwrap_or("")
         .to_lowercase();
 
-    // PSDの場合はキャッシュを使用
-    let (img, orig_width, orig_height) = if ext == "psd" {
-        let img = get_or_cache_psd(path)?;
-        let (w, h) = img.dimensions();
-        (img, w, h)
-    } else {
-        let img = ::image::open(path).map_err(|e| format!("画像の読み込みに失敗: {}", e))?;
-        let (w, h) = img.dimensions();
-        (img, w, h)
+    // PSD/PSBの場合はキャッシュを使用（レイヤー表示ルール指定時は都度合成）
+    let (img, orig_width, orig_height) = match layer_filter {
+        Some(filter) if ext == "psd" || ext == "psb" => {
+            let img = psd_layers::render_psd_layers(path, filter)?;
+            let (w, h) = img.dimensions();
+            (img, w, h)
+        }
+        _ if ext == "psd" || ext == "psb" => {
+            let img = get_or_cache_psd(path)?;
+            let (w, h) = img.dimensions();
+            (img, w, h)
+        }
+        _ => {
+            let img = ::image::open(path).map_err(|e| format!("画像の読み込みに失敗: {}", e))?;
+            let (w, h) = img.dimensions();
+            (img, w, h)
+        }
     };
 
     // リサイズ
@@ -106,7 +135,7 @@ pub fn get_image_preview_file(file_path: &str, max_size: u32, temp_dir: &str) ->
 
     // MozJPEGでファイルに保存
     let rgb_img = resized.to_rgb8();
-    write_jpeg_mozjpeg_to_file(rgb_img.as_raw(), rgb_img.width(), rgb_img.height(), 80.0, &temp_file_path)?;
+    write_jpeg_mozjpeg_to_file(rgb_img.as_raw(), rgb_img.width(), rgb_img.height(), &JpegOptions { quality: 80, ..JpegOptions::default() }, None, &temp_file_path)?;
 
     Ok(PreviewFileInfo {
         width: orig_width,
@@ -122,39 +151,15 @@ pub fn generate_pdf(
     output_path: &str,
     files: &[String],
     options: &PdfOptions,
-) -> Result<String, String> {
+) -> Result<PdfResult, String> {
     if files.is_empty() {
         return Err("処理するファイルがありません".to_string());
     }
 
-    let dpi = DEFAULT_DPI;
-    let px_to_mm_ratio = 25.4 / dpi;
-    let padding_mm = options.padding as f32 * px_to_mm_ratio;
-    let gutter_mm = options.gutter as f32 * px_to_mm_ratio;
-
+    let layout = PdfLayout::new(options, &Path::new(input_folder).join(&files[0]));
     if options.is_spread {
-        generate_spread_pdf(
-            app_handle,
-            input_folder,
-            output_path,
-            files,
-            padding_mm,
-            gutter_mm,
-            options.add_white_page,
-            options.print_work_info,
-            options.work_info.as_ref(),
-            options.add_nombre,
-            &options.nombre_size,
-        )
+        generate_spread_pdf(app_handle, input_folder, output_path, files, options, &layout)
     } else {
-        generate_single_pdf(
-            app_handle,
-            input_folder,
-            output_path,
-            files,
-            padding_mm,
-            options.add_nombre,
-            &options.nombre_size,
-        )
+        generate_single_pdf(app_handle, input_folder, output_path, files, options, &layout)
     }
 }
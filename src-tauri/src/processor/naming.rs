@@ -0,0 +1,219 @@
+//! タチミ - 出力ファイル名テンプレート
+//! 例: "{title}_{volume}_{page:03}" → "作品名_1_005.jpg"
+//!
+//! 使えるプレースホルダー:
+//! - {page}: ノンブルのページ番号（開始番号 + インデックス）
+//! - {index}: 処理順の番号（1始まり）
+//! - {stem}: 元ファイル名（拡張子なし）
+//! - {title}, {subtitle}, {volume}, {label}, {author}, {author2}: 作品情報
+//! - {date}: 処理日（YYYYMMDD）
+//!
+//! 数値（page, index）は {page:03} のようにゼロ埋め桁数を指定できる。
+//! 波括弧そのものは {{ と }} で書く。
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::types::WorkInfo;
+
+/// ファイル名に使えない文字（Windows基準）
+const INVALID_FILENAME_CHARS: &[char] = &['\\', '/', ':', '*', '?', '"', '<', '>', '|'];
+
+/// テンプレートに埋め込む日付
+#[derive(Debug, Clone, Copy)]
+pub struct NamingDate {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+/// 1ページ分のテンプレート展開に使う値
+pub struct NamingContext<'a> {
+    pub page: u32,
+    pub index: usize,
+    pub stem: &'a str,
+    pub work_info: Option<&'a WorkInfo>,
+    pub date: NamingDate,
+}
+
+/// テンプレートを展開して拡張子なしのファイル名を返す
+pub fn render_output_name(template: &str, ctx: &NamingContext) -> Result<String, String> {
+    let mut name = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                name.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                name.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return Err(format!("命名テンプレートの '{{' が閉じられていません: {}", template)),
+                    }
+                }
+                name.push_str(&expand_placeholder(&placeholder, ctx)?);
+            }
+            '}' => return Err(format!("命名テンプレートに対応しない '}}' があります: {}", template)),
+            _ => name.push(c),
+        }
+    }
+
+    let name = sanitize_filename(&name);
+    if name.trim().is_empty() {
+        return Err(format!("命名テンプレートの結果が空になります: {}", template));
+    }
+    Ok(name)
+}
+
+/// プレースホルダー1つを展開（"page:03" のようにゼロ埋め指定を含む）
+fn expand_placeholder(placeholder: &str, ctx: &NamingContext) -> Result<String, String> {
+    let (key, width) = match placeholder.split_once(':') {
+        Some((key, spec)) => {
+            let width = spec.parse::<usize>()
+                .map_err(|_| format!("命名テンプレートの桁数指定が不正です: {{{}}}", placeholder))?;
+            (key, Some(width))
+        }
+        None => (placeholder, None),
+    };
+
+    let number = match key {
+        "page" => Some(ctx.page as usize),
+        "index" => Some(ctx.index),
+        _ => None,
+    };
+    if let Some(number) = number {
+        return Ok(format!("{:0width$}", number, width = width.unwrap_or(0)));
+    }
+    if width.is_some() {
+        return Err(format!("桁数指定は page と index のみ使えます: {{{}}}", placeholder));
+    }
+
+    let work_info = ctx.work_info;
+    let field = |f: fn(&WorkInfo) -> &String| work_info.map(|w| f(w).clone()).unwrap_or_default();
+    match key {
+        "stem" => Ok(ctx.stem.to_string()),
+        "title" => Ok(field(|w| &w.title)),
+        "subtitle" => Ok(field(|w| &w.subtitle)),
+        "volume" => Ok(field(|w| &w.version)),
+        "label" => Ok(field(|w| &w.label)),
+        "author" => Ok(field(|w| &w.author1)),
+        "author2" => Ok(field(|w| &w.author2)),
+        "date" => Ok(format!("{:04}{:02}{:02}", ctx.date.year, ctx.date.month, ctx.date.day)),
+        _ => Err(format!("命名テンプレートに不明なプレースホルダーがあります: {{{}}}", placeholder)),
+    }
+}
+
+/// ファイル名に使えない文字を "_" に置き換える
+fn sanitize_filename(name: &str) -> String {
+    name.chars()
+        .map(|c| if INVALID_FILENAME_CHARS.contains(&c) || c.is_control() { '_' } else { c })
+        .collect()
+}
+
+/// 全ファイルの出力ファイル名（拡張子付き）を入力順に生成
+/// page_start: 先頭ファイルのページ番号（ノンブル開始番号）
+pub fn build_output_names(
+    files: &[String],
+    template: &str,
+    extension: &str,
+    page_start: u32,
+    work_info: Option<&WorkInfo>,
+    date: NamingDate,
+) -> Result<Vec<String>, String> {
+    files
+        .iter()
+        .enumerate()
+        .map(|(index, filename)| {
+            let stem = Path::new(filename)
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .ok_or_else(|| format!("{}: 無効なファイル名", filename))?;
+            let ctx = NamingContext {
+                page: page_start + index as u32,
+                index: index + 1,
+                stem,
+                work_info,
+                date,
+            };
+            render_output_name(template, &ctx).map(|name| format!("{}.{}", name, extension))
+        })
+        .collect()
+}
+
+/// 出力ファイル名の重複を検出してエラーメッセージを返す
+/// Windows/macOSで上書きにならないよう大文字小文字は区別しない
+pub fn find_name_collisions(files: &[String], output_names: &[String]) -> Vec<String> {
+    let mut sources: HashMap<String, Vec<&str>> = HashMap::new();
+    let mut order = Vec::new();
+    for (filename, output_name) in files.iter().zip(output_names) {
+        let key = output_name.to_lowercase();
+        let entry = sources.entry(key.clone()).or_default();
+        if entry.is_empty() {
+            order.push((key, output_name));
+        }
+        entry.push(filename);
+    }
+
+    order
+        .into_iter()
+        .filter_map(|(key, output_name)| {
+            let sources = &sources[&key];
+            (sources.len() > 1).then(|| {
+                format!("出力ファイル名が重複しています: {} ({})", output_name, sources.join(", "))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATE: NamingDate = NamingDate { year: 2024, month: 3, day: 9 };
+
+    fn work_info() -> WorkInfo {
+        WorkInfo {
+            title: "作品/名".to_string(),
+            version: "2".to_string(),
+            ..WorkInfo::default()
+        }
+    }
+
+    #[test]
+    fn test_render_output_name() {
+        let info = work_info();
+        let ctx = NamingContext { page: 5, index: 3, stem: "p005", work_info: Some(&info), date: DATE };
+        assert_eq!(render_output_name("{title}_{volume}_{page:03}", &ctx).unwrap(), "作品_名_2_005");
+        assert_eq!(render_output_name("{stem}-{index}-{date}", &ctx).unwrap(), "p005-3-20240309");
+        assert_eq!(render_output_name("{{{page}}}", &ctx).unwrap(), "{5}");
+        assert!(render_output_name("{unknown}", &ctx).is_err());
+        assert!(render_output_name("{stem:03}", &ctx).is_err());
+        assert!(render_output_name("{page", &ctx).is_err());
+    }
+
+    #[test]
+    fn test_name_collisions() {
+        let files = vec!["a.psd".to_string(), "b.psd".to_string(), "c.psd".to_string()];
+        let names = build_output_names(&files, "{title}", "jpg", 1, None, DATE);
+        assert!(names.is_err());
+
+        let names = build_output_names(&files, "{page:02}", "jpg", 1, None, DATE).unwrap();
+        assert_eq!(names, vec!["01.jpg", "02.jpg", "03.jpg"]);
+        assert!(find_name_collisions(&files, &names).is_empty());
+
+        let names = vec!["X.jpg".to_string(), "y.jpg".to_string(), "x.jpg".to_string()];
+        assert_eq!(
+            find_name_collisions(&files, &names),
+            vec!["出力ファイル名が重複しています: X.jpg (a.psd, c.psd)"]
+        );
+    }
+}
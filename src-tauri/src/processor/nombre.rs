@@ -0,0 +1,121 @@
+//! タチミ - ノンブルの表記と寄せる側
+//! 数字の表記（算用数字・ゼロ埋め・漢数字・ローマ数字）に前後の文字列を付け、
+//! 通常は左右ページの設定、隠しノンブルはノド側に寄せる
+
+use super::page_side::{nombre_edge, PageSide};
+use super::types::NombreOptions;
+
+/// 数字の表記
+pub const NUMERAL_ARABIC: &str = "arabic";
+pub const NUMERAL_PADDED: &str = "padded";
+pub const NUMERAL_KANJI: &str = "kanji";
+pub const NUMERAL_ROMAN: &str = "roman";
+
+/// ノンブルの縦位置: 上（それ以外は下）
+pub const NOMBRE_VERTICAL_TOP: &str = "top";
+
+/// 数字を表記に従って文字列にする（不明な表記・ローマ数字で表せない数は算用数字）
+pub fn format_numeral(number: u32, style: &str, pad_width: u32) -> String {
+    match style {
+        NUMERAL_PADDED => format!("{:0width$}", number, width = pad_width.min(10) as usize),
+        NUMERAL_KANJI => kanji_numeral(number),
+        NUMERAL_ROMAN => roman_numeral(number).unwrap_or_else(|| number.to_string()),
+        _ => number.to_string(),
+    }
+}
+
+/// 漢数字（十・百・千・万の位取り、先頭の「一」は省く）
+fn kanji_numeral(number: u32) -> String {
+    const DIGITS: [&str; 10] = ["〇", "一", "二", "三", "四", "五", "六", "七", "八", "九"];
+    if number == 0 {
+        return DIGITS[0].to_string();
+    }
+    if number >= 10_000 {
+        let rest = number % 10_000;
+        let upper = format!("{}万", kanji_numeral(number / 10_000));
+        return if rest == 0 { upper } else { upper + &kanji_numeral(rest) };
+    }
+
+    let mut text = String::new();
+    for (unit, name) in [(1000, "千"), (100, "百"), (10, "十")] {
+        let digit = (number / unit % 10) as usize;
+        if digit > 1 {
+            text.push_str(DIGITS[digit]);
+        }
+        if digit > 0 {
+            text.push_str(name);
+        }
+    }
+    let ones = (number % 10) as usize;
+    if ones > 0 {
+        text.push_str(DIGITS[ones]);
+    }
+    text
+}
+
+/// ローマ数字（1〜3999）
+fn roman_numeral(number: u32) -> Option<String> {
+    const TABLE: [(u32, &str); 13] = [
+        (1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+        (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+    ];
+    if number == 0 || number > 3999 {
+        return None;
+    }
+    let mut rest = number;
+    let mut text = String::new();
+    for (value, symbol) in TABLE {
+        while rest >= value {
+            text.push_str(symbol);
+            rest -= value;
+        }
+    }
+    Some(text)
+}
+
+/// ページ番号から印字する文字列を作る
+pub fn nombre_label(number: u32, options: &NombreOptions) -> String {
+    format!(
+        "{}{}{}",
+        options.prefix,
+        format_numeral(number, &options.numeral_style, options.pad_width),
+        options.suffix
+    )
+}
+
+/// どのページ番号でもASCIIのみになるか（欧文フォントで描けるか）
+pub fn nombre_is_ascii(options: &NombreOptions) -> bool {
+    options.numeral_style != NUMERAL_KANJI && options.prefix.is_ascii() && options.suffix.is_ascii()
+}
+
+/// ノンブルを寄せる側（None は中央）
+/// 隠しノンブルは常にノド側、それ以外は左右ページの横位置（"center", "outer", "inner"）に従う
+pub fn nombre_placement_edge(options: &NombreOptions, side: PageSide, position: &str) -> Option<PageSide> {
+    if options.hidden {
+        Some(side.opposite())
+    } else {
+        nombre_edge(side, position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_numeral() {
+        assert_eq!(format_numeral(7, NUMERAL_ARABIC, 3), "7");
+        assert_eq!(format_numeral(7, NUMERAL_PADDED, 3), "007");
+        assert_eq!(format_numeral(1234, NUMERAL_PADDED, 3), "1234");
+        assert_eq!(format_numeral(10, NUMERAL_KANJI, 0), "十");
+        assert_eq!(format_numeral(215, NUMERAL_KANJI, 0), "二百十五");
+        assert_eq!(format_numeral(1001, NUMERAL_KANJI, 0), "千一");
+        assert_eq!(format_numeral(20_000, NUMERAL_KANJI, 0), "二万");
+        assert_eq!(format_numeral(14, NUMERAL_ROMAN, 0), "XIV");
+        assert_eq!(format_numeral(1994, NUMERAL_ROMAN, 0), "MCMXCIV");
+        assert_eq!(format_numeral(0, NUMERAL_ROMAN, 0), "0");
+
+        let options = NombreOptions { prefix: "- ".to_string(), suffix: " -".to_string(), ..NombreOptions::default() };
+        assert_eq!(nombre_label(3, &options), "- 3 -");
+    }
+}
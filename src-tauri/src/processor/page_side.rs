@@ -0,0 +1,72 @@
+//! タチミ - 左右ページ（奇数・偶数ページ）の判定
+//! 右綴じ（縦書き・マンガ）では奇数ページが左ページ、左綴じ（横書き）では奇数ページが右ページになる
+//! 小口（外側）・ノド（内側）は左ページなら左・右、右ページなら右・左
+
+/// 綴じ方向
+pub const BINDING_RIGHT: &str = "right";
+pub const BINDING_LEFT: &str = "left";
+
+/// ノンブルの横位置
+pub const NOMBRE_POSITION_CENTER: &str = "center";
+pub const NOMBRE_POSITION_OUTER: &str = "outer";
+pub const NOMBRE_POSITION_INNER: &str = "inner";
+
+/// 見開きでの左右
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSide {
+    Left,
+    Right,
+}
+
+impl PageSide {
+    /// 反対側
+    pub fn opposite(self) -> Self {
+        match self {
+            PageSide::Left => PageSide::Right,
+            PageSide::Right => PageSide::Left,
+        }
+    }
+}
+
+/// ページ番号と綴じ方向から左右を判定（不明な綴じ方向は右綴じとして扱う）
+pub fn page_side(page_number: u32, binding: &str) -> PageSide {
+    let odd = page_number % 2 == 1;
+    match (binding == BINDING_LEFT, odd) {
+        (true, true) | (false, false) => PageSide::Right,
+        (true, false) | (false, true) => PageSide::Left,
+    }
+}
+
+/// 見開きで読む順が先になるページの側（右綴じは右、左綴じは左、不明な綴じ方向は右綴じとして扱う）
+pub fn spread_lead_side(binding: &str) -> PageSide {
+    if binding == BINDING_LEFT { PageSide::Left } else { PageSide::Right }
+}
+
+/// ノンブルを寄せる側（None は中央）
+/// "outer" は小口側、"inner" はノド側
+pub fn nombre_edge(side: PageSide, position: &str) -> Option<PageSide> {
+    match position {
+        NOMBRE_POSITION_OUTER => Some(side),
+        NOMBRE_POSITION_INNER => Some(side.opposite()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_side() {
+        assert_eq!(page_side(1, BINDING_RIGHT), PageSide::Left);
+        assert_eq!(page_side(2, BINDING_RIGHT), PageSide::Right);
+        assert_eq!(page_side(1, BINDING_LEFT), PageSide::Right);
+        assert_eq!(page_side(2, BINDING_LEFT), PageSide::Left);
+        assert_eq!(spread_lead_side(BINDING_RIGHT), PageSide::Right);
+        assert_eq!(spread_lead_side(BINDING_LEFT), PageSide::Left);
+
+        assert_eq!(nombre_edge(PageSide::Left, NOMBRE_POSITION_OUTER), Some(PageSide::Left));
+        assert_eq!(nombre_edge(PageSide::Left, NOMBRE_POSITION_INNER), Some(PageSide::Right));
+        assert_eq!(nombre_edge(PageSide::Right, NOMBRE_POSITION_CENTER), None);
+    }
+}
@@ -1,18 +1,36 @@
 //! タチミ - PDF共通ユーティリティ
 //! PDF生成で共有される機能
 
-use ::image::{DynamicImage, GenericImageView, Rgba, RgbaImage, ImageBuffer};
-use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
-use imageproc::drawing::draw_text_mut;
-use printpdf::{Image, ImageXObject, ImageFilter, ColorSpace, ColorBits, Px};
+use ::image::{DynamicImage, GenericImageView, RgbaImage, ImageBuffer};
+use printpdf::lopdf::{self, dictionary};
+use printpdf::path::PaintMode;
+use printpdf::{
+    BuiltinFont, Cmyk, Color, CurTransMat, Image, ImageTransform, ImageXObject, ImageFilter, ColorSpace, ColorBits,
+    IndirectFontRef, Line, Mm, PdfDocumentReference, PdfLayerReference, PdfPageIndex, Point, Pt, Px, Rect, Rgb,
+};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, Write};
 use std::path::Path;
 
-use crate::processor::jpeg::{encode_jpeg_mozjpeg, get_jpeg_dimensions, is_jpeg_file};
-use crate::processor::image_loader::load_image;
-use crate::processor::cache::get_cached_jp_font_data;
-use crate::processor::types::WorkInfo;
-
-/// デフォルトDPI
+use super::document::PdfDocumentInfo;
+use crate::processor::color::{
+    convert_to_grayscale, convert_to_output_profile, is_gray_profile, is_rgb_profile, matches_target_profile,
+    profile_description, read_jpeg_icc_profile, should_output_grayscale, COLOR_MODE_AUTO, COLOR_MODE_GRAY,
+    COLOR_PROFILE_SRGB,
+};
+use crate::processor::jpeg::{encode_image_jpeg, get_jpeg_dimensions, get_jpeg_frame_info, is_jpeg_file};
+use crate::processor::image_loader::{load_image, load_image_with_profile};
+use crate::processor::fonts::{load_font, FontRole, LoadedFont};
+use crate::processor::resolution::read_image_dpi;
+use crate::processor::nombre::{nombre_is_ascii, nombre_label, NOMBRE_VERTICAL_TOP};
+use crate::processor::page_side::PageSide;
+use crate::processor::trim_marks::{trim_mark_margin_mm, trim_mark_segments};
+use crate::processor::types::{color_to_rgb, JpegOptions, NombreOptions, PdfOptions, TrimMarkOptions};
+
+/// デフォルトDPI（画像に解像度情報がない場合）
 pub const DEFAULT_DPI: f32 = 350.0;
 
 /// ピクセルをmmに変換
@@ -30,28 +48,358 @@ pub fn calc_page_size_mm(width_px: u32, height_px: u32, dpi: f32) -> (f32, f32)
     (px_to_mm(width_px, dpi), px_to_mm(height_px, dpi))
 }
 
-/// 画像のサイズを取得（JPEGの場合は高速パス）
-pub fn get_image_dimensions(path: &Path) -> Result<(u32, u32), String> {
-    if is_jpeg_file(path) {
+/// ページ配置: 余白内に収まるよう拡大縮小（中央）
+pub const PAGE_FIT_FIT: &str = "fit";
+/// ページ配置: 余白内を覆うよう拡大縮小（中央、はみ出しはクリップ）
+pub const PAGE_FIT_FILL: &str = "fill";
+/// ページ配置: 実寸で左上に配置
+pub const PAGE_FIT_ACTUAL: &str = "actual";
+/// ページ配置: 実寸で中央に配置
+pub const PAGE_FIT_CENTER: &str = "center";
+
+/// プリセットから1ページ分の仕上がりサイズ（mm）を取得
+/// "custom" は width_mm / height_mm を使う。"image" や不明なプリセットは None（画像サイズ = ページサイズ）
+/// 見開きプリセットも1ページ分のサイズを返す（見開き全体は2ページ + ノド）
+pub fn preset_page_size_mm(preset: &str, width_mm: f32, height_mm: f32) -> Option<(f32, f32)> {
+    let base = preset
+        .strip_suffix("_single")
+        .or_else(|| preset.strip_suffix("_spread"))
+        .unwrap_or(preset);
+    match base {
+        "a3" => Some((297.0, 420.0)),
+        "a4" => Some((210.0, 297.0)),
+        "a5" => Some((148.0, 210.0)),
+        "b4" => Some((257.0, 364.0)),
+        "b5" => Some((182.0, 257.0)),
+        "b6" => Some((128.0, 182.0)),
+        "custom" if width_mm > 0.0 && height_mm > 0.0 => Some((width_mm, height_mm)),
+        _ => None,
+    }
+}
+
+/// ページ上の画像配置（位置はページ左下基準のmm）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImagePlacement {
+    pub x_mm: f32,
+    pub y_mm: f32,
+    pub scale: f32,
+    /// クリップ枠（x, y, 幅, 高さ）
+    pub clip: Option<(f32, f32, f32, f32)>,
+}
+
+impl ImagePlacement {
+    /// 等倍・クリップなしの配置（画像サイズ = ページサイズの場合）
+    pub fn unscaled(x_mm: f32, y_mm: f32) -> Self {
+        Self { x_mm, y_mm, scale: 1.0, clip: None }
+    }
+
+    /// 画像だけを移動した配置（クリップ枠は変えない）
+    pub fn shifted(self, dx_mm: f32, dy_mm: f32) -> Self {
+        Self { x_mm: self.x_mm + dx_mm, y_mm: self.y_mm + dy_mm, ..self }
+    }
+}
+
+/// 枠内に画像を配置モードに従って配置
+pub fn place_image_in_box(
+    img_width_mm: f32,
+    img_height_mm: f32,
+    box_x: f32,
+    box_y: f32,
+    box_width: f32,
+    box_height: f32,
+    page_fit: &str,
+) -> ImagePlacement {
+    let box_width = box_width.max(1.0);
+    let box_height = box_height.max(1.0);
+    let scale_x = box_width / img_width_mm.max(f32::EPSILON);
+    let scale_y = box_height / img_height_mm.max(f32::EPSILON);
+    let scale = match page_fit {
+        PAGE_FIT_FILL => scale_x.max(scale_y),
+        PAGE_FIT_ACTUAL | PAGE_FIT_CENTER => 1.0,
+        _ => scale_x.min(scale_y),
+    };
+
+    let (width, height) = (img_width_mm * scale, img_height_mm * scale);
+    let (x_mm, y_mm) = if page_fit == PAGE_FIT_ACTUAL {
+        (box_x, box_y + box_height - height)
+    } else {
+        (box_x + (box_width - width) / 2.0, box_y + (box_height - height) / 2.0)
+    };
+
+    ImagePlacement { x_mm, y_mm, scale, clip: Some((box_x, box_y, box_width, box_height)) }
+}
+
+/// 配置に従って画像をレイヤーに追加（クリップ枠がある場合ははみ出しを切り取る）
+pub fn add_placed_image(layer: &PdfLayerReference, image: Image, placement: &ImagePlacement, dpi: f32) {
+    if let Some((x, y, w, h)) = placement.clip {
+        layer.save_graphics_state();
+        layer.add_rect(Rect::new(Mm(x), Mm(y), Mm(x + w), Mm(y + h)).with_mode(PaintMode::Clip));
+    }
+
+    let transform = ImageTransform {
+        translate_x: Some(Mm(placement.x_mm)),
+        translate_y: Some(Mm(placement.y_mm)),
+        scale_x: Some(placement.scale),
+        scale_y: Some(placement.scale),
+        dpi: Some(dpi),
+        ..Default::default()
+    };
+    image.add_to_layer(layer.clone(), transform);
+
+    if placement.clip.is_some() {
+        layer.restore_graphics_state();
+    }
+}
+
+/// ノンブルの文字幅の目安（mm、欧文は半角、それ以外は全角）
+fn nombre_text_width_mm(text: &str, font_size_pt: f32) -> f32 {
+    text.chars()
+        .map(|c| if c.is_ascii() { font_size_pt * 0.3 } else { Mm::from(Pt(font_size_pt)).0 })
+        .sum()
+}
+
+/// ノンブルの文字の左端（mm）
+/// area: ノンブルを置く横範囲（左端, 右端）、edge: 寄せる側（None は中央）
+pub fn nombre_text_x(text: &str, font_size_pt: f32, area: (f32, f32), edge: Option<PageSide>) -> f32 {
+    let text_width = nombre_text_width_mm(text, font_size_pt);
+    match edge {
+        None => (area.0 + area.1) / 2.0 - text_width / 2.0,
+        Some(PageSide::Left) => area.0,
+        Some(PageSide::Right) => area.1 - text_width,
+    }
+}
+
+/// PDFのノンブル（フォント・文字サイズと表示設定）
+pub struct PdfNombre<'a> {
+    font: IndirectFontRef,
+    font_size_pt: f32,
+    options: &'a NombreOptions,
+}
+
+impl<'a> PdfNombre<'a> {
+    /// ノンブルを描く場合のみ作成（通常のノンブルは余白に描くため余白がなければ描かない）
+    /// フォント指定がなく欧文のみなら標準フォント、それ以外は指定または検索したフォントを埋め込む
+    pub fn new(
+        doc: &PdfDocumentReference,
+        add_nombre: bool,
+        padding_mm: f32,
+        nombre_size: &str,
+        options: &'a NombreOptions,
+    ) -> Result<Option<Self>, String> {
+        if !add_nombre || (padding_mm <= 0.0 && !options.hidden) {
+            return Ok(None);
+        }
+        let font = match options.font.as_ref() {
+            None if nombre_is_ascii(options) => doc
+                .add_builtin_font(BuiltinFont::Helvetica)
+                .map_err(|e| format!("ノンブルのフォント設定に失敗: {}", e))?,
+            spec => {
+                let role = if nombre_is_ascii(options) { FontRole::Latin } else { FontRole::Japanese };
+                add_pdf_font(doc, &load_font(spec, role)?)?
+            }
+        };
+        let font_size_pt = if options.hidden {
+            options.hidden_size_pt.max(1.0)
+        } else {
+            get_nombre_font_size_pt(nombre_size)
+        };
+        Ok(Some(Self { font, font_size_pt, options }))
+    }
+
+    /// ページ番号を描く（mm、原点は仕上がりの左下）
+    /// area: 画像の配置枠の横範囲（左端, 右端）、margin_mm: 上下の余白（通常のノンブルはこの中央に置く）
+    /// 隠しノンブルは余白の内側、寄せる側（ノド）の角に置く
+    pub fn draw(
+        &self,
+        layer: &PdfLayerReference,
+        page_number: u32,
+        area: (f32, f32),
+        page_height_mm: f32,
+        margin_mm: f32,
+        edge: Option<PageSide>,
+    ) {
+        let options = self.options;
+        let size = self.font_size_pt;
+        let text = nombre_label(page_number, options);
+        let text_height = size * 0.35;
+        let top = options.vertical == NOMBRE_VERTICAL_TOP;
+
+        let (x, y) = if options.hidden {
+            let inset = text_height * 0.5;
+            let x = nombre_text_x(&text, size, (area.0 + inset, area.1 - inset), edge);
+            let y = if top { page_height_mm - margin_mm - inset - text_height } else { margin_mm + inset };
+            (x, y)
+        } else {
+            let x = nombre_text_x(&text, size, area, edge);
+            let y = if top { page_height_mm - margin_mm / 2.0 } else { margin_mm / 2.0 } - text_height / 2.0;
+            (x, y)
+        };
+        let x = x + options.offset_x_mm;
+        let y = y - options.offset_y_mm;
+
+        // 背景ボックス（余白は白紙のため、不透明度は白との混色で表す）
+        if options.show_box && !options.hidden && options.box_opacity > 0 {
+            let alpha = options.box_opacity.min(100) as f32 / 100.0;
+            let box_color = color_to_rgb(&options.box_color);
+            let mix = |c: u8| 1.0 - (1.0 - c as f32 / 255.0) * alpha;
+            let (pad_x, pad_y) = (text_height * 0.5, text_height * 0.3);
+            let width = nombre_text_width_mm(&text, size);
+            layer.set_fill_color(Color::Rgb(Rgb::new(mix(box_color[0]), mix(box_color[1]), mix(box_color[2]), None)));
+            layer.add_rect(
+                Rect::new(Mm(x - pad_x), Mm(y - pad_y), Mm(x + width + pad_x), Mm(y + text_height + pad_y))
+                    .with_mode(PaintMode::Fill),
+            );
+        }
+
+        let text_color = color_to_rgb(&options.text_color);
+        let channel = |c: u8| c as f32 / 255.0;
+        layer.set_fill_color(Color::Rgb(Rgb::new(channel(text_color[0]), channel(text_color[1]), channel(text_color[2]), None)));
+        layer.use_text(&text, size, Mm(x), Mm(y), &self.font);
+    }
+}
+
+/// フォントをPDFに埋め込む
+/// TTC・OTCは先頭の書体のみ埋め込めるため、それ以外の書体番号はエラー
+pub fn add_pdf_font(doc: &PdfDocumentReference, font: &LoadedFont) -> Result<IndirectFontRef, String> {
+    if font.face_index != 0 {
+        return Err(format!(
+            "PDFに埋め込めるのはフォントの先頭の書体（書体番号 0）のみです: {}",
+            font.path.display()
+        ));
+    }
+    doc.add_external_font(font.data.as_slice())
+        .map_err(|e| format!("PDFへのフォント埋め込みに失敗（{}）: {}", font.path.display(), e))
+}
+
+/// 画像の解像度を決定（上書き指定 → ファイルの解像度情報 → DEFAULT_DPI の順）
+pub fn resolve_dpi(path: &Path, dpi_override: Option<f32>) -> f32 {
+    dpi_override
+        .filter(|dpi| *dpi > 0.0)
+        .or_else(|| read_image_dpi(path))
+        .unwrap_or(DEFAULT_DPI)
+}
+
+/// PdfOptions から求めた、単ページ・見開きPDFで共通のページ寸法（mm）
+#[derive(Debug, Clone, Copy)]
+pub struct PdfLayout<'a> {
+    pub padding_mm: f32,
+    pub gutter_mm: f32,
+    /// プリセット指定時の1ページのサイズ（Noneなら画像サイズから計算）
+    pub page_size_mm: Option<(f32, f32)>,
+    pub frames: PageFrames<'a>,
+}
+
+impl<'a> PdfLayout<'a> {
+    /// 余白・ノドのピクセル指定は first_file（先頭画像）の解像度でmmに換算する
+    pub fn new(options: &'a PdfOptions, first_file: &Path) -> Self {
+        let dpi = resolve_dpi(first_file, options.dpi_override);
+        let px_to_mm_ratio = 25.4 / dpi;
+        Self {
+            padding_mm: options.padding as f32 * px_to_mm_ratio,
+            gutter_mm: options.gutter as f32 * px_to_mm_ratio,
+            page_size_mm: preset_page_size_mm(&options.preset, options.width_mm, options.height_mm),
+            frames: PageFrames::new(options.trim_marks.as_ref(), options.bleed_mm, options.safe_margin_mm),
+        }
+    }
+}
+
+/// ページの仕上がり枠の外側・内側の設定（mm）
+/// 裁ち落とし幅はトンボ指定時はトンボの設定を使う
+#[derive(Debug, Clone, Copy)]
+pub struct PageFrames<'a> {
+    pub trim_marks: Option<&'a TrimMarkOptions>,
+    pub bleed_mm: f32,
+    pub safe_margin_mm: f32,
+}
+
+impl<'a> PageFrames<'a> {
+    pub fn new(trim_marks: Option<&'a TrimMarkOptions>, bleed_mm: f32, safe_margin_mm: f32) -> Self {
+        let bleed_mm = trim_marks.map(|marks| marks.bleed_mm).unwrap_or(bleed_mm).max(0.0);
+        Self { trim_marks, bleed_mm, safe_margin_mm: safe_margin_mm.max(0.0) }
+    }
+
+    /// 仕上がり枠の外側へ広げる幅（トンボ指定時はトンボ分、なければ裁ち落とし分）
+    pub fn margin_mm(&self) -> f32 {
+        self.trim_marks.map(trim_mark_margin_mm).unwrap_or(self.bleed_mm)
+    }
+
+    /// 仕上がりサイズから用紙（MediaBox）サイズを求める
+    pub fn media_size_mm(&self, trim_width: f32, trim_height: f32) -> (f32, f32) {
+        let margin = self.margin_mm();
+        (trim_width + margin * 2.0, trim_height + margin * 2.0)
+    }
+}
+
+/// ページの描画を開始
+/// TrimBox（仕上がり）・BleedBox（裁ち落とし）・ArtBox（内枠、指定時のみ）を設定し、
+/// 以降の描画原点を仕上がり枠の左下へ移動する。トンボ指定時はその外側にレジストレーション色の線で描く
+pub fn begin_page(
+    doc: &PdfDocumentReference,
+    page: PdfPageIndex,
+    layer: &PdfLayerReference,
+    trim_width: f32,
+    trim_height: f32,
+    frames: &PageFrames,
+) {
+    let margin = frames.margin_mm();
+    let pdf_box = |inset: f32| -> Vec<lopdf::Object> {
+        [margin + inset, margin + inset, margin + trim_width - inset, margin + trim_height - inset]
+            .into_iter()
+            .map(|mm| Pt::from(Mm(mm)).0.into())
+            .collect()
+    };
+    let mut boxes = dictionary! {
+        "TrimBox" => pdf_box(0.0),
+        "BleedBox" => pdf_box(-frames.bleed_mm),
+    };
+    let safe = frames.safe_margin_mm;
+    if safe > 0.0 && safe * 2.0 < trim_width.min(trim_height) {
+        boxes.set("ArtBox", pdf_box(safe));
+    }
+    doc.get_page(page).extend_with(boxes);
+
+    if margin > 0.0 {
+        layer.set_ctm(CurTransMat::Translate(Mm(margin).into(), Mm(margin).into()));
+    }
+
+    if let Some(marks) = frames.trim_marks {
+        layer.set_outline_color(Color::Cmyk(Cmyk::new(1.0, 1.0, 1.0, 1.0, None)));
+        layer.set_outline_thickness(Pt::from(Mm(marks.line_width_mm)).0);
+        for segment in trim_mark_segments(trim_width, trim_height, marks) {
+            layer.add_line(Line {
+                points: vec![
+                    (Point::new(Mm(segment.x1), Mm(segment.y1)), false),
+                    (Point::new(Mm(segment.x2), Mm(segment.y2)), false),
+                ],
+                is_closed: false,
+            });
+        }
+    }
+}
+
+/// 画像のサイズと解像度を取得（JPEGの場合は高速パス）
+pub fn get_image_dimensions(path: &Path, dpi_override: Option<f32>) -> Result<(u32, u32, f32), String> {
+    let (width, height) = if is_jpeg_file(path) {
         let data = std::fs::read(path)
             .map_err(|e| format!("ファイルを開けません: {}", e))?;
         get_jpeg_dimensions(&data)
-            .ok_or_else(|| "JPEGサイズの取得に失敗".to_string())
+            .ok_or_else(|| "JPEGサイズの取得に失敗".to_string())?
     } else {
-        let img = load_image(path)?;
-        Ok(img.dimensions())
-    }
+        load_image(path)?.dimensions()
+    };
+    Ok((width, height, resolve_dpi(path, dpi_override)))
 }
 
 /// 画像をMozJPEGエンコードしてPDF用Imageを作成
-pub fn create_pdf_image(img: &DynamicImage) -> Option<Image> {
-    let rgb_img = img.to_rgb8();
-    let jpeg_data = encode_jpeg_mozjpeg(rgb_img.as_raw(), rgb_img.width(), rgb_img.height(), 100.0)?;
+/// グレースケール画像は1チャンネルJPEG（DeviceGray）として埋め込む
+pub fn create_pdf_image(img: &DynamicImage, jpeg_options: &JpegOptions) -> Option<Image> {
+    let jpeg_data = encode_image_jpeg(img, jpeg_options, None)?;
+    let color_space = if img.color().has_color() { ColorSpace::Rgb } else { ColorSpace::Greyscale };
 
     Some(Image::from(ImageXObject {
         width: Px(img.width() as usize),
         height: Px(img.height() as usize),
-        color_space: ColorSpace::Rgb,
+        color_space,
         bits_per_component: ColorBits::Bit8,
         interpolate: true,
         image_data: jpeg_data,
@@ -64,12 +412,17 @@ pub fn create_pdf_image(img: &DynamicImage) -> Option<Image> {
 /// JPEGファイルから直接PDF用Imageを作成（デコード不要で高速）
 pub fn create_pdf_image_from_jpeg_file(path: &Path) -> Option<(Image, u32, u32)> {
     let jpeg_data = std::fs::read(path).ok()?;
-    let (width, height) = get_jpeg_dimensions(&jpeg_data)?;
+    let (width, height, components) = get_jpeg_frame_info(&jpeg_data)?;
+    let color_space = match components {
+        1 => ColorSpace::Greyscale,
+        3 => ColorSpace::Rgb,
+        _ => return None,
+    };
 
     let image = Image::from(ImageXObject {
         width: Px(width as usize),
         height: Px(height as usize),
-        color_space: ColorSpace::Rgb,
+        color_space,
         bits_per_component: ColorBits::Bit8,
         interpolate: true,
         image_data: jpeg_data,
@@ -81,18 +434,200 @@ pub fn create_pdf_image_from_jpeg_file(path: &Path) -> Option<(Image, u32, u32)>
     Some((image, width, height))
 }
 
-/// 画像を読み込んでPDF用Imageを作成
-pub fn load_and_create_pdf_image(path: &Path) -> Result<(Image, u32, u32), String> {
-    if is_jpeg_file(path) {
-        create_pdf_image_from_jpeg_file(path)
-            .ok_or_else(|| "JPEGファイルの読み込みに失敗".to_string())
+/// JPEGをデコードせずにそのまま埋め込めるか判定
+/// 出力プロファイルと同じ色空間で、カラーモードとチャンネル数が一致する場合のみ
+/// （"auto" ではRGBのJPEGを再エンコードしないため、グレー判定は行わない）
+pub fn can_embed_jpeg_directly(path: &Path, color_profile: &str, color_mode: &str) -> bool {
+    if !is_jpeg_file(path) || !matches_target_profile(read_jpeg_icc_profile(path).as_deref(), color_profile) {
+        return false;
+    }
+    let components = std::fs::read(path)
+        .ok()
+        .and_then(|data| get_jpeg_frame_info(&data))
+        .map(|(_, _, components)| components);
+    match components {
+        Some(1) => color_mode == COLOR_MODE_GRAY || (color_mode == COLOR_MODE_AUTO && color_profile != COLOR_PROFILE_SRGB),
+        Some(3) => color_mode != COLOR_MODE_GRAY,
+        _ => false,
+    }
+}
+
+/// 画像を読み込んでPDFの出力プロファイル・カラーモードに変換
+/// 戻り値は変換後の画像と、その画像の色を表すICCプロファイル
+pub fn load_image_for_pdf(
+    path: &Path,
+    color_profile: &str,
+    color_mode: &str,
+) -> Result<(DynamicImage, Option<Vec<u8>>), String> {
+    let (img, icc_profile) = load_image_with_profile(path)?;
+    let (img, icc_profile) = convert_to_output_profile(img, icc_profile, color_profile)?;
+    if should_output_grayscale(&img, color_mode, color_profile) {
+        convert_to_grayscale(img, icc_profile)
     } else {
-        let img = load_image(path)?;
+        Ok((img, icc_profile))
+    }
+}
+
+/// 画像を読み込んでPDF用Imageを作成（JPEG高速パス対応）
+/// 画像のICCプロファイルは profiles に登録する
+/// 戻り値: (Image, 幅px, 高さpx, 解像度dpi)
+pub fn load_and_create_pdf_image(
+    path: &Path,
+    color_profile: &str,
+    color_mode: &str,
+    jpeg_options: &JpegOptions,
+    dpi_override: Option<f32>,
+    profiles: &mut PdfImageProfiles,
+) -> Result<(Image, u32, u32, f32), String> {
+    let (pdf_img, w, h, icc_profile) = if can_embed_jpeg_directly(path, color_profile, color_mode) {
+        let (pdf_img, w, h) = create_pdf_image_from_jpeg_file(path)
+            .ok_or_else(|| "JPEGファイルの読み込みに失敗".to_string())?;
+        (pdf_img, w, h, read_jpeg_icc_profile(path))
+    } else {
+        let (img, icc_profile) = load_image_for_pdf(path, color_profile, color_mode)?;
         let (w, h) = img.dimensions();
-        create_pdf_image(&img)
-            .map(|pdf_img| (pdf_img, w, h))
-            .ok_or_else(|| "PDF画像の変換に失敗".to_string())
+        let pdf_img = create_pdf_image(&img, jpeg_options)
+            .ok_or_else(|| "PDF画像の変換に失敗".to_string())?;
+        (pdf_img, w, h, icc_profile)
+    };
+    profiles.register(&pdf_img, icc_profile);
+    Ok((pdf_img, w, h, resolve_dpi(path, dpi_override)))
+}
+
+/// PDFに配置した画像ごとのICCプロファイル
+/// 画像データのハッシュで画像XObjectと対応付け、保存時にそれぞれの色空間をICCBasedにする
+#[derive(Default)]
+pub struct PdfImageProfiles {
+    profiles: Vec<Vec<u8>>,
+    images: HashMap<u64, usize>,
+}
+
+impl PdfImageProfiles {
+    /// 画像のICCプロファイルを登録（プロファイルなしの画像はDeviceRGB / DeviceGrayのまま）
+    pub fn register(&mut self, image: &Image, icc_profile: Option<Vec<u8>>) {
+        let Some(icc) = icc_profile else { return };
+        let index = match self.profiles.iter().position(|p| *p == icc) {
+            Some(index) => index,
+            None => {
+                self.profiles.push(icc);
+                self.profiles.len() - 1
+            }
+        };
+        self.images.insert(image_data_hash(&image.image.image_data), index);
     }
+
+    /// 画像データに対応するプロファイルの番号
+    fn profile_index(&self, image_data: &[u8]) -> Option<usize> {
+        self.images.get(&image_data_hash(image_data)).copied()
+    }
+}
+
+fn image_data_hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// PDFを保存（画像のICCプロファイルと出力インテントを埋め込み、文書情報・ページラベル・しおりを書き込んでから保存）
+/// output_intent は出力プロファイルを固定したときだけ指定する
+/// 戻り値は文書情報を書き込めなかった設定の警告
+pub fn save_pdf(
+    doc: PdfDocumentReference,
+    path: &str,
+    image_profiles: &PdfImageProfiles,
+    output_intent: Option<&[u8]>,
+    document_info: &PdfDocumentInfo,
+) -> Result<Vec<String>, String> {
+    let bytes = doc.save_to_bytes()
+        .map_err(|e| format!("PDFの保存に失敗: {}", e))?;
+    let mut doc = lopdf::Document::load_mem(&bytes)
+        .map_err(|e| format!("PDFの読み込みに失敗: {}", e))?;
+    embed_icc_profiles(&mut doc, image_profiles, output_intent)?;
+    let warnings = document_info.apply(&mut doc)?;
+
+    let file = File::create(path)
+        .map_err(|e| format!("PDFファイルの作成に失敗: {}", e))?;
+    let mut writer = BufWriter::new(file);
+    doc.save_to(&mut writer)
+        .map_err(|e| format!("PDFの保存に失敗: {}", e))?;
+    writer.flush()
+        .map_err(|e| format!("PDFの保存に失敗: {}", e))?;
+    Ok(warnings)
+}
+
+/// ICCプロファイルをPDFのストリームとして追加
+fn add_icc_stream(doc: &mut lopdf::Document, icc: &[u8]) -> lopdf::ObjectId {
+    let (components, device) = if is_gray_profile(icc) { (1, "DeviceGray") } else { (3, "DeviceRGB") };
+    let mut icc_stream = lopdf::Stream::new(
+        dictionary! { "N" => components, "Alternate" => device },
+        icc.to_vec(),
+    );
+    let _ = icc_stream.compress();
+    doc.add_object(icc_stream)
+}
+
+/// PDFにICCプロファイルを埋め込む
+/// 画像XObject（DeviceRGB / DeviceGray）の色空間を、登録された画像ごとのプロファイルのICCBasedに置き換える
+/// 成分数が合わないプロファイルや、プロファイルのない画像はそのままにする
+/// output_intent 指定時は出力インテント（GTS_PDFX）として登録する
+pub fn embed_icc_profiles(
+    doc: &mut lopdf::Document,
+    profiles: &PdfImageProfiles,
+    output_intent: Option<&[u8]>,
+) -> Result<(), String> {
+    let mut retag = Vec::new();
+    for (&object_id, object) in doc.objects.iter() {
+        let lopdf::Object::Stream(stream) = object else { continue };
+        let is_image = stream.dict.get(b"Subtype")
+            .and_then(|o| o.as_name())
+            .map(|name| name == b"Image")
+            .unwrap_or(false);
+        let device = stream.dict.get(b"ColorSpace")
+            .and_then(|o| o.as_name())
+            .ok();
+        let Some(index) = profiles.profile_index(&stream.content).filter(|_| is_image) else { continue };
+        let icc = &profiles.profiles[index];
+        let matches = match device {
+            Some(b"DeviceGray") => is_gray_profile(icc),
+            Some(b"DeviceRGB") => is_rgb_profile(icc),
+            _ => false,
+        };
+        if matches {
+            retag.push((object_id, index));
+        }
+    }
+
+    // プロファイルは画像間で共有し、1つにつき1回だけ埋め込む
+    let mut icc_ids: Vec<Option<lopdf::ObjectId>> = vec![None; profiles.profiles.len()];
+    for (object_id, index) in retag {
+        let icc_id = match icc_ids[index] {
+            Some(id) => id,
+            None => *icc_ids[index].insert(add_icc_stream(doc, &profiles.profiles[index])),
+        };
+        if let Ok(lopdf::Object::Stream(stream)) = doc.get_object_mut(object_id) {
+            stream.dict.set("ColorSpace", vec![lopdf::Object::Name(b"ICCBased".to_vec()), icc_id.into()]);
+        }
+    }
+
+    let Some(icc) = output_intent else {
+        return Ok(());
+    };
+    let icc_id = profiles.profiles.iter()
+        .position(|p| p == icc)
+        .and_then(|index| icc_ids[index])
+        .unwrap_or_else(|| add_icc_stream(doc, icc));
+    let condition = profile_description(icc).unwrap_or_else(|| "Custom".to_string());
+    let intent_id = doc.add_object(dictionary! {
+        "Type" => "OutputIntent",
+        "S" => "GTS_PDFX",
+        "OutputConditionIdentifier" => lopdf::Object::string_literal(condition.as_str()),
+        "Info" => lopdf::Object::string_literal(condition.as_str()),
+        "DestOutputProfile" => icc_id,
+    });
+    doc.catalog_mut()
+        .map_err(|e| format!("PDFカタログの取得に失敗: {}", e))?
+        .set("OutputIntents", vec![lopdf::Object::Reference(intent_id)]);
+    Ok(())
 }
 
 /// 2枚の画像を横に結合（見開き用）
@@ -192,174 +727,131 @@ pub fn get_nombre_font_size_pt(nombre_size: &str) -> f32 {
     }
 }
 
-/// 白紙ページのPDF用Imageを作成（作品情報印字対応）
-pub fn create_white_page_image(width: u32, height: u32, work_info: Option<&WorkInfo>, print_work_info: bool) -> Option<Image> {
-    let mut white_img: RgbaImage = ImageBuffer::from_fn(width, height, |_, _| {
-        Rgba([255u8, 255u8, 255u8, 255u8])
-    });
-
-    if print_work_info {
-        if let Some(info) = work_info {
-            draw_work_info_on_image(&mut white_img, info);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset_page_size() {
+        assert_eq!(preset_page_size_mm("b4_spread", 0.0, 0.0), Some((257.0, 364.0)));
+        assert_eq!(preset_page_size_mm("a4_single", 0.0, 0.0), Some((210.0, 297.0)));
+        assert_eq!(preset_page_size_mm("custom", 100.0, 150.0), Some((100.0, 150.0)));
+        assert_eq!(preset_page_size_mm("custom", 0.0, 150.0), None);
+        assert_eq!(preset_page_size_mm("image", 100.0, 150.0), None);
     }
 
-    let rgb_img: ImageBuffer<::image::Rgb<u8>, Vec<u8>> = ImageBuffer::from_fn(width, height, |x, y| {
-        let p = white_img.get_pixel(x, y);
-        ::image::Rgb([p[0], p[1], p[2]])
-    });
-
-    let jpeg_data = encode_jpeg_mozjpeg(rgb_img.as_raw(), width, height, 100.0)?;
-
-    Some(Image::from(ImageXObject {
-        width: Px(width as usize),
-        height: Px(height as usize),
-        color_space: ColorSpace::Rgb,
-        bits_per_component: ColorBits::Bit8,
-        interpolate: true,
-        image_data: jpeg_data,
-        image_filter: Some(ImageFilter::DCT),
-        clipping_bbox: None,
-        smask: None,
-    }))
-}
-
-/// 白紙画像に作品情報を描画
-pub fn draw_work_info_on_image(img: &mut RgbaImage, info: &WorkInfo) {
-    let (width, height) = img.dimensions();
-
-    let font_data = match get_cached_jp_font_data() {
-        Some(data) => data,
-        None => return,
-    };
-
-    let font = match FontRef::try_from_slice(font_data) {
-        Ok(f) => f,
-        Err(_) => return,
-    };
-
-    let base_size = height as f32 / 30.0;
-    let title_size = base_size * 2.0;
-    let subtitle_size = base_size * 1.0;
-    let version_size = base_size * 1.2;
-    let author_size = base_size * 0.85;
-    let label_size = base_size * 0.7;
-
-    let black = Rgba([0u8, 0u8, 0u8, 255u8]);
-    let gray = Rgba([80u8, 80u8, 80u8, 255u8]);
-
-    let page_center_y = height as f32 / 2.0;
-    let golden_ratio = 0.35;
-    let title_center_y = height as f32 * golden_ratio;
-
-    // タイトルブロック
-    let mut title_block: Vec<(String, f32, Rgba<u8>)> = Vec::new();
-    if !info.title.is_empty() {
-        title_block.push((info.title.clone(), title_size, black));
-    }
-    if !info.subtitle.is_empty() {
-        title_block.push((info.subtitle.clone(), subtitle_size, black));
-    }
-    if !info.version.is_empty() {
-        title_block.push((info.version.clone(), version_size, black));
-    }
-
-    // 上部ブロック（レーベル）
-    let mut top_block: Vec<(String, f32, Rgba<u8>)> = Vec::new();
-    if !info.label.is_empty() {
-        top_block.push((info.label.clone(), label_size, gray));
+    #[test]
+    fn test_place_image_in_box() {
+        // 横長の枠に縦長画像: fitは高さ合わせで中央、fillは幅合わせ
+        let fit = place_image_in_box(100.0, 200.0, 10.0, 10.0, 200.0, 100.0, PAGE_FIT_FIT);
+        assert_eq!((fit.scale, fit.x_mm, fit.y_mm), (0.5, 85.0, 10.0));
+        let fill = place_image_in_box(100.0, 200.0, 10.0, 10.0, 200.0, 100.0, PAGE_FIT_FILL);
+        assert_eq!((fill.scale, fill.x_mm, fill.y_mm), (2.0, 10.0, -140.0));
+
+        // 実寸: actualは左上、centerは中央
+        let actual = place_image_in_box(50.0, 40.0, 0.0, 0.0, 100.0, 100.0, PAGE_FIT_ACTUAL);
+        assert_eq!((actual.scale, actual.x_mm, actual.y_mm), (1.0, 0.0, 60.0));
+        let center = place_image_in_box(50.0, 40.0, 0.0, 0.0, 100.0, 100.0, PAGE_FIT_CENTER);
+        assert_eq!((center.x_mm, center.y_mm), (25.0, 30.0));
     }
 
-    // 下部ブロック（著者）
-    let mut bottom_block: Vec<(String, f32, Rgba<u8>)> = Vec::new();
-    let author_text = match info.author_type {
-        0 => {
-            if !info.author1.is_empty() {
-                format!("著　{}", info.author1)
-            } else {
-                String::new()
-            }
-        }
-        1 => {
-            let mut parts = Vec::new();
-            if !info.author1.is_empty() {
-                parts.push(format!("作画　{}", info.author1));
-            }
-            if !info.author2.is_empty() {
-                parts.push(format!("原作　{}", info.author2));
-            }
-            parts.join("　　")
-        }
-        _ => info.author1.clone(),
-    };
-    if !author_text.is_empty() {
-        bottom_block.push((author_text, author_size, gray));
-    }
-
-    // タイトルブロック描画
-    let title_line_height = 1.6;
-    let title_total_height: f32 = title_block.iter()
-        .map(|(_, size, _)| size * title_line_height)
-        .sum();
-    let title_start_y = title_center_y - title_total_height / 2.0;
-    let mut current_y = title_start_y;
-
-    for (text, font_size, color) in &title_block {
-        if !text.is_empty() {
-            let scale = PxScale::from(*font_size);
-            let scaled_font = font.as_scaled(scale);
-            let text_width: f32 = text.chars()
-                .map(|c| scaled_font.h_advance(scaled_font.glyph_id(c)))
-                .sum();
-            let x = ((width as f32 - text_width) / 2.0).max(0.0) as i32;
-            draw_text_mut(img, *color, x, current_y as i32, scale, &font, text);
-        }
-        current_y += font_size * title_line_height;
-    }
-
-    // 上部ブロック描画
-    if !top_block.is_empty() {
-        let top_spacing = base_size * 3.0;
-        let top_line_height = 1.4;
-        let top_total_height: f32 = top_block.iter()
-            .map(|(_, size, _)| size * top_line_height)
-            .sum();
-        let mut top_y = title_start_y - top_spacing - top_total_height;
-
-        for (text, font_size, color) in &top_block {
-            if !text.is_empty() {
-                let scale = PxScale::from(*font_size);
-                let scaled_font = font.as_scaled(scale);
-                let text_width: f32 = text.chars()
-                    .map(|c| scaled_font.h_advance(scaled_font.glyph_id(c)))
-                    .sum();
-                let x = ((width as f32 - text_width) / 2.0).max(0.0) as i32;
-                draw_text_mut(img, *color, x, top_y as i32, scale, &font, text);
-            }
-            top_y += font_size * top_line_height;
-        }
+    #[test]
+    fn test_page_frame_boxes() {
+        let marks = TrimMarkOptions::default();
+        let frames = PageFrames::new(Some(&marks), 5.0, 0.0);
+        assert_eq!(frames.bleed_mm, marks.bleed_mm);
+        assert_eq!(frames.margin_mm(), trim_mark_margin_mm(&marks));
+
+        // 裁ち落としのみ: 用紙は裁ち落とし分広がり、TrimBox・BleedBox・ArtBoxが設定される
+        let frames = PageFrames::new(None, 3.0, 10.0);
+        let (media_width, media_height) = frames.media_size_mm(100.0, 150.0);
+        assert_eq!((media_width, media_height), (106.0, 156.0));
+        let (doc, page, layer) = printpdf::PdfDocument::new("test", Mm(media_width), Mm(media_height), "Layer 1");
+        let layer = doc.get_page(page).get_layer(layer);
+        begin_page(&doc, page, &layer, 100.0, 150.0, &frames);
+
+        let bytes = doc.save_to_bytes().unwrap();
+        let pdf = lopdf::Document::load_mem(&bytes).unwrap();
+        let page_id = *pdf.get_pages().values().next().unwrap();
+        let page = pdf.get_dictionary(page_id).unwrap();
+        let pdf_box = |key: &[u8]| -> Vec<f32> {
+            page.get(key).unwrap().as_array().unwrap().iter().map(|v| v.as_float().unwrap()).collect()
+        };
+        let pt = |mm: f32| Pt::from(Mm(mm)).0;
+        let close = |a: Vec<f32>, b: [f32; 4]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 0.01);
+        assert!(close(pdf_box(b"TrimBox"), [pt(3.0), pt(3.0), pt(103.0), pt(153.0)]));
+        assert!(close(pdf_box(b"BleedBox"), [0.0, 0.0, pt(106.0), pt(156.0)]));
+        assert!(close(pdf_box(b"ArtBox"), [pt(13.0), pt(13.0), pt(93.0), pt(143.0)]));
     }
 
-    // 下部ブロック描画
-    if !bottom_block.is_empty() {
-        let bottom_margin = base_size * 2.5;
-        let bottom_line_height = 1.4;
-        let bottom_total_height: f32 = bottom_block.iter()
-            .map(|(_, size, _)| size * bottom_line_height)
-            .sum();
-        let mut bottom_y = page_center_y - bottom_margin - bottom_total_height;
-
-        for (text, font_size, color) in &bottom_block {
-            if !text.is_empty() {
-                let scale = PxScale::from(*font_size);
-                let scaled_font = font.as_scaled(scale);
-                let text_width: f32 = text.chars()
-                    .map(|c| scaled_font.h_advance(scaled_font.glyph_id(c)))
-                    .sum();
-                let x = ((width as f32 - text_width) / 2.0).max(0.0) as i32;
-                draw_text_mut(img, *color, x, bottom_y as i32, scale, &font, text);
-            }
-            bottom_y += font_size * bottom_line_height;
+    #[test]
+    fn test_embed_icc_profiles() {
+        use crate::processor::color::{target_icc_profile, COLOR_PROFILE_GRAY_GAMMA22};
+        use ::image::{GrayImage, Luma, Rgb, RgbImage};
+
+        let srgb = target_icc_profile(COLOR_PROFILE_SRGB).unwrap();
+        let gray = target_icc_profile(COLOR_PROFILE_GRAY_GAMMA22).unwrap();
+        let jpeg = JpegOptions::default();
+        let rgb_image = |v: u8| {
+            let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, Rgb([v, 0, 0])));
+            create_pdf_image(&img, &jpeg).unwrap()
+        };
+        let gray_img = DynamicImage::ImageLuma8(GrayImage::from_pixel(4, 4, Luma([128])));
+        let gray_image = create_pdf_image(&gray_img, &jpeg).unwrap();
+
+        // sRGBの画像2枚・グレーの画像・プロファイルなしの画像・成分数が合わないプロファイルの画像
+        let mut profiles = PdfImageProfiles::default();
+        let images = [
+            (rgb_image(10), Some(srgb)),
+            (rgb_image(20), Some(srgb)),
+            (gray_image, Some(gray)),
+            (rgb_image(30), None),
+            (rgb_image(40), Some(gray)),
+        ];
+        let (doc, page, layer) = printpdf::PdfDocument::new("test", Mm(100.0), Mm(100.0), "Layer 1");
+        let layer = doc.get_page(page).get_layer(layer);
+        for (image, icc) in images {
+            profiles.register(&image, icc.map(<[u8]>::to_vec));
+            add_placed_image(&layer, image, &ImagePlacement::unscaled(0.0, 0.0), 72.0);
         }
+        let bytes = doc.save_to_bytes().unwrap();
+
+        let color_spaces = |pdf: &lopdf::Document| {
+            let mut spaces: Vec<String> = pdf.objects.values()
+                .filter_map(|o| o.as_stream().ok())
+                .filter(|s| s.dict.get(b"Subtype").and_then(|o| o.as_name()).ok() == Some(b"Image"))
+                .map(|s| match s.dict.get(b"ColorSpace").unwrap() {
+                    lopdf::Object::Name(name) => String::from_utf8_lossy(name).to_string(),
+                    lopdf::Object::Array(array) => {
+                        let icc = pdf.get_object(array[1].as_reference().unwrap()).unwrap().as_stream().unwrap();
+                        format!("ICCBased/{}", icc.dict.get(b"N").unwrap().as_i64().unwrap())
+                    }
+                    other => format!("{:?}", other),
+                })
+                .collect();
+            spaces.sort();
+            spaces
+        };
+        let icc_streams = |pdf: &lopdf::Document| {
+            pdf.objects.values()
+                .filter_map(|o| o.as_stream().ok())
+                .filter(|s| s.dict.get(b"Alternate").is_ok())
+                .count()
+        };
+
+        // 出力プロファイルを固定しない場合は画像ごとのプロファイルだけを埋め込み、出力インテントは付けない
+        let mut pdf = lopdf::Document::load_mem(&bytes).unwrap();
+        embed_icc_profiles(&mut pdf, &profiles, None).unwrap();
+        assert_eq!(color_spaces(&pdf), ["DeviceRGB", "DeviceRGB", "ICCBased/1", "ICCBased/3", "ICCBased/3"]);
+        assert_eq!(icc_streams(&pdf), 2);
+        assert!(pdf.catalog().unwrap().get(b"OutputIntents").is_err());
+
+        // 固定した出力プロファイルはPDF/Xの出力インテントにし、同じプロファイルのストリームを共有する
+        let mut pdf = lopdf::Document::load_mem(&bytes).unwrap();
+        embed_icc_profiles(&mut pdf, &profiles, Some(srgb)).unwrap();
+        assert_eq!(icc_streams(&pdf), 2);
+        let intents = pdf.catalog().unwrap().get(b"OutputIntents").unwrap().as_array().unwrap();
+        let intent = pdf.get_dictionary(intents[0].as_reference().unwrap()).unwrap();
+        assert_eq!(intent.get(b"S").unwrap().as_name().unwrap(), b"GTS_PDFX");
     }
 }
@@ -0,0 +1,418 @@
+//! タチミ - PDFの文書情報・ページラベル・しおり
+//! 保存時にlopdfで書き込む（printpdfは文字列をUTF-8のまま書くため、日本語のタイトルやしおりが文字化けする）
+
+use printpdf::lopdf::{self, dictionary, Object, StringFormat};
+
+use super::generated::work_info_author_lines;
+use crate::processor::nombre::{format_numeral, nombre_label, NUMERAL_ARABIC, NUMERAL_ROMAN};
+use crate::processor::page_side::BINDING_LEFT;
+use crate::processor::types::{NombreOptions, PdfChapter, WorkInfo};
+
+/// 作品情報がないときの文書タイトル
+pub const DEFAULT_DOCUMENT_TITLE: &str = "タチミ出力";
+/// 文書の作成アプリケーション
+const CREATOR_TOOL: &str = "タチミ";
+
+/// PDFの1ページのラベル
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageLabel {
+    /// ノンブルのないページ（白紙・奥付）: 名前だけのラベル
+    Unnumbered(&'static str),
+    /// ノンブルの番号（見開きは読む順）
+    Numbered(Vec<u32>),
+}
+
+/// 白紙ページのラベル
+pub const PAGE_LABEL_WHITE: &str = "白紙";
+/// 奥付ページのラベル
+pub const PAGE_LABEL_COLOPHON: &str = "奥付";
+
+/// ページラベルの範囲（/PageLabels の1項目）
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageLabelRange {
+    /// 範囲の先頭ページ（0始まり）
+    pub page_index: u32,
+    /// 番号の表記（"D": 算用数字, "R": ローマ数字）、None はprefixのみ
+    pub style: Option<&'static str>,
+    pub prefix: String,
+    /// 先頭ページの番号
+    pub start: u32,
+}
+
+/// PDFの文書情報（タイトル・著者など）・ページラベル・しおり・見開き表示の指定
+/// 生成中に追加したページの順にラベルを記録し、保存時に apply で書き込む
+pub struct PdfDocumentInfo<'a> {
+    work_info: Option<&'a WorkInfo>,
+    default_title: &'a str,
+    nombre: &'a NombreOptions,
+    chapters: &'a [PdfChapter],
+    pages: Vec<PageLabel>,
+    /// 2ページ表示の綴じ方向と、先頭ページのノンブル
+    two_page_layout: Option<(&'a str, u32)>,
+}
+
+impl<'a> PdfDocumentInfo<'a> {
+    /// work_info がなければ default_title をタイトルにする
+    pub fn new(
+        work_info: Option<&'a WorkInfo>,
+        default_title: &'a str,
+        nombre: &'a NombreOptions,
+        chapters: &'a [PdfChapter],
+    ) -> Self {
+        Self { work_info, default_title, nombre, chapters, pages: Vec::new(), two_page_layout: None }
+    }
+
+    /// ビューアで2ページずつ並べて表示する（単ページPDF用）
+    /// 先頭ページのノンブル first_number が奇数なら1ページ目を単独で置き（/TwoPageRight）、偶数なら次のページと並べる（/TwoPageLeft）
+    /// binding が "right" なら右から左へ並べ（/Direction /R2L）、奇数ノンブルのページは右綴じなら左、左綴じなら右に並ぶ
+    /// （page_side の左右と一致する）
+    pub fn set_two_page_layout(&mut self, binding: &'a str, first_number: u32) {
+        self.two_page_layout = Some((binding, first_number));
+    }
+
+    /// 追加したページのラベルを記録
+    pub fn add_page(&mut self, label: PageLabel) {
+        self.pages.push(label);
+    }
+
+    /// 文書情報・XMP・ページラベル・しおりを書き込む
+    /// 戻り値は書き込めなかった設定の警告（開始ページがPDFにない章のしおり）
+    pub fn apply(&self, doc: &mut lopdf::Document) -> Result<Vec<String>, String> {
+        let title = document_title(self.work_info, self.default_title);
+        let author = self.work_info.map(document_author).unwrap_or_default();
+        let subject = self.work_info.map(|w| w.subtitle.clone()).unwrap_or_default();
+        let keywords = self.work_info.map(document_keywords).unwrap_or_default();
+
+        // 文書情報辞書
+        let info_id = doc.trailer.get(b"Info")
+            .and_then(|o| o.as_reference())
+            .map_err(|e| format!("PDFの文書情報の取得に失敗: {}", e))?;
+        let info = doc.get_object_mut(info_id)
+            .and_then(|o| o.as_dict_mut())
+            .map_err(|e| format!("PDFの文書情報の取得に失敗: {}", e))?;
+        for (key, value) in [("Title", &title), ("Author", &author), ("Subject", &subject), ("Keywords", &keywords)] {
+            if value.is_empty() {
+                info.remove(key.as_bytes());
+            } else {
+                info.set(key, text_string(value));
+            }
+        }
+        info.set("Creator", text_string(CREATOR_TOOL));
+
+        // XMPメタデータ
+        let xmp = xmp_metadata(&title, &author, &subject, &keywords);
+        let xmp_id = doc.add_object(lopdf::Stream::new(
+            dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+            xmp.into_bytes(),
+        ));
+
+        // ページラベル
+        let nums = page_label_ranges(&self.pages, self.nombre)
+            .into_iter()
+            .flat_map(|range| {
+                let mut label = dictionary! {};
+                if let Some(style) = range.style {
+                    label.set("S", style);
+                    label.set("St", range.start as i64);
+                }
+                if !range.prefix.is_empty() {
+                    label.set("P", text_string(&range.prefix));
+                }
+                [Object::Integer(range.page_index as i64), Object::Dictionary(label)]
+            })
+            .collect::<Vec<_>>();
+
+        // しおり
+        let page_ids = doc.get_pages().into_values().collect::<Vec<_>>();
+        let mut warnings = Vec::new();
+        let outline_id = self.add_outline(doc, &page_ids, &mut warnings);
+
+        let catalog = doc.catalog_mut()
+            .map_err(|e| format!("PDFカタログの取得に失敗: {}", e))?;
+        catalog.set("Metadata", xmp_id);
+        if !nums.is_empty() {
+            catalog.set("PageLabels", dictionary! { "Nums" => nums });
+        }
+        match outline_id {
+            Some(outline_id) => {
+                catalog.set("Outlines", outline_id);
+                catalog.set("PageMode", "UseOutlines");
+            }
+            None => {
+                catalog.remove(b"Outlines");
+                catalog.set("PageMode", "UseNone");
+            }
+        }
+        if let Some((binding, first_number)) = self.two_page_layout {
+            catalog.set("PageLayout", if first_number % 2 == 1 { "TwoPageRight" } else { "TwoPageLeft" });
+            catalog.set("ViewerPreferences", dictionary! { "Direction" => reading_direction(binding) });
+        }
+        Ok(warnings)
+    }
+
+    /// 章ごとにしおりを追加（開始ページのノンブルがPDFにない章は省き、warnings に追加）
+    fn add_outline(
+        &self,
+        doc: &mut lopdf::Document,
+        page_ids: &[lopdf::ObjectId],
+        warnings: &mut Vec<String>,
+    ) -> Option<lopdf::ObjectId> {
+        let targets = self.chapters.iter()
+            .filter_map(|chapter| match chapter_page_index(&self.pages, chapter.page) {
+                Some(index) => page_ids.get(index).map(|&page_id| (chapter.title.as_str(), page_id)),
+                None => {
+                    warnings.push(format!("しおり「{}」の開始ページ {} がありません", chapter.title, chapter.page));
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        if targets.is_empty() {
+            return None;
+        }
+
+        let outline_id = doc.new_object_id();
+        let item_ids = targets.iter().map(|_| doc.new_object_id()).collect::<Vec<_>>();
+        for (i, (title, page_id)) in targets.iter().enumerate() {
+            let mut item = dictionary! {
+                "Title" => text_string(title),
+                "Parent" => outline_id,
+                "Dest" => vec![Object::Reference(*page_id), "Fit".into()],
+            };
+            if i > 0 {
+                item.set("Prev", item_ids[i - 1]);
+            }
+            if let Some(&next) = item_ids.get(i + 1) {
+                item.set("Next", next);
+            }
+            doc.objects.insert(item_ids[i], Object::Dictionary(item));
+        }
+        doc.objects.insert(outline_id, Object::Dictionary(dictionary! {
+            "Type" => "Outlines",
+            "First" => item_ids[0],
+            "Last" => item_ids[item_ids.len() - 1],
+            "Count" => item_ids.len() as i64,
+        }));
+        Some(outline_id)
+    }
+}
+
+/// 綴じ方向から読む向き（右綴じ・不明な綴じ方向は右から左）
+fn reading_direction(binding: &str) -> &'static str {
+    if binding == BINDING_LEFT { "L2R" } else { "R2L" }
+}
+
+/// 文書タイトル（タイトルと巻数、どちらもなければ default_title）
+pub fn document_title(work_info: Option<&WorkInfo>, default_title: &str) -> String {
+    let title = work_info
+        .map(|w| [w.title.as_str(), w.version.as_str()]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("　"))
+        .unwrap_or_default();
+    if title.is_empty() { default_title.to_string() } else { title }
+}
+
+/// 著者（作品情報の著者表記を全角空白でつなぐ）
+fn document_author(info: &WorkInfo) -> String {
+    work_info_author_lines(info).join("　")
+}
+
+/// キーワード（レーベル・タイトル）
+fn document_keywords(info: &WorkInfo) -> String {
+    [info.label.as_str(), info.title.as_str()]
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// 章の開始ページ（ノンブルの番号）を含む最初のPDFページ
+fn chapter_page_index(pages: &[PageLabel], number: u32) -> Option<usize> {
+    pages.iter().position(|label| matches!(label, PageLabel::Numbered(numbers) if numbers.contains(&number)))
+}
+
+/// ページごとのラベルを /PageLabels の範囲にまとめる
+/// 算用数字・ローマ数字で後置文字のない単ページは番号が続く限り1つの範囲にし、
+/// それ以外（ゼロ埋め・漢数字・後置文字・見開き・ノンブルなし）はページごとに表記どおりの文字列を prefix にする
+pub fn page_label_ranges(pages: &[PageLabel], nombre: &NombreOptions) -> Vec<PageLabelRange> {
+    let numeric_style = match nombre.numeral_style.as_str() {
+        NUMERAL_ARABIC if nombre.suffix.is_empty() => Some("D"),
+        NUMERAL_ROMAN if nombre.suffix.is_empty() => Some("R"),
+        _ => None,
+    };
+
+    let mut ranges: Vec<PageLabelRange> = Vec::new();
+    let mut previous: Option<u32> = None;
+    for (index, label) in pages.iter().enumerate() {
+        let index = index as u32;
+        let range = match (label, numeric_style) {
+            (PageLabel::Numbered(numbers), Some(style))
+                if numbers.len() == 1 && numbers[0] >= 1 && (style != "R" || numbers[0] <= 3999) =>
+            {
+                let number = numbers[0];
+                let continues = previous.is_some_and(|p| number == p + 1);
+                previous = Some(number);
+                if continues {
+                    continue;
+                }
+                PageLabelRange { page_index: index, style: Some(style), prefix: nombre.prefix.clone(), start: number }
+            }
+            (PageLabel::Numbered(numbers), _) => {
+                previous = None;
+                let text = match numbers.as_slice() {
+                    [single] => nombre_label(*single, nombre),
+                    _ => {
+                        let numerals = numbers.iter()
+                            .map(|&n| format_numeral(n, &nombre.numeral_style, nombre.pad_width))
+                            .collect::<Vec<_>>();
+                        format!("{}{}{}", nombre.prefix, numerals.join("-"), nombre.suffix)
+                    }
+                };
+                PageLabelRange { page_index: index, style: None, prefix: text, start: 1 }
+            }
+            (PageLabel::Unnumbered(name), _) => {
+                previous = None;
+                PageLabelRange { page_index: index, style: None, prefix: name.to_string(), start: 1 }
+            }
+        };
+        ranges.push(range);
+    }
+    ranges
+}
+
+/// PDFのテキスト文字列（ASCIIのみならそのまま、それ以外はBOM付きUTF-16BE）
+fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::string_literal(text);
+    }
+    let bytes = [0xFE, 0xFF].into_iter()
+        .chain(text.encode_utf16().flat_map(|unit| unit.to_be_bytes()))
+        .collect();
+    Object::String(bytes, StringFormat::Hexadecimal)
+}
+
+/// XMPメタデータ（Dublin Core のタイトル・著者・説明とPDFのキーワード）
+fn xmp_metadata(title: &str, author: &str, subject: &str, keywords: &str) -> String {
+    let mut fields = format!(
+        "   <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n",
+        xml_escape(title)
+    );
+    if !author.is_empty() {
+        fields += &format!("   <dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>\n", xml_escape(author));
+    }
+    if !subject.is_empty() {
+        fields += &format!(
+            "   <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n",
+            xml_escape(subject)
+        );
+    }
+    if !keywords.is_empty() {
+        fields += &format!("   <pdf:Keywords>{}</pdf:Keywords>\n", xml_escape(keywords));
+    }
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+         \x20<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+         \x20 <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+         xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\" xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\">\n\
+         {}   <xmp:CreatorTool>{}</xmp:CreatorTool>\n\
+         \x20 </rdf:Description>\n\
+         \x20</rdf:RDF>\n\
+         </x:xmpmeta>\n\
+         <?xpacket end=\"w\"?>",
+        fields, CREATOR_TOOL
+    )
+}
+
+/// XMLの特殊文字をエスケープ
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_label_ranges() {
+        let nombre = NombreOptions { prefix: "P".to_string(), ..NombreOptions::default() };
+        let pages = [
+            PageLabel::Unnumbered(PAGE_LABEL_WHITE),
+            PageLabel::Numbered(vec![5]),
+            PageLabel::Numbered(vec![6]),
+            PageLabel::Numbered(vec![8]),
+            PageLabel::Unnumbered(PAGE_LABEL_COLOPHON),
+        ];
+        let ranges = page_label_ranges(&pages, &nombre);
+        let summary = ranges.iter().map(|r| (r.page_index, r.style, r.prefix.as_str(), r.start)).collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (0, None, "白紙", 1),
+                (1, Some("D"), "P", 5),
+                (3, Some("D"), "P", 8),
+                (4, None, "奥付", 1),
+            ]
+        );
+
+        // 漢数字・見開きはページごとの文字列
+        let nombre = NombreOptions { numeral_style: "kanji".to_string(), ..NombreOptions::default() };
+        let ranges = page_label_ranges(&[PageLabel::Numbered(vec![1]), PageLabel::Numbered(vec![2, 3])], &nombre);
+        let prefixes = ranges.iter().map(|r| (r.style, r.prefix.as_str())).collect::<Vec<_>>();
+        assert_eq!(prefixes, vec![(None, "一"), (None, "二-三")]);
+    }
+
+    #[test]
+    fn test_document_title() {
+        let info = WorkInfo { title: "作品名".to_string(), version: "3".to_string(), ..WorkInfo::default() };
+        assert_eq!(document_title(Some(&info), DEFAULT_DOCUMENT_TITLE), "作品名　3");
+        assert_eq!(document_title(Some(&WorkInfo::default()), DEFAULT_DOCUMENT_TITLE), DEFAULT_DOCUMENT_TITLE);
+        assert_eq!(text_string("ab").as_str().unwrap(), b"ab");
+        assert_eq!(text_string("あ").as_str().unwrap(), [0xFE, 0xFF, 0x30, 0x42]);
+    }
+
+    #[test]
+    fn test_outline_warnings() {
+        let (doc, _, _) = printpdf::PdfDocument::new("test", printpdf::Mm(100.0), printpdf::Mm(100.0), "Layer 1");
+        doc.add_page(printpdf::Mm(100.0), printpdf::Mm(100.0), "Layer 1");
+        let mut pdf = lopdf::Document::load_mem(&doc.save_to_bytes().unwrap()).unwrap();
+        let nombre = NombreOptions::default();
+        let chapters = [
+            PdfChapter { title: "第1話".to_string(), page: 1 },
+            PdfChapter { title: "第2話".to_string(), page: 9 },
+        ];
+        let mut info = PdfDocumentInfo::new(None, DEFAULT_DOCUMENT_TITLE, &nombre, &chapters);
+        info.add_page(PageLabel::Numbered(vec![1]));
+        info.add_page(PageLabel::Numbered(vec![2]));
+
+        let warnings = info.apply(&mut pdf).unwrap();
+        assert_eq!(warnings, vec!["しおり「第2話」の開始ページ 9 がありません".to_string()]);
+        let outlines = pdf.catalog().unwrap().get(b"Outlines").unwrap().as_reference().unwrap();
+        let count = pdf.get_dictionary(outlines).unwrap().get(b"Count").unwrap().as_i64().unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_two_page_layout() {
+        let layout = |binding: &str, first_number: u32| {
+            let (doc, _, _) = printpdf::PdfDocument::new("test", printpdf::Mm(100.0), printpdf::Mm(100.0), "Layer 1");
+            let mut pdf = lopdf::Document::load_mem(&doc.save_to_bytes().unwrap()).unwrap();
+            let nombre = NombreOptions::default();
+            let mut info = PdfDocumentInfo::new(None, DEFAULT_DOCUMENT_TITLE, &nombre, &[]);
+            info.set_two_page_layout(binding, first_number);
+            info.add_page(PageLabel::Numbered(vec![first_number]));
+            info.apply(&mut pdf).unwrap();
+            let catalog = pdf.catalog().unwrap();
+            let direction = catalog.get(b"ViewerPreferences").unwrap().as_dict().unwrap().get(b"Direction").unwrap();
+            (
+                String::from_utf8(catalog.get(b"PageLayout").unwrap().as_name().unwrap().to_vec()).unwrap(),
+                String::from_utf8(direction.as_name().unwrap().to_vec()).unwrap(),
+            )
+        };
+        assert_eq!(layout("right", 1), ("TwoPageRight".to_string(), "R2L".to_string()));
+        // 偶数ノンブルから始まるときは1ページ目を単独にしない
+        assert_eq!(layout("right", 2), ("TwoPageLeft".to_string(), "R2L".to_string()));
+        assert_eq!(layout("left", 1), ("TwoPageRight".to_string(), "L2R".to_string()));
+    }
+}
@@ -0,0 +1,446 @@
+//! タチミ - 生成ページ（白紙・作品情報・奥付）
+//! 組版は TextCanvas に対して行い、PDFには埋め込みフォント（使う文字だけのサブセット）のテキストとして書く
+//! raster 指定時やフォントを埋め込めない場合は、ページを画像にしてJPEGで埋め込む
+
+use ::image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
+use printpdf::path::PaintMode;
+use printpdf::{Color, Greyscale, Image, IndirectFontRef, Mm, PdfDocumentReference, PdfLayerReference, Pt, Rect, Rgb, TextMatrix};
+
+use super::common::{add_pdf_font, add_placed_image, create_pdf_image, ImagePlacement};
+use crate::processor::colophon::{build_colophon_rows, ColophonRow};
+use crate::processor::color::should_output_grayscale;
+use crate::processor::fonts::{load_font, FontRole, LoadedFont};
+use crate::processor::text_layout::{
+    column_height, draw_vertical_column, line_box_height, text_width, vertical_cells, wrap_horizontal, wrap_vertical,
+    ImageCanvas, TextCanvas, VerticalCell, WRITING_MODE_VERTICAL,
+};
+use crate::processor::types::{ColophonInfo, FontSpec, JpegOptions, WorkInfo};
+
+/// 生成ページの文字の書き方
+pub const GENERATED_TEXT_VECTOR: &str = "vector";
+pub const GENERATED_TEXT_RASTER: &str = "raster";
+
+/// 作品情報・奥付の文字色
+const TEXT_BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+const TEXT_GRAY: Rgba<u8> = Rgba([80, 80, 80, 255]);
+
+/// 生成ページの中身
+#[derive(Debug, Clone, Copy)]
+pub enum GeneratedPage<'a> {
+    /// 白紙（作品情報を印字する場合は指定）
+    White(Option<&'a WorkInfo>),
+    /// 奥付
+    Colophon(&'a ColophonInfo, Option<&'a WorkInfo>),
+}
+
+impl GeneratedPage<'_> {
+    /// 文字を描くページか
+    pub fn has_text(&self) -> bool {
+        !matches!(self, GeneratedPage::White(None))
+    }
+}
+
+/// PDFのページに置く中身（読み込んだ画像または生成ページ）
+pub enum PageContent<'a> {
+    Image(Image),
+    Generated(GeneratedPage<'a>),
+}
+
+/// 生成ページの描画設定（PDF1つにつき1つ）
+pub struct PdfGeneratedPages<'a> {
+    font_spec: Option<&'a FontSpec>,
+    /// テキストで書く場合のフォント（None なら画像にする）
+    vector_font: Option<(LoadedFont, IndirectFontRef)>,
+    color_profile: &'a str,
+    color_mode: &'a str,
+    jpeg_options: &'a JpegOptions,
+}
+
+impl<'a> PdfGeneratedPages<'a> {
+    /// text_mode が "vector" で文字を描く生成ページがある場合は、フォントを読み込んでPDFに埋め込む
+    /// 埋め込めないフォント（TTC・OTCの先頭以外の書体）の場合は画像にする
+    pub fn new(
+        doc: &PdfDocumentReference,
+        has_text: bool,
+        text_mode: &str,
+        font_spec: Option<&'a FontSpec>,
+        color_profile: &'a str,
+        color_mode: &'a str,
+        jpeg_options: &'a JpegOptions,
+    ) -> Result<Self, String> {
+        let vector_font = match (has_text, text_mode) {
+            (true, GENERATED_TEXT_VECTOR) => {
+                let loaded = load_font(font_spec, FontRole::Japanese)?;
+                match add_pdf_font(doc, &loaded) {
+                    Ok(pdf_font) => Some((loaded, pdf_font)),
+                    Err(e) => {
+                        eprintln!("生成ページの文字を画像で埋め込みます: {}", e);
+                        None
+                    }
+                }
+            }
+            (false, _) | (true, GENERATED_TEXT_RASTER) => None,
+            (true, mode) => return Err(format!("生成ページの文字の書き方が不正です: {}", mode)),
+        };
+        Ok(Self { font_spec, vector_font, color_profile, color_mode, jpeg_options })
+    }
+
+    /// ページの中身をレイヤーに置く（width, height: ピクセルサイズ）
+    pub fn add_content(
+        &self,
+        layer: &PdfLayerReference,
+        content: PageContent,
+        width: u32,
+        height: u32,
+        placement: &ImagePlacement,
+        dpi: f32,
+    ) -> Result<(), String> {
+        match content {
+            PageContent::Image(image) => {
+                add_placed_image(layer, image, placement, dpi);
+                Ok(())
+            }
+            PageContent::Generated(page) => self.add_to_layer(layer, page, width, height, placement, dpi),
+        }
+    }
+
+    /// 生成ページをレイヤーに置く（width, height: ページのピクセルサイズ、配置は画像と同じ）
+    pub fn add_to_layer(
+        &self,
+        layer: &PdfLayerReference,
+        page: GeneratedPage,
+        width: u32,
+        height: u32,
+        placement: &ImagePlacement,
+        dpi: f32,
+    ) -> Result<(), String> {
+        let Some((loaded, pdf_font)) = &self.vector_font else {
+            let image = create_generated_page_image(
+                width, height, page, self.font_spec, self.color_profile, self.color_mode, self.jpeg_options,
+            )?;
+            add_placed_image(layer, image, placement, dpi);
+            return Ok(());
+        };
+        if !page.has_text() {
+            return Ok(());
+        }
+
+        let font = loaded.font_ref()?;
+        if let Some((x, y, w, h)) = placement.clip {
+            layer.save_graphics_state();
+            layer.add_rect(Rect::new(Mm(x), Mm(y), Mm(x + w), Mm(y + h)).with_mode(PaintMode::Clip));
+        }
+        let mut canvas = PdfTextCanvas { layer, pdf_font, font: &font, height_px: height as f32, placement, dpi };
+        let result = layout_generated_page(&mut canvas, &font, width, height, page);
+        if placement.clip.is_some() {
+            layer.restore_graphics_state();
+        }
+        result
+    }
+}
+
+/// 生成ページを画像にしてPDF用Imageを作成
+/// 文字は黒・グレーのみのため、カラーモードに従ってグレースケールで埋め込む
+pub fn create_generated_page_image(
+    width: u32,
+    height: u32,
+    page: GeneratedPage,
+    font_spec: Option<&FontSpec>,
+    color_profile: &str,
+    color_mode: &str,
+    jpeg_options: &JpegOptions,
+) -> Result<Image, String> {
+    let mut img: RgbaImage = ImageBuffer::from_pixel(width, height, Rgba([255u8, 255u8, 255u8, 255u8]));
+
+    if page.has_text() {
+        let loaded = load_font(font_spec, FontRole::Japanese)?;
+        let font = loaded.font_ref()?;
+        layout_generated_page(&mut ImageCanvas { img: &mut img, font: &font }, &font, width, height, page)?;
+    }
+
+    let img = DynamicImage::ImageRgba8(img);
+    let img = if should_output_grayscale(&img, color_mode, color_profile) {
+        DynamicImage::ImageLuma8(img.to_luma8())
+    } else {
+        DynamicImage::ImageRgb8(img.to_rgb8())
+    };
+    create_pdf_image(&img, jpeg_options).ok_or_else(|| "生成ページの作成に失敗".to_string())
+}
+
+/// PDFへのテキスト描画
+/// ピクセル座標を画像と同じ配置（placement, dpi）でページのmmに換算する
+struct PdfTextCanvas<'a> {
+    layer: &'a PdfLayerReference,
+    pdf_font: &'a IndirectFontRef,
+    font: &'a FontRef<'a>,
+    height_px: f32,
+    placement: &'a ImagePlacement,
+    dpi: f32,
+}
+
+impl PdfTextCanvas<'_> {
+    /// ページ上の位置（mm、左下原点）
+    fn point(&self, x: f32, y: f32) -> (Mm, Mm) {
+        let mm_per_px = 25.4 / self.dpi * self.placement.scale;
+        (
+            Mm(self.placement.x_mm + x * mm_per_px),
+            Mm(self.placement.y_mm + (self.height_px - y) * mm_per_px),
+        )
+    }
+
+    /// 文字サイズ（pt）
+    /// PxScale はアセント - ディセントの高さのため、PDFの文字サイズ（全角の幅）に直す
+    fn font_size_pt(&self, scale: PxScale) -> f32 {
+        let em_px = scale.y * self.font.units_per_em().unwrap_or(1000.0) / self.font.height_unscaled();
+        em_px * 72.0 / self.dpi * self.placement.scale
+    }
+
+    /// テキスト行列を指定して1行書く（origin: ベースラインの書き始め）
+    fn write(&self, text: &str, origin: (Mm, Mm), scale: PxScale, matrix: [f32; 4], color: Rgba<u8>) {
+        let (x, y) = (Pt::from(origin.0).0, Pt::from(origin.1).0);
+        self.layer.set_fill_color(pdf_color(color));
+        self.layer.begin_text_section();
+        self.layer.set_font(self.pdf_font, self.font_size_pt(scale));
+        self.layer.set_text_matrix(TextMatrix::Raw([matrix[0], matrix[1], matrix[2], matrix[3], x, y]));
+        self.layer.write_text(text, self.pdf_font);
+        self.layer.end_text_section();
+    }
+}
+
+impl TextCanvas for PdfTextCanvas<'_> {
+    fn draw_text(&mut self, text: &str, x: f32, y: f32, scale: PxScale, max_width: Option<f32>, color: Rgba<u8>) {
+        let width = text_width(self.font, scale, text);
+        let squeeze = max_width.filter(|max| width > *max).map_or(1.0, |max| max / width);
+        let origin = self.point(x, y + self.font.as_scaled(scale).ascent());
+        self.write(text, origin, scale, [squeeze, 0.0, 0.0, 1.0], color);
+    }
+
+    fn draw_text_sideways(&mut self, text: &str, x: f32, y: f32, scale: PxScale, color: Rgba<u8>) {
+        // 時計回りに回すと、ベースラインは箱の右端からアセント分内側（左端からディセント分）の縦線になる
+        let origin = self.point(x - self.font.as_scaled(scale).descent(), y);
+        self.write(text, origin, scale, [0.0, -1.0, 1.0, 0.0], color);
+    }
+
+    fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: Rgba<u8>) {
+        let (left, bottom) = self.point(x, y + height);
+        let (right, top) = self.point(x + width, y);
+        self.layer.set_fill_color(pdf_color(color));
+        self.layer.add_rect(Rect::new(left, bottom, right, top).with_mode(PaintMode::Fill));
+    }
+}
+
+/// 文字色（無彩色はグレースケール）
+fn pdf_color(color: Rgba<u8>) -> Color {
+    let channel = |c: u8| c as f32 / 255.0;
+    if color[0] == color[1] && color[1] == color[2] {
+        Color::Greyscale(Greyscale::new(channel(color[0]), None))
+    } else {
+        Color::Rgb(Rgb::new(channel(color[0]), channel(color[1]), channel(color[2]), None))
+    }
+}
+
+/// 生成ページを組む
+fn layout_generated_page<C: TextCanvas>(
+    canvas: &mut C,
+    font: &FontRef,
+    width: u32,
+    height: u32,
+    page: GeneratedPage,
+) -> Result<(), String> {
+    match page {
+        GeneratedPage::White(None) => {}
+        GeneratedPage::White(Some(info)) if info.writing_mode == WRITING_MODE_VERTICAL => {
+            layout_work_info_vertical(canvas, font, width, height, info)
+        }
+        GeneratedPage::White(Some(info)) => layout_work_info_horizontal(canvas, font, width, height, info),
+        GeneratedPage::Colophon(colophon, info) => {
+            layout_colophon(canvas, font, width, height, &build_colophon_rows(colophon, info)?)
+        }
+    }
+    Ok(())
+}
+
+/// 著者表記（作画・原作を分ける場合は2行）
+pub fn work_info_author_lines(info: &WorkInfo) -> Vec<String> {
+    match info.author_type {
+        0 if !info.author1.is_empty() => vec![format!("著　{}", info.author1)],
+        0 => Vec::new(),
+        1 => {
+            let mut parts = Vec::new();
+            if !info.author1.is_empty() {
+                parts.push(format!("作画　{}", info.author1));
+            }
+            if !info.author2.is_empty() {
+                parts.push(format!("原作　{}", info.author2));
+            }
+            parts
+        }
+        _ if !info.author1.is_empty() => vec![info.author1.clone()],
+        _ => Vec::new(),
+    }
+}
+
+/// タイトルブロック（タイトル・サブタイトル・巻数）の文字と倍率（base_size に対する）
+fn work_info_title_block(info: &WorkInfo) -> Vec<(&str, f32)> {
+    [(info.title.as_str(), 2.0), (info.subtitle.as_str(), 1.0), (info.version.as_str(), 1.2)]
+        .into_iter()
+        .filter(|(text, _)| !text.is_empty())
+        .collect()
+}
+
+/// 横書きの作品情報: タイトルブロックを上寄り（黄金比）の中央に、レーベルをその上、著者をページ中央の上に置く
+/// 版面の幅に収まらない行は折り返す
+fn layout_work_info_horizontal<C: TextCanvas>(canvas: &mut C, font: &FontRef, width: u32, height: u32, info: &WorkInfo) {
+    let max_width = width as f32 * 0.8;
+
+    let base_size = height as f32 / 30.0;
+    let author_size = base_size * 0.85;
+    let label_size = base_size * 0.7;
+
+    let page_center_y = height as f32 / 2.0;
+    let golden_ratio = 0.35;
+    let title_center_y = height as f32 * golden_ratio;
+
+    // 各行を (文字, サイズ, 色) に折り返して並べる
+    let wrap = |text: &str, size: f32, color: Rgba<u8>| -> Vec<(String, f32, Rgba<u8>)> {
+        wrap_horizontal(font, PxScale::from(size), text, max_width)
+            .into_iter()
+            .map(|line| (line, size, color))
+            .collect()
+    };
+
+    // タイトルブロック描画
+    let title_block: Vec<(String, f32, Rgba<u8>)> = work_info_title_block(info)
+        .into_iter()
+        .flat_map(|(text, ratio)| wrap(text, base_size * ratio, TEXT_BLACK))
+        .collect();
+    let title_line_height = 1.6;
+    let title_total_height: f32 = title_block.iter().map(|(_, size, _)| size * title_line_height).sum();
+    let title_start_y = title_center_y - title_total_height / 2.0;
+    draw_centered_lines(canvas, font, width, &title_block, title_start_y, title_line_height);
+
+    // 上部ブロック描画（レーベル）
+    if !info.label.is_empty() {
+        let top_block = wrap(&info.label, label_size, TEXT_GRAY);
+        let top_spacing = base_size * 3.0;
+        let top_line_height = 1.4;
+        let top_total_height: f32 = top_block.iter().map(|(_, size, _)| size * top_line_height).sum();
+        draw_centered_lines(canvas, font, width, &top_block, title_start_y - top_spacing - top_total_height, top_line_height);
+    }
+
+    // 下部ブロック描画（著者）
+    let author_lines = work_info_author_lines(info);
+    if !author_lines.is_empty() {
+        let bottom_block = wrap(&author_lines.join("　　"), author_size, TEXT_GRAY);
+        let bottom_margin = base_size * 2.5;
+        let bottom_line_height = 1.4;
+        let bottom_total_height: f32 = bottom_block.iter().map(|(_, size, _)| size * bottom_line_height).sum();
+        let bottom_y = page_center_y - bottom_margin - bottom_total_height;
+        draw_centered_lines(canvas, font, width, &bottom_block, bottom_y, bottom_line_height);
+    }
+}
+
+/// 横書きの行を左右中央に揃えて上から順に描く
+fn draw_centered_lines<C: TextCanvas>(
+    canvas: &mut C,
+    font: &FontRef,
+    width: u32,
+    lines: &[(String, f32, Rgba<u8>)],
+    top: f32,
+    line_height: f32,
+) {
+    let mut y = top;
+    for (text, font_size, color) in lines {
+        let scale = PxScale::from(*font_size);
+        let x = ((width as f32 - text_width(font, scale, text)) / 2.0).max(0.0);
+        canvas.draw_text(text, x, y, scale, None, *color);
+        y += font_size * line_height;
+    }
+}
+
+/// 縦書きの作品情報: 右からレーベル・タイトル・サブタイトル・巻数の順に列を並べて天に揃え、
+/// 著者は左側に地に揃えて置く（全体を左右中央に配置）
+/// 版面の高さに収まらない列は折り返して左の列に送る
+fn layout_work_info_vertical<C: TextCanvas>(canvas: &mut C, font: &FontRef, width: u32, height: u32, info: &WorkInfo) {
+    let base_size = height as f32 / 30.0;
+    let column_spacing = 1.5;
+
+    let top = height as f32 * 0.15;
+    let bottom = height as f32 * 0.85;
+    let max_height = bottom - top;
+
+    // 列を (マス, サイズ, 色, 地に揃えるか) で右から並べる
+    let mut columns: Vec<(Vec<VerticalCell>, f32, Rgba<u8>, bool)> = Vec::new();
+    let mut push = |text: &str, size: f32, color: Rgba<u8>, align_bottom: bool| {
+        let scale = PxScale::from(size);
+        for column in wrap_vertical(font, scale, vertical_cells(text), max_height) {
+            columns.push((column, size, color, align_bottom));
+        }
+    };
+    if !info.label.is_empty() {
+        push(&info.label, base_size * 0.7, TEXT_GRAY, false);
+    }
+    for (text, ratio) in work_info_title_block(info) {
+        push(text, base_size * ratio, TEXT_BLACK, false);
+    }
+    for line in work_info_author_lines(info) {
+        push(&line, base_size * 0.85, TEXT_GRAY, true);
+    }
+
+    let total_width: f32 = columns.iter().map(|(_, size, _, _)| size * column_spacing).sum();
+    let mut x = (width as f32 + total_width) / 2.0;
+    for (cells, size, color, align_bottom) in &columns {
+        let scale = PxScale::from(*size);
+        let column_width = size * column_spacing;
+        let column_top = if *align_bottom { bottom - column_height(font, scale, cells) } else { top };
+        draw_vertical_column(canvas, font, scale, cells, x - column_width / 2.0, column_top, *color);
+        x -= column_width;
+    }
+}
+
+/// 奥付: ページ幅の6割の版面を左右中央に取り、地から1割の位置に下端を揃えて行を積む
+/// 見出しは大きめ、ラベル付きの行は値を版面の右端に揃え、罫線は版面の幅いっぱいに引く
+fn layout_colophon<C: TextCanvas>(canvas: &mut C, font: &FontRef, width: u32, height: u32, rows: &[ColophonRow]) {
+    let base_size = height as f32 / 60.0;
+    let heading_size = base_size * 1.6;
+    let line_height = 1.7;
+    let rule_height = base_size * 1.2;
+    let rule_thickness = (height as f32 / 1500.0).round().max(1.0);
+
+    let block_width = width as f32 * 0.6;
+    let block_left = (width as f32 - block_width) / 2.0;
+    let block_right = block_left + block_width;
+
+    let row_height = |row: &ColophonRow| match row {
+        ColophonRow::Heading(_) => heading_size * line_height,
+        ColophonRow::Rule => rule_height,
+        _ => base_size * line_height,
+    };
+    let total_height: f32 = rows.iter().map(row_height).sum();
+    let mut y = height as f32 * 0.9 - total_height;
+
+    for row in rows {
+        let row_h = row_height(row);
+        match row {
+            ColophonRow::Heading(text) | ColophonRow::Text(text) => {
+                let size = if matches!(row, ColophonRow::Heading(_)) { heading_size } else { base_size };
+                let scale = PxScale::from(size);
+                let text_y = y + (row_h - line_box_height(font, scale)) / 2.0;
+                canvas.draw_text(text, block_left, text_y, scale, None, TEXT_BLACK);
+            }
+            ColophonRow::Field { label, value } => {
+                let scale = PxScale::from(base_size);
+                let text_y = y + (row_h - line_box_height(font, scale)) / 2.0;
+                canvas.draw_text(label, block_left, text_y, scale, None, TEXT_BLACK);
+                let value_x = (block_right - text_width(font, scale, value)).max(block_left);
+                canvas.draw_text(value, value_x, text_y, scale, None, TEXT_BLACK);
+            }
+            ColophonRow::Rule => {
+                let rule_y = y + (row_h - rule_thickness) / 2.0;
+                canvas.fill_rect(block_left, rule_y, block_width, rule_thickness, TEXT_BLACK);
+            }
+        }
+        y += row_h;
+    }
+}
@@ -2,6 +2,8 @@
 //! PDF生成機能を提供
 
 pub mod common;
+pub mod document;
+pub mod generated;
 pub mod single;
 pub mod spread;
 
@@ -1,64 +1,96 @@
 //! タチミ - 単ページPDF生成
 //! 各画像を1ページとしたPDFを生成
 
-use ::image::GenericImageView;
-use printpdf::{BuiltinFont, ImageTransform, Mm, PdfDocument};
-use std::fs::File;
-use std::io::BufWriter;
+use printpdf::{Mm, PdfDocument};
 use std::path::Path;
 use tauri::Emitter;
 
 use super::common::{
-    create_pdf_image, create_pdf_image_from_jpeg_file, get_image_dimensions,
-    get_nombre_font_size_pt, px_to_mm, unique_output_path, DEFAULT_DPI,
+    begin_page, get_image_dimensions, load_and_create_pdf_image, place_image_in_box, px_to_mm, save_pdf,
+    unique_output_path, ImagePlacement, PdfImageProfiles, PdfLayout, PdfNombre,
 };
-use crate::processor::jpeg::is_jpeg_file;
-use crate::processor::image_loader::load_image;
-
-/// 単ページPDF生成（画像サイズ = ページサイズ）
+use super::document::{PageLabel, PdfDocumentInfo, DEFAULT_DOCUMENT_TITLE, PAGE_LABEL_COLOPHON};
+use super::generated::{GeneratedPage, PageContent, PdfGeneratedPages};
+use crate::processor::color::target_icc_profile;
+use crate::processor::nombre::nombre_placement_edge;
+use crate::processor::page_side::{page_side, PageSide};
+use crate::processor::types::{PdfOptions, PdfResult};
+
+/// 単ページPDF生成
+/// 設定は options、余白・ページサイズ・枠は layout から取る
+/// page_size_mm 指定時はそのサイズのページに page_fit で配置、None なら画像サイズ = ページサイズ
+/// ページを仕上がりとして frames の裁ち落とし・トンボ分だけ外側を広げ、TrimBox等を設定する
+/// 各ページは番号の奇偶と binding で左右を判定し、left_page / right_page の画像のずらし量・ノンブル位置を使う
+/// ビューアでは binding の向きに、1ページ目を単独にした見開きで表示されるようにする
+/// colophon 指定時は末尾に先頭画像と同じサイズの奥付ページ（ノンブルなし）を追加する
+/// 奥付の文字は generated_text が "vector" ならPDFのテキスト、"raster" なら画像で埋め込む
+/// ノンブルとページラベルは先頭の画像を nombre_start_number として数え、chapters の開始ページにしおりを付ける
 pub fn generate_single_pdf(
     app_handle: &tauri::AppHandle,
     input_folder: &str,
     output_path: &str,
     files: &[String],
-    padding_mm: f32,
-    add_nombre: bool,
-    nombre_size: &str,
-) -> Result<String, String> {
+    options: &PdfOptions,
+    layout: &PdfLayout,
+) -> Result<PdfResult, String> {
+    let PdfLayout { padding_mm, page_size_mm, .. } = *layout;
+    let frames = &layout.frames;
+    let work_info = options.work_info.as_ref();
+    let colophon = options.colophon.as_ref();
+    let nombre = &options.nombre;
+    let (color_profile, color_mode) = (options.color_profile.as_str(), options.color_mode.as_str());
+    let jpeg_options = &options.jpeg;
+    let dpi_override = options.dpi_override;
+    let binding = options.binding.as_str();
+
     let input_path = Path::new(input_folder);
     let total = files.len();
-    let dpi = DEFAULT_DPI;
 
     if total == 0 {
         return Err("処理するファイルがありません".to_string());
     }
 
-    // 最初の画像でドキュメントを初期化
+    // 最初のページサイズでドキュメントを初期化
     let first_file = input_path.join(&files[0]);
-    let (first_w, first_h) = get_image_dimensions(&first_file)?;
-
-    let first_page_width_mm = px_to_mm(first_w, dpi);
-    let first_page_height_mm = px_to_mm(first_h, dpi);
-    let first_page_width_with_padding = first_page_width_mm + padding_mm * 2.0;
-    let first_page_height_with_padding = first_page_height_mm + padding_mm * 2.0;
+    let (first_page_width, first_page_height) = match page_size_mm {
+        Some(size) => size,
+        None => {
+            let (first_w, first_h, dpi) = get_image_dimensions(&first_file, dpi_override)?;
+            (px_to_mm(first_w, dpi) + padding_mm * 2.0, px_to_mm(first_h, dpi) + padding_mm * 2.0)
+        }
+    };
 
-    let (doc, page1, layer1) = PdfDocument::new(
-        "タチミ出力",
-        Mm(first_page_width_with_padding),
-        Mm(first_page_height_with_padding),
+    let (media_width, media_height) = frames.media_size_mm(first_page_width, first_page_height);
+    let (doc, first_page, layer1) = PdfDocument::new(
+        DEFAULT_DOCUMENT_TITLE,
+        Mm(media_width),
+        Mm(media_height),
         "Layer 1"
     );
-    let mut current_layer = doc.get_page(page1).get_layer(layer1);
+    let mut current_layer = doc.get_page(first_page).get_layer(layer1);
+    let mut current_page = first_page;
+    let mut document_info = PdfDocumentInfo::new(work_info, DEFAULT_DOCUMENT_TITLE, nombre, &options.chapters);
+    let mut image_profiles = PdfImageProfiles::default();
+    document_info.set_two_page_layout(binding, options.nombre_start_number);
 
     // ノンブル用フォント
-    let nombre_font = if add_nombre && padding_mm > 0.0 {
-        doc.add_builtin_font(BuiltinFont::Helvetica).ok()
-    } else {
-        None
-    };
-    let nombre_font_size_pt = get_nombre_font_size_pt(nombre_size);
+    let pdf_nombre = PdfNombre::new(&doc, options.add_nombre, padding_mm, &options.nombre_size, nombre)?;
+
+    // 奥付の描画設定
+    let generated = PdfGeneratedPages::new(
+        &doc,
+        colophon.is_some(),
+        &options.generated_text,
+        options.work_info_font.as_ref(),
+        color_profile,
+        color_mode,
+        jpeg_options,
+    )?;
+
+    let page_count = total + colophon.is_some() as usize;
+    for i in 0..page_count {
+        let filename = files.get(i).map(String::as_str).unwrap_or("奥付");
 
-    for (i, filename) in files.iter().enumerate() {
         // キャンセルチェック
         if crate::CANCEL_FLAG.load(std::sync::atomic::Ordering::Relaxed) {
             return Err("処理がキャンセルされました".to_string());
@@ -68,77 +100,92 @@ pub fn generate_single_pdf(
         let _ = app_handle.emit("progress", crate::ProgressPayload {
             current: i + 1,
             total,
-            filename: filename.clone(),
+            filename: filename.to_string(),
             phase: format!("PDF生成: 画像読み込み中 ({}/{})", i + 1, total),
             in_progress: 0,
         });
 
-        let file_path = input_path.join(filename);
-
-        // 画像読み込み（JPEG高速パス対応）
-        let (pdf_image, img_w, img_h) = if is_jpeg_file(&file_path) {
-            match create_pdf_image_from_jpeg_file(&file_path) {
-                Some((img, w, h)) => (img, w, h),
-                None => {
-                    eprintln!("PDF生成: JPEGファイル読み込みエラー: {}", filename);
-                    continue;
-                }
-            }
-        } else {
-            let img = match load_image(&file_path) {
-                Ok(img) => img,
-                Err(e) => {
-                    eprintln!("PDF生成: 画像読み込みエラー ({}): {}", filename, e);
-                    continue;
-                }
-            };
-            let (w, h) = img.dimensions();
-            match create_pdf_image(&img) {
-                Some(pdf_img) => (pdf_img, w, h),
-                None => {
-                    eprintln!("PDF生成: PDF画像変換エラー: {}", filename);
-                    continue;
-                }
+        // 画像読み込み（JPEG高速パス対応、解像度は画像ごと、奥付は先頭画像に合わせる）
+        let loaded = match colophon.filter(|_| i == total) {
+            Some(colophon) => get_image_dimensions(&first_file, dpi_override)
+                .map(|(w, h, dpi)| (PageContent::Generated(GeneratedPage::Colophon(colophon, work_info)), w, h, dpi)),
+            None => load_and_create_pdf_image(
+                &input_path.join(filename), color_profile, color_mode, jpeg_options, dpi_override, &mut image_profiles,
+            )
+            .map(|(img, w, h, dpi)| (PageContent::Image(img), w, h, dpi)),
+        };
+        let (content, img_w, img_h, dpi) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                eprintln!("PDF生成: 画像読み込みエラー ({}): {}", filename, e);
+                continue;
             }
         };
 
         let _ = app_handle.emit("progress", crate::ProgressPayload {
             current: i + 1,
             total,
-            filename: filename.clone(),
+            filename: filename.to_string(),
             phase: format!("PDF生成: ページ追加中 ({}/{})", i + 1, total),
             in_progress: 0,
         });
 
-        let page_width_mm = px_to_mm(img_w, dpi);
-        let page_height_mm = px_to_mm(img_h, dpi);
-        let page_width_with_padding = page_width_mm + padding_mm * 2.0;
-        let page_height_with_padding = page_height_mm + padding_mm * 2.0;
+        // ノンブルと左右ページの判定
+        let (nombre_number, side) = page_number_and_side(i, options.nombre_start_number, binding);
+        let side_options = match side {
+            PageSide::Left => &options.left_page,
+            PageSide::Right => &options.right_page,
+        };
+
+        // ページサイズと画像配置（プリセット指定時は余白を除いた枠内に配置）
+        let img_width_mm = px_to_mm(img_w, dpi);
+        let img_height_mm = px_to_mm(img_h, dpi);
+        let (page_width_mm, page_height_mm, placement) = match page_size_mm {
+            Some((width, height)) => (
+                width,
+                height,
+                place_image_in_box(
+                    img_width_mm, img_height_mm,
+                    padding_mm, padding_mm,
+                    width - padding_mm * 2.0, height - padding_mm * 2.0,
+                    &options.page_fit,
+                ),
+            ),
+            None => (
+                img_width_mm + padding_mm * 2.0,
+                img_height_mm + padding_mm * 2.0,
+                ImagePlacement::unscaled(padding_mm, padding_mm),
+            ),
+        };
 
         // 2ページ目以降は新しいページを追加
         if i > 0 {
-            let (page, layer) = doc.add_page(Mm(page_width_with_padding), Mm(page_height_with_padding), "Layer 1");
+            let (media_width, media_height) = frames.media_size_mm(page_width_mm, page_height_mm);
+            let (page, layer) = doc.add_page(
+                Mm(media_width),
+                Mm(media_height),
+                "Layer 1",
+            );
             current_layer = doc.get_page(page).get_layer(layer);
+            current_page = page;
         }
+        begin_page(&doc, current_page, &current_layer, page_width_mm, page_height_mm, frames);
 
         // 画像配置
-        let transform = ImageTransform {
-            translate_x: Some(Mm(padding_mm)),
-            translate_y: Some(Mm(padding_mm)),
-            scale_x: Some(1.0),
-            scale_y: Some(1.0),
-            dpi: Some(dpi),
-            ..Default::default()
-        };
-        pdf_image.add_to_layer(current_layer.clone(), transform);
-
-        // ノンブル描画
-        if let Some(ref font) = nombre_font {
-            let page_num = (i + 1).to_string();
-            let text_x = page_width_with_padding / 2.0 - (page_num.len() as f32 * nombre_font_size_pt * 0.3 / 2.0);
-            let text_y = padding_mm / 2.0 - nombre_font_size_pt * 0.35 / 2.0;
-            current_layer.use_text(&page_num, nombre_font_size_pt, Mm(text_x), Mm(text_y), font);
+        let placement = placement.shifted(side_options.offset_x_mm, side_options.offset_y_mm);
+        generated.add_content(&current_layer, content, img_w, img_h, &placement, dpi)?;
+
+        // ノンブル描画（奥付には入れない）
+        if let Some(pdf_nombre) = pdf_nombre.as_ref().filter(|_| i < total) {
+            let edge = nombre_placement_edge(nombre, side, &side_options.nombre_position);
+            let area = (padding_mm, page_width_mm - padding_mm);
+            pdf_nombre.draw(&current_layer, nombre_number, area, page_height_mm, padding_mm, edge);
         }
+        document_info.add_page(if i < total {
+            PageLabel::Numbered(vec![nombre_number])
+        } else {
+            PageLabel::Unnumbered(PAGE_LABEL_COLOPHON)
+        });
     }
 
     // PDF保存
@@ -151,12 +198,29 @@ pub fn generate_single_pdf(
     });
 
     let actual_path = unique_output_path(output_path);
-    let file = File::create(&actual_path)
-        .map_err(|e| format!("PDFファイルの作成に失敗: {}", e))?;
-    let mut writer = BufWriter::new(file);
+    let warnings = save_pdf(doc, &actual_path, &image_profiles, target_icc_profile(color_profile), &document_info)?;
 
-    doc.save(&mut writer)
-        .map_err(|e| format!("PDFの保存に失敗: {}", e))?;
+    Ok(PdfResult { output_path: actual_path, warnings })
+}
 
-    Ok(actual_path)
+/// i番目（0始まり）のページのノンブルと左右（ノンブルの奇偶と binding で判定）
+fn page_number_and_side(index: usize, nombre_start_number: u32, binding: &str) -> (u32, PageSide) {
+    let number = nombre_start_number + index as u32;
+    (number, page_side(number, binding))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_number_and_side() {
+        // 右綴じ・1始まり: 1ページ目は左
+        assert_eq!(page_number_and_side(0, 1, "right"), (1, PageSide::Left));
+        assert_eq!(page_number_and_side(1, 1, "right"), (2, PageSide::Right));
+        // 偶数始まりは1ページ目から左右が入れ替わる
+        assert_eq!(page_number_and_side(0, 4, "right"), (4, PageSide::Right));
+        assert_eq!(page_number_and_side(1, 4, "right"), (5, PageSide::Left));
+        assert_eq!(page_number_and_side(0, 4, "left"), (4, PageSide::Left));
+    }
 }
@@ -1,38 +1,56 @@
 //! タチミ - 見開きPDF生成
 //! 2ページずつ見開きで配置したPDFを生成
 
-use ::image::GenericImageView;
-use printpdf::{BuiltinFont, Image, ImageTransform, Mm, PdfDocument};
-use std::fs::File;
-use std::io::BufWriter;
+use printpdf::{Mm, PdfDocument};
 use std::path::Path;
 use tauri::Emitter;
 
 use super::common::{
-    create_pdf_image, create_pdf_image_from_jpeg_file, create_white_page_image,
-    get_image_dimensions, get_nombre_font_size_pt, px_to_mm, unique_output_path, DEFAULT_DPI,
+    begin_page, get_image_dimensions, load_and_create_pdf_image, place_image_in_box, px_to_mm, save_pdf,
+    unique_output_path, ImagePlacement, PdfImageProfiles, PdfLayout, PdfNombre,
 };
-use crate::processor::jpeg::is_jpeg_file;
-use crate::processor::image_loader::load_image;
-use crate::processor::types::WorkInfo;
+use super::document::{PageLabel, PdfDocumentInfo, PAGE_LABEL_COLOPHON, PAGE_LABEL_WHITE};
+use super::generated::{GeneratedPage, PageContent, PdfGeneratedPages};
+use crate::processor::color::target_icc_profile;
+use crate::processor::nombre::nombre_placement_edge;
+use crate::processor::page_side::{spread_lead_side, PageSide};
+use crate::processor::types::{PdfOptions, PdfResult};
+
+/// 見開きPDFの文書タイトル（作品情報がないとき）
+const SPREAD_DOCUMENT_TITLE: &str = "タチミ出力（見開き）";
 
 /// 見開きPDF生成
+/// 設定は options、余白・ページサイズ・枠は layout から取る
+/// page_size_mm 指定時は1ページ分のサイズ×2 + ノドの見開きに page_fit で配置、None なら画像サイズから計算
+/// 見開き全体を仕上がりとして frames の裁ち落とし・トンボ分だけ外側を広げ、TrimBox等を設定する
+/// 2ページのうち読む順で先のページを、binding が "right"（右綴じ）なら右、"left"（左綴じ）なら左に置く
+/// 左右のページにはそれぞれ left_page / right_page の画像のずらし量・ノンブル位置を使う
+/// colophon 指定時は最後の画像の次のページに、先頭画像と同じサイズの奥付（ノンブルなし）を置く
+/// 作品情報・奥付の文字は generated_text が "vector" ならPDFのテキスト、"raster" なら画像で埋め込む
+/// ノンブルは先頭の画像を nombre_start_number として数え、ページラベルは見開きに含まれる番号（例: "2-3"）にする
+/// chapters の開始ページを含む見開きにしおりを付ける
 pub fn generate_spread_pdf(
     app_handle: &tauri::AppHandle,
     input_folder: &str,
     output_path: &str,
     files: &[String],
-    padding_mm: f32,
-    gutter_mm: f32,
-    add_white_page: bool,
-    print_work_info: bool,
-    work_info: Option<&WorkInfo>,
-    add_nombre: bool,
-    nombre_size: &str,
-) -> Result<String, String> {
+    options: &PdfOptions,
+    layout: &PdfLayout,
+) -> Result<PdfResult, String> {
+    let PdfLayout { padding_mm, gutter_mm, page_size_mm, .. } = *layout;
+    let frames = &layout.frames;
+    let add_white_page = options.add_white_page;
+    let work_info = options.work_info.as_ref();
+    let colophon = options.colophon.as_ref();
+    let nombre = &options.nombre;
+    let (color_profile, color_mode) = (options.color_profile.as_str(), options.color_mode.as_str());
+    let jpeg_options = &options.jpeg;
+    let dpi_override = options.dpi_override;
+    let (left_page, right_page) = (&options.left_page, &options.right_page);
+    let page_fit = options.page_fit.as_str();
+
     let input_path = Path::new(input_folder);
     let total = files.len();
-    let dpi = DEFAULT_DPI;
 
     if total == 0 {
         return Err("処理するファイルがありません".to_string());
@@ -40,38 +58,56 @@ pub fn generate_spread_pdf(
 
     // 最初の画像でサイズを取得
     let first_file = input_path.join(&files[0]);
-    let (first_w, first_h) = get_image_dimensions(&first_file)?;
+    let (first_w, first_h, first_dpi) = get_image_dimensions(&first_file, dpi_override)?;
 
-    let first_width_mm = px_to_mm(first_w, dpi);
-    let first_height_mm = px_to_mm(first_h, dpi);
+    let first_width_mm = px_to_mm(first_w, first_dpi);
+    let first_height_mm = px_to_mm(first_h, first_dpi);
 
-    // 初期ページサイズ（見開き: 画像×2 + ノド + 余白）
-    let init_page_width = first_width_mm * 2.0 + gutter_mm + padding_mm * 2.0;
-    let init_page_height = first_height_mm + padding_mm * 2.0;
+    // 初期ページサイズ（見開き: ページ×2 + ノド、画像サイズ基準の場合は + 余白）
+    let (init_page_width, init_page_height) = match page_size_mm {
+        Some((width, height)) => (width * 2.0 + gutter_mm, height),
+        None => (first_width_mm * 2.0 + gutter_mm + padding_mm * 2.0, first_height_mm + padding_mm * 2.0),
+    };
 
-    let (doc, page1, layer1) = PdfDocument::new(
-        "タチミ出力（見開き）",
-        Mm(init_page_width),
-        Mm(init_page_height),
+    let (media_width, media_height) = frames.media_size_mm(init_page_width, init_page_height);
+    let (doc, first_page, layer1) = PdfDocument::new(
+        SPREAD_DOCUMENT_TITLE,
+        Mm(media_width),
+        Mm(media_height),
         "Layer 1"
     );
-    let mut current_layer = doc.get_page(page1).get_layer(layer1);
+    let mut current_layer = doc.get_page(first_page).get_layer(layer1);
+    let mut current_page = first_page;
+    let mut document_info = PdfDocumentInfo::new(work_info, SPREAD_DOCUMENT_TITLE, nombre, &options.chapters);
+    let mut image_profiles = PdfImageProfiles::default();
 
     // ノンブル用フォント
-    let nombre_font = if add_nombre && padding_mm > 0.0 {
-        doc.add_builtin_font(BuiltinFont::Helvetica).ok()
-    } else {
-        None
-    };
-    let nombre_font_size_pt = get_nombre_font_size_pt(nombre_size);
+    let pdf_nombre = PdfNombre::new(&doc, options.add_nombre, padding_mm, &options.nombre_size, nombre)?;
+
+    // 白紙ページ・奥付の描画設定
+    let printed_info = work_info.filter(|_| add_white_page && options.print_work_info);
+    let generated = PdfGeneratedPages::new(
+        &doc,
+        printed_info.is_some() || colophon.is_some(),
+        &options.generated_text,
+        options.work_info_font.as_ref(),
+        color_profile,
+        color_mode,
+        jpeg_options,
+    )?;
+
+    let lead_side = spread_lead_side(&options.binding);
+    let trail_side = lead_side.opposite();
 
     let mut is_first_page = true;
-    let effective_total = if add_white_page { total + 1 } else { total };
+    // 奥付は最後の画像の次のページ（ページ番号 total）として扱う
+    let page_total = total + colophon.is_some() as usize;
+    let effective_total = if add_white_page { page_total + 1 } else { page_total };
     let spread_total = (effective_total + 1) / 2;
     let mut spread_index = 0;
     let mut file_idx: i32 = if add_white_page { -1 } else { 0 };
 
-    while (file_idx as usize) < total || (add_white_page && file_idx == -1) {
+    while (file_idx as usize) < page_total || (add_white_page && file_idx == -1) {
         // キャンセルチェック
         if crate::CANCEL_FLAG.load(std::sync::atomic::Ordering::Relaxed) {
             return Err("処理がキャンセルされました".to_string());
@@ -89,80 +125,47 @@ pub fn generate_spread_pdf(
             in_progress: 0,
         });
 
-        // 右ページを取得
-        let (pdf_right, right_w, right_h): (Image, u32, u32) = if file_idx == -1 {
+        // 先のページ（読む順）を取得（解像度は画像ごと、白紙ページは先頭画像に合わせる）
+        let (lead_content, lead_px_w, lead_px_h, lead_dpi) = if file_idx == -1 {
             // 白紙ページ
-            match create_white_page_image(first_w, first_h, work_info, print_work_info) {
-                Some(img) => (img, first_w, first_h),
-                None => {
+            (PageContent::Generated(GeneratedPage::White(printed_info)), first_w, first_h, first_dpi)
+        } else if let Some(colophon) = colophon.filter(|_| file_idx as usize == total) {
+            (PageContent::Generated(GeneratedPage::Colophon(colophon, work_info)), first_w, first_h, first_dpi)
+        } else {
+            let lead_file = input_path.join(&files[file_idx as usize]);
+            let loaded = load_and_create_pdf_image(
+                &lead_file, color_profile, color_mode, jpeg_options, dpi_override, &mut image_profiles,
+            );
+            match loaded {
+                Ok((img, w, h, dpi)) => (PageContent::Image(img), w, h, dpi),
+                Err(_) => {
                     file_idx += 2;
                     continue;
                 }
             }
-        } else {
-            let right_file = input_path.join(&files[file_idx as usize]);
-            if is_jpeg_file(&right_file) {
-                match create_pdf_image_from_jpeg_file(&right_file) {
-                    Some((img, w, h)) => (img, w, h),
-                    None => {
-                        file_idx += 2;
-                        continue;
-                    }
-                }
-            } else {
-                let right_img = match load_image(&right_file) {
-                    Ok(img) => img,
-                    Err(_) => {
-                        file_idx += 2;
-                        continue;
-                    }
-                };
-                let (w, h) = right_img.dimensions();
-                match create_pdf_image(&right_img) {
-                    Some(pdf_img) => (pdf_img, w, h),
-                    None => {
-                        file_idx += 2;
-                        continue;
-                    }
-                }
-            }
         };
 
-        let right_width_mm = px_to_mm(right_w, dpi);
-        let right_height_mm = px_to_mm(right_h, dpi);
-
-        // 左ページのファイルインデックス
-        let left_file_idx = file_idx + 1;
-
-        // 左ページ（存在すれば）
-        let (pdf_left, left_width_mm, left_height_mm) = if left_file_idx >= 0 && (left_file_idx as usize) < total {
-            let left_file = input_path.join(&files[left_file_idx as usize]);
-
-            if is_jpeg_file(&left_file) {
-                match create_pdf_image_from_jpeg_file(&left_file) {
-                    Some((img, w, h)) => {
-                        let lw = px_to_mm(w, dpi);
-                        let lh = px_to_mm(h, dpi);
-                        (Some(img), lw, lh)
-                    }
-                    None => (None, right_width_mm, right_height_mm)
-                }
-            } else {
-                match load_image(&left_file) {
-                    Ok(left_img) => {
-                        let (w, h) = left_img.dimensions();
-                        let lw = px_to_mm(w, dpi);
-                        let lh = px_to_mm(h, dpi);
-                        match create_pdf_image(&left_img) {
-                            Some(pdf_img) => (Some(pdf_img), lw, lh),
-                            None => (None, right_width_mm, right_height_mm)
-                        }
-                    }
-                    Err(_) => (None, right_width_mm, right_height_mm)
-                }
+        let lead_width_mm = px_to_mm(lead_px_w, lead_dpi);
+        let lead_height_mm = px_to_mm(lead_px_h, lead_dpi);
+
+        // 後のページのファイルインデックス
+        let trail_file_idx = file_idx + 1;
+
+        // 後のページ（存在すれば、中身とピクセルサイズ）
+        let (trail_content, trail_width_mm, trail_height_mm, trail_dpi) = if trail_file_idx >= 0 && (trail_file_idx as usize) < total {
+            let trail_file = input_path.join(&files[trail_file_idx as usize]);
+            let loaded = load_and_create_pdf_image(
+                &trail_file, color_profile, color_mode, jpeg_options, dpi_override, &mut image_profiles,
+            );
+            match loaded {
+                Ok((img, w, h, dpi)) => (Some((PageContent::Image(img), w, h)), px_to_mm(w, dpi), px_to_mm(h, dpi), dpi),
+                Err(_) => (None, lead_width_mm, lead_height_mm, lead_dpi),
             }
+        } else if let Some(colophon) = colophon.filter(|_| trail_file_idx as usize == total) {
+            let content = PageContent::Generated(GeneratedPage::Colophon(colophon, work_info));
+            (Some((content, first_w, first_h)), first_width_mm, first_height_mm, first_dpi)
         } else {
-            (None, right_width_mm, right_height_mm)
+            (None, lead_width_mm, lead_height_mm, lead_dpi)
         };
 
         let _ = app_handle.emit("progress", crate::ProgressPayload {
@@ -173,71 +176,105 @@ pub fn generate_spread_pdf(
             in_progress: 0,
         });
 
-        let has_left_page = pdf_left.is_some();
-        let page_width_mm = right_width_mm + left_width_mm + gutter_mm + padding_mm * 2.0;
-        let page_height_mm = right_height_mm.max(left_height_mm) + padding_mm * 2.0;
+        let has_trail_page = trail_content.is_some();
+
+        // 左右ページの画像サイズ（右綴じは先のページが右、左綴じは左）
+        let ((left_width_mm, left_height_mm), (right_width_mm, right_height_mm)) = match lead_side {
+            PageSide::Right => ((trail_width_mm, trail_height_mm), (lead_width_mm, lead_height_mm)),
+            PageSide::Left => ((lead_width_mm, lead_height_mm), (trail_width_mm, trail_height_mm)),
+        };
+
+        // ページサイズと左右ページの枠（x, 幅）
+        let (page_width_mm, page_height_mm, left_x, left_w, right_x, right_w) = match page_size_mm {
+            Some((width, height)) => (width * 2.0 + gutter_mm, height, 0.0, width, width + gutter_mm, width),
+            None => (
+                right_width_mm + left_width_mm + gutter_mm + padding_mm * 2.0,
+                right_height_mm.max(left_height_mm) + padding_mm * 2.0,
+                padding_mm,
+                left_width_mm,
+                padding_mm + left_width_mm + gutter_mm,
+                right_width_mm,
+            ),
+        };
+
+        // 左右の枠（x, 幅, 設定, ノンブルを寄せる側）
+        let slot = |side: PageSide| match side {
+            PageSide::Left => (left_x, left_w, left_page, nombre_placement_edge(nombre, side, &left_page.nombre_position)),
+            PageSide::Right => (right_x, right_w, right_page, nombre_placement_edge(nombre, side, &right_page.nombre_position)),
+        };
+
+        // 枠への画像配置（プリセット指定時は各ページの余白を除いた枠内に配置）
+        let place = |img_width_mm: f32, img_height_mm: f32, side: PageSide| {
+            let (slot_x, slot_w, options, _) = slot(side);
+            let placement = match page_size_mm {
+                Some((_, height)) => place_image_in_box(
+                    img_width_mm, img_height_mm,
+                    slot_x + padding_mm, padding_mm,
+                    slot_w - padding_mm * 2.0, height - padding_mm * 2.0,
+                    page_fit,
+                ),
+                None => ImagePlacement::unscaled(slot_x, padding_mm),
+            };
+            placement.shifted(options.offset_x_mm, options.offset_y_mm)
+        };
+
+        // ノンブルを置く横範囲（画像の配置枠）
+        let nombre_area = |side: PageSide| {
+            let (slot_x, slot_w, _, _) = slot(side);
+            match page_size_mm {
+                Some(_) => (slot_x + padding_mm, slot_x + slot_w - padding_mm),
+                None => (slot_x, slot_x + slot_w),
+            }
+        };
 
         // 2ページ目以降は新しいページを追加
         if !is_first_page {
-            let (page, layer) = doc.add_page(Mm(page_width_mm), Mm(page_height_mm), "Layer 1");
+            let (media_width, media_height) = frames.media_size_mm(page_width_mm, page_height_mm);
+            let (page, layer) = doc.add_page(
+                Mm(media_width),
+                Mm(media_height),
+                "Layer 1",
+            );
             current_layer = doc.get_page(page).get_layer(layer);
+            current_page = page;
         }
         is_first_page = false;
+        begin_page(&doc, current_page, &current_layer, page_width_mm, page_height_mm, frames);
 
-        // 右ページを配置
-        let right_x = padding_mm + left_width_mm + gutter_mm;
-        let transform = ImageTransform {
-            translate_x: Some(Mm(right_x)),
-            translate_y: Some(Mm(padding_mm)),
-            scale_x: Some(1.0),
-            scale_y: Some(1.0),
-            dpi: Some(dpi),
-            ..Default::default()
-        };
-        pdf_right.add_to_layer(current_layer.clone(), transform);
-
-        // 左ページを配置
-        if let Some(left_img) = pdf_left {
-            let transform = ImageTransform {
-                translate_x: Some(Mm(padding_mm)),
-                translate_y: Some(Mm(padding_mm)),
-                scale_x: Some(1.0),
-                scale_y: Some(1.0),
-                dpi: Some(dpi),
-                ..Default::default()
-            };
-            left_img.add_to_layer(current_layer.clone(), transform);
+        // 先のページを配置
+        let lead_placement = place(lead_width_mm, lead_height_mm, lead_side);
+        generated.add_content(&current_layer, lead_content, lead_px_w, lead_px_h, &lead_placement, lead_dpi)?;
+
+        // 後のページを配置
+        if let Some((content, trail_px_w, trail_px_h)) = trail_content {
+            let trail_placement = place(trail_width_mm, trail_height_mm, trail_side);
+            generated.add_content(&current_layer, content, trail_px_w, trail_px_h, &trail_placement, trail_dpi)?;
         }
 
+        // ノンブルの番号（白紙ページ・奥付には振らない）
+        let nombre_number = |idx: i32| {
+            (idx >= 0 && (idx as usize) < total).then(|| options.nombre_start_number + idx as u32)
+        };
+        let lead_number = nombre_number(file_idx);
+        let trail_number = nombre_number(trail_file_idx).filter(|_| has_trail_page);
+
         // ノンブル描画
-        if let Some(ref font) = nombre_font {
-            let text_y = padding_mm / 2.0 - nombre_font_size_pt * 0.35 / 2.0;
-
-            if file_idx == -1 {
-                // 白紙見開き: 左ページのみノンブル
-                if has_left_page {
-                    let left_num_str = "1".to_string();
-                    let left_text_x = padding_mm + left_width_mm / 2.0 - (left_num_str.len() as f32 * nombre_font_size_pt * 0.3 / 2.0);
-                    current_layer.use_text(&left_num_str, nombre_font_size_pt, Mm(left_text_x), Mm(text_y), font);
-                }
-            } else {
-                let right_page_num = file_idx as usize + 1;
-                let left_page_num = right_page_num + 1;
-
-                // 右ページのノンブル
-                let right_num_str = right_page_num.to_string();
-                let right_text_x = right_x + right_width_mm / 2.0 - (right_num_str.len() as f32 * nombre_font_size_pt * 0.3 / 2.0);
-                current_layer.use_text(&right_num_str, nombre_font_size_pt, Mm(right_text_x), Mm(text_y), font);
-
-                // 左ページのノンブル
-                if has_left_page {
-                    let left_num_str = left_page_num.to_string();
-                    let left_text_x = padding_mm + left_width_mm / 2.0 - (left_num_str.len() as f32 * nombre_font_size_pt * 0.3 / 2.0);
-                    current_layer.use_text(&left_num_str, nombre_font_size_pt, Mm(left_text_x), Mm(text_y), font);
+        if let Some(ref pdf_nombre) = pdf_nombre {
+            for (number, side) in [(lead_number, lead_side), (trail_number, trail_side)] {
+                if let Some(number) = number {
+                    let (_, _, _, edge) = slot(side);
+                    pdf_nombre.draw(&current_layer, number, nombre_area(side), page_height_mm, padding_mm, edge);
                 }
             }
         }
 
+        let numbers = lead_number.into_iter().chain(trail_number).collect::<Vec<_>>();
+        document_info.add_page(match (numbers.is_empty(), file_idx) {
+            (false, _) => PageLabel::Numbered(numbers),
+            (true, -1) => PageLabel::Unnumbered(PAGE_LABEL_WHITE),
+            (true, _) => PageLabel::Unnumbered(PAGE_LABEL_COLOPHON),
+        });
+
         file_idx += 2;
     }
 
@@ -251,12 +288,7 @@ pub fn generate_spread_pdf(
     });
 
     let actual_path = unique_output_path(output_path);
-    let file = File::create(&actual_path)
-        .map_err(|e| format!("PDFファイルの作成に失敗: {}", e))?;
-    let mut writer = BufWriter::new(file);
-
-    doc.save(&mut writer)
-        .map_err(|e| format!("PDFの保存に失敗: {}", e))?;
+    let warnings = save_pdf(doc, &actual_path, &image_profiles, target_icc_profile(color_profile), &document_info)?;
 
-    Ok(actual_path)
+    Ok(PdfResult { output_path: actual_path, warnings })
 }
@@ -0,0 +1,1437 @@
+//! タチミ - PSDレイヤーモジュール
+//! レイヤー/グループ構成の取得と、表示ルールに従ったレイヤー合成
+
+use ::image::{DynamicImage, ImageBuffer, Primitive, Rgba};
+use flate2::read::ZlibDecoder;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use super::image_loader::{
+    decode_packbits, linear_to_srgb, load_psd_fast, psd_row_bytes, read_psd_header,
+    read_section_length, undo_zip_prediction, PsdHeader, COLOR_MODE_CMYK, COLOR_MODE_GRAYSCALE,
+    COLOR_MODE_RGB,
+};
+use super::types::LayerFilter;
+
+/// レイヤー/グループ情報（UI表示用）
+#[derive(Debug, Clone, Serialize)]
+pub struct PsdLayerInfo {
+    /// レイヤーID（PSD内のレコード順、0 = 最背面）
+    pub id: usize,
+    pub name: String,
+    /// "layer" = 通常レイヤー, "group" = グループ
+    pub kind: String,
+    /// 保存時の表示状態
+    pub visible: bool,
+    /// 描画モード（"normal", "multiply", "pass_through" など）
+    pub blend_mode: String,
+    /// 不透明度: 0-100
+    pub opacity: u8,
+    /// クリッピングマスク（下のレイヤーでクリップ）
+    pub clipping: bool,
+    /// 親グループのID（最上位ならNone）
+    pub parent_id: Option<usize>,
+    pub top: i32,
+    pub left: i32,
+    pub bottom: i32,
+    pub right: i32,
+}
+
+/// セクション区切りの種類（lsct）
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SectionType {
+    Layer,
+    /// グループ本体（開/閉）
+    Folder,
+    /// グループの終端マーカー
+    Divider,
+}
+
+/// レイヤーマスク
+struct LayerMask {
+    top: i32,
+    left: i32,
+    bottom: i32,
+    right: i32,
+    default_color: u8,
+    disabled: bool,
+}
+
+/// チャンネル画像データの位置
+struct ChannelInfo {
+    id: i16,
+    offset: u64,
+    length: u64,
+}
+
+/// レイヤーレコード
+struct LayerRecord {
+    name: String,
+    top: i32,
+    left: i32,
+    bottom: i32,
+    right: i32,
+    channels: Vec<ChannelInfo>,
+    blend_key: [u8; 4],
+    opacity: u8,
+    fill_opacity: u8,
+    clipping: bool,
+    visible: bool,
+    mask: Option<LayerMask>,
+    section: SectionType,
+}
+
+impl LayerRecord {
+    fn width(&self) -> u32 {
+        (self.right - self.left).max(0) as u32
+    }
+
+    fn height(&self) -> u32 {
+        (self.bottom - self.top).max(0) as u32
+    }
+}
+
+/// レイヤーツリー（子は背面→前面の順）
+enum LayerNode {
+    Layer(usize),
+    Group { id: usize, children: Vec<LayerNode> },
+}
+
+impl LayerNode {
+    fn id(&self) -> usize {
+        match self {
+            LayerNode::Layer(id) => *id,
+            LayerNode::Group { id, .. } => *id,
+        }
+    }
+
+    fn is_group(&self) -> bool {
+        matches!(self, LayerNode::Group { .. })
+    }
+}
+
+/// 描画モード
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Darken,
+    Lighten,
+    Overlay,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    LinearDodge,
+    LinearBurn,
+    ColorDodge,
+    ColorBurn,
+}
+
+/// PSDのレイヤー/グループ一覧を取得（Photoshopのレイヤーパネルと同じ前面→背面の順）
+pub fn list_psd_layers(path: &Path) -> Result<Vec<PsdLayerInfo>, String> {
+    let (_, _, records) = open_psd_layers(path)?;
+    let tree = build_layer_tree(&records);
+
+    let mut infos = Vec::with_capacity(records.len());
+    collect_layer_infos(&tree, &records, None, &mut infos);
+    Ok(infos)
+}
+
+/// 表示ルールに従ってPSDのレイヤーを合成
+pub fn render_psd_layers(path: &Path, filter: &LayerFilter) -> Result<DynamicImage, String> {
+    let (mut file, header, records) = open_psd_layers(path)?;
+
+    // 背景のみのPSDはレイヤー情報を持たないため統合画像を使う
+    if records.is_empty() {
+        return load_psd_fast(path);
+    }
+
+    match header.color_mode {
+        COLOR_MODE_RGB | COLOR_MODE_GRAYSCALE | COLOR_MODE_CMYK => {}
+        mode => return Err(format!("レイヤー合成に対応していないカラーモードです: {}", mode)),
+    }
+
+    let tree = build_layer_tree(&records);
+    let document = Region { left: 0, top: 0, right: header.width as i32, bottom: header.height as i32 };
+    let mut ctx = RenderContext {
+        file: &mut file,
+        header,
+        records: &records,
+        filter,
+    };
+    let included = filter.include.is_empty();
+
+    if header.depth == 8 {
+        let mut canvas = Canvas::<u8>::new(document);
+        render_nodes(&mut ctx, &tree, &mut canvas, 1.0, included)?;
+        let data = canvas.flatten_on_white();
+        let buf = ImageBuffer::<Rgba<u8>, Vec<u8>>::from_raw(header.width, header.height, data)
+            .ok_or("画像バッファの作成に失敗しました")?;
+        Ok(DynamicImage::ImageRgba8(buf))
+    } else {
+        // 16bit/32bitは精度を落とさないよう16bitで合成
+        let mut canvas = Canvas::<u16>::new(document);
+        render_nodes(&mut ctx, &tree, &mut canvas, 1.0, included)?;
+        let data = canvas.flatten_on_white();
+        let buf = ImageBuffer::<Rgba<u16>, Vec<u16>>::from_raw(header.width, header.height, data)
+            .ok_or("画像バッファの作成に失敗しました")?;
+        Ok(DynamicImage::ImageRgba16(buf))
+    }
+}
+
+// ============================================================
+// レイヤー情報の読み込み
+// ============================================================
+
+/// PSDを開いてレイヤーレコードを読み込む（チャンネルデータは位置のみ記録）
+fn open_psd_layers(path: &Path) -> Result<(BufReader<File>, PsdHeader, Vec<LayerRecord>), String> {
+    let file = File::open(path).map_err(|e| format!("ファイルを開けません: {}", e))?;
+    let mut file = BufReader::with_capacity(64 * 1024, file);
+
+    let header = read_psd_header(&mut file)?;
+
+    // Color Mode Data / Image Resources をスキップ
+    for _ in 0..2 {
+        let len = read_u32(&mut file)?;
+        skip(&mut file, len as u64)?;
+    }
+
+    // Layer and Mask Information
+    let section_len = read_section_length(&mut file, header.version)?;
+    if section_len == 0 {
+        return Ok((file, header, Vec::new()));
+    }
+    let section_end = position(&mut file)? + section_len;
+
+    let info_len = read_section_length(&mut file, header.version)?;
+    let info_end = position(&mut file)? + info_len;
+    let mut records = if info_len > 0 {
+        read_layer_info(&mut file, header.version)?
+    } else {
+        Vec::new()
+    };
+    file.seek(SeekFrom::Start(info_end))
+        .map_err(|e| format!("シークエラー: {}", e))?;
+
+    // 16bit/32bitのレイヤー情報は追加レイヤー情報（Lr16/Lr32）に格納される
+    if records.is_empty() {
+        let global_mask_len = read_u32(&mut file)?;
+        skip(&mut file, global_mask_len as u64)?;
+
+        while position(&mut file)? + 12 <= section_end {
+            let mut sig = [0u8; 4];
+            let mut key = [0u8; 4];
+            read_bytes(&mut file, &mut sig)?;
+            if &sig != b"8BIM" && &sig != b"8B64" {
+                break;
+            }
+            read_bytes(&mut file, &mut key)?;
+            let len = read_block_length(&mut file, header.version, &key)?;
+            let block_start = position(&mut file)?;
+
+            if &key == b"Lr16" || &key == b"Lr32" || &key == b"Layr" {
+                records = read_layer_info(&mut file, header.version)?;
+                break;
+            }
+            file.seek(SeekFrom::Start(block_start + len.div_ceil(4) * 4))
+                .map_err(|e| format!("シークエラー: {}", e))?;
+        }
+    }
+
+    Ok((file, header, records))
+}
+
+/// Layer Info（レイヤー数 + レコード + チャンネル画像データ）を読み込む
+fn read_layer_info<R: Read + Seek>(file: &mut R, version: u16) -> Result<Vec<LayerRecord>, String> {
+    // 負の値は統合画像の先頭アルファに透明度が入っていることを示すだけなので絶対値を使う
+    let count = read_i16(file)?.unsigned_abs() as usize;
+
+    let mut records = Vec::with_capacity(count);
+    for _ in 0..count {
+        records.push(read_layer_record(file, version)?);
+    }
+
+    // チャンネル画像データはレコード順・チャンネル順に並ぶ
+    let mut offset = position(file)?;
+    for record in records.iter_mut() {
+        for channel in record.channels.iter_mut() {
+            channel.offset = offset;
+            offset += channel.length;
+        }
+    }
+
+    Ok(records)
+}
+
+/// レイヤーレコードを1件読み込む
+fn read_layer_record<R: Read + Seek>(file: &mut R, version: u16) -> Result<LayerRecord, String> {
+    let top = read_i32(file)?;
+    let left = read_i32(file)?;
+    let bottom = read_i32(file)?;
+    let right = read_i32(file)?;
+
+    let channel_count = read_u16(file)? as usize;
+    let mut channels = Vec::with_capacity(channel_count);
+    for _ in 0..channel_count {
+        let id = read_i16(file)?;
+        let length = if version == 2 {
+            read_u64(file)?
+        } else {
+            read_u32(file)? as u64
+        };
+        channels.push(ChannelInfo { id, offset: 0, length });
+    }
+
+    let mut sig = [0u8; 4];
+    read_bytes(file, &mut sig)?;
+    if &sig != b"8BIM" {
+        return Err("不正なレイヤーレコードです".to_string());
+    }
+    let mut blend_key = [0u8; 4];
+    read_bytes(file, &mut blend_key)?;
+
+    let opacity = read_u8(file)?;
+    let clipping = read_u8(file)? != 0;
+    let flags = read_u8(file)?;
+    let _filler = read_u8(file)?;
+
+    let extra_len = read_u32(file)? as u64;
+    let extra_end = position(file)? + extra_len;
+
+    // レイヤーマスク
+    let mask_len = read_u32(file)? as u64;
+    let mask_end = position(file)? + mask_len;
+    let mask = if mask_len >= 18 {
+        let mask_top = read_i32(file)?;
+        let mask_left = read_i32(file)?;
+        let mask_bottom = read_i32(file)?;
+        let mask_right = read_i32(file)?;
+        let default_color = read_u8(file)?;
+        let mask_flags = read_u8(file)?;
+        Some(LayerMask {
+            top: mask_top,
+            left: mask_left,
+            bottom: mask_bottom,
+            right: mask_right,
+            default_color,
+            disabled: mask_flags & 0x02 != 0,
+        })
+    } else {
+        None
+    };
+    file.seek(SeekFrom::Start(mask_end))
+        .map_err(|e| format!("シークエラー: {}", e))?;
+
+    // ブレンド条件
+    let ranges_len = read_u32(file)?;
+    skip(file, ranges_len as u64)?;
+
+    // レイヤー名（Pascal文字列、長さバイトを含めて4バイト境界）
+    let name_len = read_u8(file)? as usize;
+    let mut name_buf = vec![0u8; name_len];
+    read_bytes(file, &mut name_buf)?;
+    let padded = (name_len + 1).div_ceil(4) * 4;
+    skip(file, (padded - name_len - 1) as u64)?;
+    let mut name = String::from_utf8_lossy(&name_buf).to_string();
+
+    let mut section = SectionType::Layer;
+    let mut fill_opacity = 255u8;
+
+    // 追加レイヤー情報
+    while position(file)? + 12 <= extra_end {
+        let mut block_sig = [0u8; 4];
+        read_bytes(file, &mut block_sig)?;
+        if &block_sig != b"8BIM" && &block_sig != b"8B64" {
+            break;
+        }
+        let mut key = [0u8; 4];
+        read_bytes(file, &mut key)?;
+        let len = read_block_length(file, version, &key)?;
+        let block_start = position(file)?;
+
+        match &key {
+            // Unicodeレイヤー名（日本語名はこちらが正）
+            b"luni" => {
+                let chars = read_u32(file)? as usize;
+                let mut units = Vec::with_capacity(chars);
+                for _ in 0..chars.min(len as usize / 2) {
+                    units.push(read_u16(file)?);
+                }
+                while units.last() == Some(&0) {
+                    units.pop();
+                }
+                name = String::from_utf16_lossy(&units);
+            }
+            // グループ区切り
+            b"lsct" | b"lsdk" => {
+                section = match read_u32(file)? {
+                    1 | 2 => SectionType::Folder,
+                    3 => SectionType::Divider,
+                    _ => SectionType::Layer,
+                };
+                // グループの描画モード（通過など）はこちらに入る
+                if len >= 12 {
+                    skip(file, 4)?;
+                    read_bytes(file, &mut blend_key)?;
+                }
+            }
+            // 塗りの不透明度
+            b"iOpa" => {
+                fill_opacity = read_u8(file)?;
+            }
+            _ => {}
+        }
+
+        file.seek(SeekFrom::Start(block_start + len))
+            .map_err(|e| format!("シークエラー: {}", e))?;
+    }
+
+    file.seek(SeekFrom::Start(extra_end))
+        .map_err(|e| format!("シークエラー: {}", e))?;
+
+    Ok(LayerRecord {
+        name,
+        top,
+        left,
+        bottom,
+        right,
+        channels,
+        blend_key,
+        opacity,
+        fill_opacity,
+        clipping,
+        visible: flags & 0x02 == 0,
+        mask,
+        section,
+    })
+}
+
+/// 追加レイヤー情報のデータ長を読む（PSBでは一部キーが8バイト）
+fn read_block_length<R: Read>(file: &mut R, version: u16, key: &[u8; 4]) -> Result<u64, String> {
+    const LONG_KEYS: [&[u8; 4]; 13] = [
+        b"LMsk", b"Lr16", b"Lr32", b"Layr", b"Mt16", b"Mt32", b"Mtrn",
+        b"Alph", b"FMsk", b"lnk2", b"FEid", b"FXid", b"PxSD",
+    ];
+    if version == 2 && LONG_KEYS.contains(&key) {
+        read_u64(file)
+    } else {
+        Ok(read_u32(file)? as u64)
+    }
+}
+
+/// レコード列（背面→前面、グループは終端マーカー→中身→本体の順）からツリーを構築
+fn build_layer_tree(records: &[LayerRecord]) -> Vec<LayerNode> {
+    let mut stack: Vec<Vec<LayerNode>> = vec![Vec::new()];
+
+    for (id, record) in records.iter().enumerate() {
+        match record.section {
+            SectionType::Divider => stack.push(Vec::new()),
+            SectionType::Folder => {
+                let children = if stack.len() > 1 {
+                    stack.pop().unwrap_or_default()
+                } else {
+                    Vec::new()
+                };
+                if let Some(parent) = stack.last_mut() {
+                    parent.push(LayerNode::Group { id, children });
+                }
+            }
+            SectionType::Layer => {
+                if let Some(parent) = stack.last_mut() {
+                    parent.push(LayerNode::Layer(id));
+                }
+            }
+        }
+    }
+
+    // 閉じられていないグループは親に展開
+    while stack.len() > 1 {
+        let children = stack.pop().unwrap_or_default();
+        if let Some(parent) = stack.last_mut() {
+            parent.extend(children);
+        }
+    }
+
+    stack.pop().unwrap_or_default()
+}
+
+/// ツリーを前面→背面の順に平坦化して一覧を作る
+fn collect_layer_infos(
+    nodes: &[LayerNode],
+    records: &[LayerRecord],
+    parent_id: Option<usize>,
+    infos: &mut Vec<PsdLayerInfo>,
+) {
+    for node in nodes.iter().rev() {
+        let record = &records[node.id()];
+        infos.push(PsdLayerInfo {
+            id: node.id(),
+            name: record.name.clone(),
+            kind: if node.is_group() { "group" } else { "layer" }.to_string(),
+            visible: record.visible,
+            blend_mode: blend_mode_name(&record.blend_key),
+            opacity: ((record.opacity as u32 * 100 + 127) / 255) as u8,
+            clipping: record.clipping,
+            parent_id,
+            top: record.top,
+            left: record.left,
+            bottom: record.bottom,
+            right: record.right,
+        });
+        if let LayerNode::Group { id, children } = node {
+            collect_layer_infos(children, records, Some(*id), infos);
+        }
+    }
+}
+
+/// 描画モードキーを表示名に変換
+fn blend_mode_name(key: &[u8; 4]) -> String {
+    match key {
+        b"pass" => "pass_through".to_string(),
+        b"norm" => "normal".to_string(),
+        b"diss" => "dissolve".to_string(),
+        b"dark" => "darken".to_string(),
+        b"mul " => "multiply".to_string(),
+        b"idiv" => "color_burn".to_string(),
+        b"lbrn" => "linear_burn".to_string(),
+        b"dkCl" => "darker_color".to_string(),
+        b"lite" => "lighten".to_string(),
+        b"scrn" => "screen".to_string(),
+        b"div " => "color_dodge".to_string(),
+        b"lddg" => "linear_dodge".to_string(),
+        b"lgCl" => "lighter_color".to_string(),
+        b"over" => "overlay".to_string(),
+        b"sLit" => "soft_light".to_string(),
+        b"hLit" => "hard_light".to_string(),
+        b"vLit" => "vivid_light".to_string(),
+        b"lLit" => "linear_light".to_string(),
+        b"pLit" => "pin_light".to_string(),
+        b"hMix" => "hard_mix".to_string(),
+        b"diff" => "difference".to_string(),
+        b"smud" => "exclusion".to_string(),
+        b"fsub" => "subtract".to_string(),
+        b"fdiv" => "divide".to_string(),
+        b"hue " => "hue".to_string(),
+        b"sat " => "saturation".to_string(),
+        b"colr" => "color".to_string(),
+        b"lum " => "luminosity".to_string(),
+        _ => String::from_utf8_lossy(key).trim().to_string(),
+    }
+}
+
+/// 描画モードキーを合成用の描画モードに変換（未対応のモードは通常として扱う）
+fn blend_mode_from_key(key: &[u8; 4]) -> BlendMode {
+    match key {
+        b"mul " => BlendMode::Multiply,
+        b"scrn" => BlendMode::Screen,
+        b"dark" => BlendMode::Darken,
+        b"lite" => BlendMode::Lighten,
+        b"over" => BlendMode::Overlay,
+        b"hLit" => BlendMode::HardLight,
+        b"sLit" => BlendMode::SoftLight,
+        b"diff" => BlendMode::Difference,
+        b"smud" => BlendMode::Exclusion,
+        b"lddg" => BlendMode::LinearDodge,
+        b"lbrn" => BlendMode::LinearBurn,
+        b"div " => BlendMode::ColorDodge,
+        b"idiv" => BlendMode::ColorBurn,
+        _ => BlendMode::Normal,
+    }
+}
+
+// ============================================================
+// 合成
+// ============================================================
+
+/// ドキュメント座標の矩形（right / bottom は範囲外）
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Region {
+    left: i32,
+    top: i32,
+    right: i32,
+    bottom: i32,
+}
+
+impl Region {
+    /// レイヤーの範囲（空ならNone）
+    fn of_record(record: &LayerRecord) -> Option<Self> {
+        Region { left: record.left, top: record.top, right: record.right, bottom: record.bottom }.non_empty()
+    }
+
+    fn non_empty(self) -> Option<Self> {
+        (self.left < self.right && self.top < self.bottom).then_some(self)
+    }
+
+    fn union(self, other: Self) -> Self {
+        Region {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    fn intersect(self, other: Self) -> Option<Self> {
+        Region {
+            left: self.left.max(other.left),
+            top: self.top.max(other.top),
+            right: self.right.min(other.right),
+            bottom: self.bottom.min(other.bottom),
+        }
+        .non_empty()
+    }
+
+    fn width(&self) -> u32 {
+        (self.right - self.left).max(0) as u32
+    }
+
+    fn height(&self) -> u32 {
+        (self.bottom - self.top).max(0) as u32
+    }
+
+    /// 範囲内の i 番目（行優先）のピクセルの座標
+    fn position(&self, i: usize) -> (i32, i32) {
+        let width = self.width() as usize;
+        (self.left + (i % width) as i32, self.top + (i / width) as i32)
+    }
+}
+
+/// 合成先キャンバス（RGBA、ストレートアルファ）
+/// グループはドキュメント全体ではなく子レイヤーの範囲（region）だけ確保する
+struct Canvas<T> {
+    region: Region,
+    data: Vec<T>,
+}
+
+impl<T: Primitive> Canvas<T> {
+    fn new(region: Region) -> Self {
+        Canvas {
+            region,
+            data: vec![T::zero(); region.width() as usize * region.height() as usize * 4],
+        }
+    }
+
+    /// ドキュメント座標のピクセルのデータ位置
+    fn index(&self, x: i32, y: i32) -> usize {
+        let width = self.region.width() as usize;
+        ((y - self.region.top) as usize * width + (x - self.region.left) as usize) * 4
+    }
+
+    /// region の範囲の不透明度に opacity を掛けたクリッピング用の不透明度マップ
+    fn coverage(&self, region: Region, opacity: f32) -> Coverage {
+        let max = max_value::<T>();
+        let mut data = Vec::with_capacity(region.width() as usize * region.height() as usize);
+        for y in region.top..region.bottom {
+            for x in region.left..region.right {
+                let a = self.data[self.index(x, y) + 3].to_f32().unwrap_or(0.0) / max;
+                data.push((a * opacity * 255.0 + 0.5) as u8);
+            }
+        }
+        Coverage { region, data }
+    }
+
+    /// 白背景に統合して不透明な画像データにする（Photoshopの統合画像と同じ扱い）
+    fn flatten_on_white(mut self) -> Vec<T> {
+        let max = max_value::<T>();
+        for px in self.data.chunks_exact_mut(4) {
+            let a = px[3].to_f32().unwrap_or(0.0) / max;
+            for c in px.iter_mut().take(3) {
+                let v = c.to_f32().unwrap_or(0.0) / max;
+                *c = from_unit::<T>(v * a + (1.0 - a));
+            }
+            px[3] = T::DEFAULT_MAX_VALUE;
+        }
+        self.data
+    }
+}
+
+/// クリッピング用の不透明度マップ（ベースの範囲内、0-255）
+struct Coverage {
+    region: Region,
+    data: Vec<u8>,
+}
+
+impl Coverage {
+    fn at(&self, x: i32, y: i32) -> f32 {
+        let r = &self.region;
+        if x < r.left || y < r.top || x >= r.right || y >= r.bottom {
+            return 0.0;
+        }
+        let i = (y - r.top) as usize * r.width() as usize + (x - r.left) as usize;
+        self.data[i] as f32 / 255.0
+    }
+}
+
+/// デコード済みのレイヤー画像
+struct DecodedLayer {
+    color: Vec<Vec<u8>>,
+    alpha: Option<Vec<u8>>,
+    mask: Option<Vec<u8>>,
+}
+
+struct RenderContext<'a, R> {
+    file: &'a mut R,
+    header: PsdHeader,
+    records: &'a [LayerRecord],
+    filter: &'a LayerFilter,
+}
+
+impl<R> RenderContext<'_, R> {
+    /// 保存時の表示状態と除外ルールから表示対象か判定
+    fn is_shown(&self, record: &LayerRecord, is_group: bool) -> bool {
+        (self.filter.show_hidden || record.visible)
+            && !self.filter.exclude.iter().any(|m| m.matches(&record.name, is_group))
+    }
+
+    /// 表示ルールのincludeに一致するか判定
+    fn is_included(&self, record: &LayerRecord, is_group: bool) -> bool {
+        self.filter.include.iter().any(|m| m.matches(&record.name, is_group))
+    }
+}
+
+/// ノード列（背面→前面）をキャンバスに合成
+/// `included` は祖先グループがincludeに一致済み（またはinclude未指定）かどうか
+fn render_nodes<T: Primitive, R: Read + Seek>(
+    ctx: &mut RenderContext<'_, R>,
+    nodes: &[LayerNode],
+    canvas: &mut Canvas<T>,
+    opacity: f32,
+    included: bool,
+) -> Result<(), String> {
+    let mut clip_base: Option<Coverage> = None;
+
+    for (pos, node) in nodes.iter().enumerate() {
+        let record = &ctx.records[node.id()];
+
+        // クリッピングされたレイヤー/グループはベースが描画された場合のみ描画
+        if record.clipping {
+            if let Some(base) = &clip_base {
+                if ctx.is_shown(record, node.is_group()) {
+                    match node {
+                        LayerNode::Layer(id) => {
+                            draw_layer(ctx, *id, canvas, opacity, Some(base), false)?;
+                        }
+                        LayerNode::Group { .. } => {
+                            render_group(ctx, node, canvas, opacity, included, Some(base), false)?;
+                        }
+                    }
+                }
+            }
+            continue;
+        }
+        clip_base = None;
+
+        if !ctx.is_shown(record, node.is_group()) {
+            continue;
+        }
+
+        let next_clipped = nodes
+            .get(pos + 1)
+            .map(|n| ctx.records[n.id()].clipping)
+            .unwrap_or(false);
+        clip_base = match node {
+            LayerNode::Layer(id) => {
+                if !included && !ctx.is_included(record, false) {
+                    continue;
+                }
+                draw_layer(ctx, *id, canvas, opacity, None, next_clipped)?
+            }
+            LayerNode::Group { .. } => render_group(ctx, node, canvas, opacity, included, None, next_clipped)?,
+        };
+    }
+
+    Ok(())
+}
+
+/// グループをキャンバスに合成（クリッピングベースになる場合は不透明度マップを返す）
+/// クリッピングされたグループは通過でも単独で合成してからベースの範囲に切り抜く
+fn render_group<T: Primitive, R: Read + Seek>(
+    ctx: &mut RenderContext<'_, R>,
+    node: &LayerNode,
+    canvas: &mut Canvas<T>,
+    opacity: f32,
+    included: bool,
+    clip: Option<&Coverage>,
+    want_coverage: bool,
+) -> Result<Option<Coverage>, String> {
+    let LayerNode::Group { id, children } = node else {
+        return Ok(None);
+    };
+    let record = &ctx.records[*id];
+    let Some(region) = nodes_region(ctx.records, children).and_then(|r| r.intersect(canvas.region)) else {
+        return Ok(None);
+    };
+    let group_included = included || ctx.is_included(record, true);
+    let group_opacity = opacity * record.opacity as f32 / 255.0;
+    let depth = ctx.header.depth;
+    let mask = read_group_mask(ctx, record)?;
+
+    if &record.blend_key == b"pass" && clip.is_none() {
+        // 通過: 子レイヤーを直接合成し、マスクがあれば合成前の状態と混ぜる
+        let backdrop = mask.as_ref().map(|_| canvas.data.clone());
+        render_nodes(ctx, children, canvas, group_opacity, group_included)?;
+        if let (Some((m, data)), Some(backdrop)) = (mask, backdrop) {
+            mix_with_backdrop(canvas, &backdrop, m, &data, depth);
+        }
+        // 通過グループのクリッピングベースは合成後のキャンバスの不透明度
+        return Ok(want_coverage.then(|| canvas.coverage(region, 1.0)));
+    }
+
+    // グループ単位で合成してからマスクと描画モードを適用
+    let mode = blend_mode_from_key(&record.blend_key);
+    let mut group_canvas = Canvas::<T>::new(region);
+    render_nodes(ctx, children, &mut group_canvas, 1.0, group_included)?;
+    if let Some((m, data)) = mask {
+        apply_canvas_mask(&mut group_canvas, m, &data, depth);
+    }
+    composite_canvas(canvas, &group_canvas, mode, group_opacity, clip);
+    Ok(want_coverage.then(|| group_canvas.coverage(region, group_opacity)))
+}
+
+/// ノード列に含まれるレイヤーの範囲を合わせた矩形（描画する範囲がなければNone）
+fn nodes_region(records: &[LayerRecord], nodes: &[LayerNode]) -> Option<Region> {
+    nodes
+        .iter()
+        .filter_map(|node| match node {
+            LayerNode::Layer(id) => Region::of_record(&records[*id]),
+            LayerNode::Group { children, .. } => nodes_region(records, children),
+        })
+        .reduce(Region::union)
+}
+
+/// レイヤー1枚をキャンバスに合成（クリッピングベースになる場合は不透明度マップを返す）
+fn draw_layer<T: Primitive, R: Read + Seek>(
+    ctx: &mut RenderContext<'_, R>,
+    id: usize,
+    canvas: &mut Canvas<T>,
+    opacity: f32,
+    clip: Option<&Coverage>,
+    want_coverage: bool,
+) -> Result<Option<Coverage>, String> {
+    let record = &ctx.records[id];
+    let width = record.width();
+    let height = record.height();
+    if width == 0 || height == 0 {
+        return Ok(None);
+    }
+
+    let layer = decode_layer(ctx, record)?;
+    let header = ctx.header;
+    let mode = blend_mode_from_key(&record.blend_key);
+    let layer_opacity = opacity * record.opacity as f32 / 255.0;
+    let fill = record.fill_opacity as f32 / 255.0;
+    let mask = record
+        .mask
+        .as_ref()
+        .filter(|m| !m.disabled)
+        .zip(layer.mask.as_ref());
+
+    let mut coverage = if want_coverage {
+        Region::of_record(record).map(|region| Coverage {
+            region,
+            data: vec![0u8; width as usize * height as usize],
+        })
+    } else {
+        None
+    };
+
+    let x0 = record.left.max(canvas.region.left);
+    let y0 = record.top.max(canvas.region.top);
+    let x1 = record.right.min(canvas.region.right);
+    let y1 = record.bottom.min(canvas.region.bottom);
+
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let li = (y - record.top) as usize * width as usize + (x - record.left) as usize;
+
+            let mut alpha = layer
+                .alpha
+                .as_ref()
+                .map(|a| sample(a, li, header.depth, false))
+                .unwrap_or(1.0);
+            if let Some((m, data)) = mask {
+                alpha *= mask_value(m, data, x, y, header.depth);
+            }
+
+            let cov = alpha * layer_opacity;
+            if let Some(c) = coverage.as_mut() {
+                c.data[li] = (cov * 255.0 + 0.5) as u8;
+            }
+
+            let mut src_a = cov * fill;
+            if let Some(base) = clip {
+                src_a *= base.at(x, y);
+            }
+            if src_a <= 0.0 {
+                continue;
+            }
+
+            let src = layer_color(&layer, li, &header);
+            let ci = canvas.index(x, y);
+            composite_pixel(&mut canvas.data[ci..ci + 4], src, src_a, mode);
+        }
+    }
+
+    Ok(coverage)
+}
+
+/// グループのレイヤーマスクを読み込む（無効なマスクはNone）
+fn read_group_mask<'r, R: Read + Seek>(
+    ctx: &mut RenderContext<'_, R>,
+    record: &'r LayerRecord,
+) -> Result<Option<(&'r LayerMask, Vec<u8>)>, String> {
+    let Some(mask) = record.mask.as_ref().filter(|m| !m.disabled) else {
+        return Ok(None);
+    };
+    let header = ctx.header;
+    // マスクのチャンネルがなければ既定色だけのマスクとして扱う
+    let data = match record.channels.iter().find(|c| c.id == -2) {
+        Some(channel) => {
+            let w = (mask.right - mask.left).max(0) as u32;
+            let h = (mask.bottom - mask.top).max(0) as u32;
+            read_channel(ctx.file, channel, w, h, &header)?
+        }
+        None => Vec::new(),
+    };
+    Ok(Some((mask, data)))
+}
+
+/// グループキャンバスの不透明度にマスクを掛ける
+fn apply_canvas_mask<T: Primitive>(canvas: &mut Canvas<T>, mask: &LayerMask, data: &[u8], depth: u16) {
+    let max = max_value::<T>();
+    let region = canvas.region;
+    for (i, px) in canvas.data.chunks_exact_mut(4).enumerate() {
+        let (x, y) = region.position(i);
+        let m = mask_value(mask, data, x, y, depth);
+        let a = px[3].to_f32().unwrap_or(0.0) / max;
+        px[3] = from_unit::<T>(a * m);
+    }
+}
+
+/// 通過グループの合成結果を、マスクの値に応じて合成前のキャンバスと混ぜる（乗算済みアルファで補間）
+fn mix_with_backdrop<T: Primitive>(canvas: &mut Canvas<T>, backdrop: &[T], mask: &LayerMask, data: &[u8], depth: u16) {
+    let max = max_value::<T>();
+    let region = canvas.region;
+    let unit = |v: T| v.to_f32().unwrap_or(0.0) / max;
+    for (i, (px, bd)) in canvas.data.chunks_exact_mut(4).zip(backdrop.chunks_exact(4)).enumerate() {
+        let (x, y) = region.position(i);
+        let m = mask_value(mask, data, x, y, depth);
+        if m >= 1.0 {
+            continue;
+        }
+        let (src_a, dst_a) = (unit(px[3]), unit(bd[3]));
+        let out_a = dst_a + (src_a - dst_a) * m;
+        for c in 0..3 {
+            let premultiplied = unit(bd[c]) * dst_a + (unit(px[c]) * src_a - unit(bd[c]) * dst_a) * m;
+            px[c] = from_unit::<T>(if out_a > 0.0 { premultiplied / out_a } else { 0.0 });
+        }
+        px[3] = from_unit::<T>(out_a);
+    }
+}
+
+/// グループキャンバスを親キャンバスに合成（src の範囲は dst の範囲内、clip はクリッピングベース）
+fn composite_canvas<T: Primitive>(
+    dst: &mut Canvas<T>,
+    src: &Canvas<T>,
+    mode: BlendMode,
+    opacity: f32,
+    clip: Option<&Coverage>,
+) {
+    let max = max_value::<T>();
+    for (i, s) in src.data.chunks_exact(4).enumerate() {
+        let (x, y) = src.region.position(i);
+        let mut src_a = s[3].to_f32().unwrap_or(0.0) / max * opacity;
+        if let Some(base) = clip {
+            src_a *= base.at(x, y);
+        }
+        if src_a <= 0.0 {
+            continue;
+        }
+        let color = [
+            s[0].to_f32().unwrap_or(0.0) / max,
+            s[1].to_f32().unwrap_or(0.0) / max,
+            s[2].to_f32().unwrap_or(0.0) / max,
+        ];
+        let di = dst.index(x, y);
+        composite_pixel(&mut dst.data[di..di + 4], color, src_a, mode);
+    }
+}
+
+/// 1ピクセルを合成（W3C Compositingの分離型描画モード）
+fn composite_pixel<T: Primitive>(dst: &mut [T], src: [f32; 3], src_a: f32, mode: BlendMode) {
+    let max = max_value::<T>();
+    let dst_a = dst[3].to_f32().unwrap_or(0.0) / max;
+    let out_a = src_a + dst_a - src_a * dst_a;
+    if out_a <= 0.0 {
+        return;
+    }
+
+    for c in 0..3 {
+        let cb = dst[c].to_f32().unwrap_or(0.0) / max;
+        let cs = src[c];
+        let blended = blend_channel(mode, cb, cs);
+        let out = ((1.0 - src_a) * dst_a * cb + (1.0 - dst_a) * src_a * cs + src_a * dst_a * blended) / out_a;
+        dst[c] = from_unit::<T>(out);
+    }
+    dst[3] = from_unit::<T>(out_a);
+}
+
+/// 描画モードごとのチャンネル合成（cb = 下、cs = 上、0.0-1.0）
+fn blend_channel(mode: BlendMode, cb: f32, cs: f32) -> f32 {
+    let screen = |a: f32, b: f32| a + b - a * b;
+    let hard_light = |b: f32, s: f32| {
+        if s <= 0.5 {
+            b * 2.0 * s
+        } else {
+            screen(b, 2.0 * s - 1.0)
+        }
+    };
+
+    match mode {
+        BlendMode::Normal => cs,
+        BlendMode::Multiply => cb * cs,
+        BlendMode::Screen => screen(cb, cs),
+        BlendMode::Darken => cb.min(cs),
+        BlendMode::Lighten => cb.max(cs),
+        BlendMode::Overlay => hard_light(cs, cb),
+        BlendMode::HardLight => hard_light(cb, cs),
+        BlendMode::SoftLight => {
+            if cs <= 0.5 {
+                cb - (1.0 - 2.0 * cs) * cb * (1.0 - cb)
+            } else {
+                let d = if cb <= 0.25 {
+                    ((16.0 * cb - 12.0) * cb + 4.0) * cb
+                } else {
+                    cb.sqrt()
+                };
+                cb + (2.0 * cs - 1.0) * (d - cb)
+            }
+        }
+        BlendMode::Difference => (cb - cs).abs(),
+        BlendMode::Exclusion => cb + cs - 2.0 * cb * cs,
+        BlendMode::LinearDodge => (cb + cs).min(1.0),
+        BlendMode::LinearBurn => (cb + cs - 1.0).max(0.0),
+        BlendMode::ColorDodge => {
+            if cb <= 0.0 {
+                0.0
+            } else if cs >= 1.0 {
+                1.0
+            } else {
+                (cb / (1.0 - cs)).min(1.0)
+            }
+        }
+        BlendMode::ColorBurn => {
+            if cb >= 1.0 {
+                1.0
+            } else if cs <= 0.0 {
+                0.0
+            } else {
+                1.0 - ((1.0 - cb) / cs).min(1.0)
+            }
+        }
+    }
+}
+
+/// レイヤーのピクセル色をRGB（0.0-1.0）で取得
+fn layer_color(layer: &DecodedLayer, i: usize, header: &PsdHeader) -> [f32; 3] {
+    let depth = header.depth;
+    let get = |c: usize| {
+        layer
+            .color
+            .get(c)
+            .filter(|data| !data.is_empty())
+            .map(|data| sample(data, i, depth, true))
+            .unwrap_or(0.0)
+    };
+
+    match header.color_mode {
+        COLOR_MODE_GRAYSCALE => {
+            let v = get(0);
+            [v, v, v]
+        }
+        COLOR_MODE_CMYK => {
+            // CMYKは反転して格納されている（0 = 100%）
+            let k = get(3);
+            [get(0) * k, get(1) * k, get(2) * k]
+        }
+        _ => [get(0), get(1), get(2)],
+    }
+}
+
+/// レイヤーマスクの値（マスク範囲外は既定色）
+fn mask_value(mask: &LayerMask, data: &[u8], x: i32, y: i32, depth: u16) -> f32 {
+    let width = (mask.right - mask.left).max(0);
+    if x < mask.left || y < mask.top || x >= mask.right || y >= mask.bottom || data.is_empty() {
+        return mask.default_color as f32 / 255.0;
+    }
+    let i = (y - mask.top) as usize * width as usize + (x - mask.left) as usize;
+    sample(data, i, depth, false)
+}
+
+/// チャンネルデータから1サンプルを0.0-1.0で取得
+fn sample(data: &[u8], i: usize, depth: u16, is_color: bool) -> f32 {
+    match depth {
+        16 => {
+            let o = i * 2;
+            data.get(o..o + 2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]) as f32 / 65535.0)
+                .unwrap_or(0.0)
+        }
+        32 => {
+            let o = i * 4;
+            let v = data
+                .get(o..o + 4)
+                .map(|b| f32::from_be_bytes([b[0], b[1], b[2], b[3]]).clamp(0.0, 1.0))
+                .unwrap_or(0.0);
+            // 32bitはリニアで格納されている
+            if is_color { linear_to_srgb(v) } else { v }
+        }
+        _ => data.get(i).map(|&v| v as f32 / 255.0).unwrap_or(0.0),
+    }
+}
+
+/// レイヤーのチャンネルを読み込んでデコード
+fn decode_layer<R: Read + Seek>(ctx: &mut RenderContext<'_, R>, record: &LayerRecord) -> Result<DecodedLayer, String> {
+    let header = ctx.header;
+    let color_count = match header.color_mode {
+        COLOR_MODE_GRAYSCALE => 1,
+        COLOR_MODE_CMYK => 4,
+        _ => 3,
+    };
+
+    let mut layer = DecodedLayer {
+        color: vec![Vec::new(); color_count],
+        alpha: None,
+        mask: None,
+    };
+
+    for channel in &record.channels {
+        match channel.id {
+            -1 => {
+                layer.alpha = Some(read_channel(ctx.file, channel, record.width(), record.height(), &header)?);
+            }
+            -2 => {
+                if let Some(mask) = &record.mask {
+                    let w = (mask.right - mask.left).max(0) as u32;
+                    let h = (mask.bottom - mask.top).max(0) as u32;
+                    layer.mask = Some(read_channel(ctx.file, channel, w, h, &header)?);
+                }
+            }
+            id if id >= 0 && (id as usize) < color_count => {
+                layer.color[id as usize] = read_channel(ctx.file, channel, record.width(), record.height(), &header)?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(layer)
+}
+
+/// チャンネル画像データを1つデコード（非圧縮/RLE/ZIP/予測付きZIP）
+fn read_channel<R: Read + Seek>(
+    file: &mut R,
+    channel: &ChannelInfo,
+    width: u32,
+    height: u32,
+    header: &PsdHeader,
+) -> Result<Vec<u8>, String> {
+    let row_bytes = psd_row_bytes(width, header.depth);
+    let size = row_bytes * height as usize;
+    if channel.length < 2 || size == 0 {
+        return Ok(Vec::new());
+    }
+
+    file.seek(SeekFrom::Start(channel.offset))
+        .map_err(|e| format!("シークエラー: {}", e))?;
+    let compression = read_u16(file)?;
+    let data_len = channel.length - 2;
+
+    let mut output = vec![0u8; size];
+    match compression {
+        0 => {
+            let n = (data_len as usize).min(size);
+            read_bytes(file, &mut output[..n])?;
+        }
+        1 => {
+            let mut counts = Vec::with_capacity(height as usize);
+            for _ in 0..height {
+                counts.push(if header.version == 2 {
+                    read_u32(file)? as usize
+                } else {
+                    read_u16(file)? as usize
+                });
+            }
+            let mut compressed = Vec::new();
+            for (row, &count) in output.chunks_exact_mut(row_bytes).zip(counts.iter()) {
+                compressed.resize(count, 0);
+                read_bytes(file, &mut compressed)?;
+                decode_packbits(&compressed, row);
+            }
+        }
+        2 | 3 => {
+            let mut decoder = ZlibDecoder::new(file.take(data_len));
+            decoder
+                .read_exact(&mut output)
+                .map_err(|e| format!("ZIP展開エラー: {}", e))?;
+            if compression == 3 {
+                for row in output.chunks_exact_mut(row_bytes) {
+                    undo_zip_prediction(row, width as usize, header.depth);
+                }
+            }
+        }
+        c => return Err(format!("未対応の圧縮形式です: {}", c)),
+    }
+
+    Ok(output)
+}
+
+// ============================================================
+// 読み込みヘルパー
+// ============================================================
+
+fn max_value<T: Primitive>() -> f32 {
+    T::DEFAULT_MAX_VALUE.to_f32().unwrap_or(1.0)
+}
+
+fn from_unit<T: Primitive>(v: f32) -> T {
+    let max = max_value::<T>();
+    T::from((v.clamp(0.0, 1.0) * max + 0.5).min(max)).unwrap_or(T::zero())
+}
+
+fn position<R: Seek>(file: &mut R) -> Result<u64, String> {
+    file.stream_position()
+        .map_err(|e| format!("シークエラー: {}", e))
+}
+
+fn skip<R: Read>(file: &mut R, len: u64) -> Result<(), String> {
+    let skipped = std::io::copy(&mut file.take(len), &mut std::io::sink())
+        .map_err(|e| format!("PSD読み込みエラー: {}", e))?;
+    if skipped != len {
+        return Err("PSDデータが途中で終わっています".to_string());
+    }
+    Ok(())
+}
+
+fn read_bytes<R: Read>(file: &mut R, buf: &mut [u8]) -> Result<(), String> {
+    file.read_exact(buf)
+        .map_err(|e| format!("PSD読み込みエラー: {}", e))
+}
+
+fn read_u8<R: Read>(file: &mut R) -> Result<u8, String> {
+    let mut buf = [0u8; 1];
+    read_bytes(file, &mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16<R: Read>(file: &mut R) -> Result<u16, String> {
+    let mut buf = [0u8; 2];
+    read_bytes(file, &mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_i16<R: Read>(file: &mut R) -> Result<i16, String> {
+    let mut buf = [0u8; 2];
+    read_bytes(file, &mut buf)?;
+    Ok(i16::from_be_bytes(buf))
+}
+
+fn read_u32<R: Read>(file: &mut R) -> Result<u32, String> {
+    let mut buf = [0u8; 4];
+    read_bytes(file, &mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_i32<R: Read>(file: &mut R) -> Result<i32, String> {
+    let mut buf = [0u8; 4];
+    read_bytes(file, &mut buf)?;
+    Ok(i32::from_be_bytes(buf))
+}
+
+fn read_u64<R: Read>(file: &mut R) -> Result<u64, String> {
+    let mut buf = [0u8; 8];
+    read_bytes(file, &mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::types::LayerMatcher;
+    use std::io::{Cursor, Write};
+    use std::path::PathBuf;
+
+    /// tests/fixtures/layers.psd は7x1 RGBの合成用PSD（背面→前面）:
+    /// - 背景: 全体グレー(128)、非圧縮
+    /// - グループ（通過、マスクでx=1を隠す）
+    ///   - ベース: x=0〜1 赤、アルファ付きRLE
+    ///   - クリップ: 全体 青、ベースでクリッピング、ZIP
+    /// - 乗算: x=2 (255,128,0) 乗算、予測付きZIP
+    /// - 非表示: x=3 黒、保存時に非表示
+    /// - メモ: x=3 緑、RLE
+    /// - グループ2（通常）
+    ///   - 土台: x=4〜5 白、RLE
+    /// - クリップ2: x=4 マゼンタ、グループ2でクリッピング
+    /// - クリップグループ（通過、グループ2でクリッピング）
+    ///   - シアン: x=5〜6 シアン、ZIP
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+    }
+
+    fn matcher(name: &str, target: &str) -> LayerMatcher {
+        LayerMatcher {
+            name: name.to_string(),
+            target: target.to_string(),
+            match_mode: "exact".to_string(),
+        }
+    }
+
+    fn render(filter: &LayerFilter) -> Vec<[u8; 3]> {
+        let img = render_psd_layers(&fixture("layers.psd"), filter).unwrap().to_rgb8();
+        img.pixels().map(|p| p.0).collect()
+    }
+
+    #[test]
+    fn test_list_psd_layers() {
+        let layers = list_psd_layers(&fixture("layers.psd")).unwrap();
+        let names: Vec<&str> = layers.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["クリップグループ", "シアン", "クリップ2", "グループ2", "土台", "メモ", "非表示", "乗算", "グループ", "クリップ", "ベース", "背景"]
+        );
+
+        let group = &layers[8];
+        assert_eq!((group.kind.as_str(), group.blend_mode.as_str()), ("group", "pass_through"));
+        assert_eq!(layers[9].parent_id, Some(group.id));
+        assert_eq!(layers[10].parent_id, Some(group.id));
+        assert!(layers[9].clipping && !layers[10].clipping);
+        assert_eq!(layers[7].blend_mode, "multiply");
+        assert!(!layers[6].visible && layers[5].visible);
+        assert_eq!((layers[7].left, layers[7].right), (2, 3));
+        assert!(layers[0].clipping && layers[0].kind == "group" && layers[2].clipping);
+    }
+
+    #[test]
+    fn test_render_psd_layers() {
+        // クリップは赤いベースの範囲だけ青、x=1はグループマスクで背景が見える
+        // グループ2にクリッピングしたレイヤー・グループはグループ2の範囲（x=4〜5）だけ描画される
+        assert_eq!(
+            render(&LayerFilter::default()),
+            [[0, 0, 255], [128, 128, 128], [128, 64, 0], [0, 255, 0], [255, 0, 255], [0, 255, 255], [128, 128, 128]]
+        );
+    }
+
+    #[test]
+    fn test_nodes_region() {
+        // グループのキャンバスは子レイヤーの範囲だけ確保する
+        let (_, _, records) = open_psd_layers(&fixture("layers.psd")).unwrap();
+        let tree = build_layer_tree(&records);
+        let regions: Vec<Option<Region>> = tree
+            .iter()
+            .filter_map(|node| match node {
+                LayerNode::Group { children, .. } => Some(nodes_region(&records, children)),
+                LayerNode::Layer(_) => None,
+            })
+            .collect();
+        assert_eq!(
+            regions,
+            [
+                Some(Region { left: 0, top: 0, right: 7, bottom: 1 }),
+                Some(Region { left: 4, top: 0, right: 6, bottom: 1 }),
+                Some(Region { left: 5, top: 0, right: 7, bottom: 1 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_psd_layers_filter() {
+        // 除外したレイヤー/グループは中身ごと描画しない
+        let filter = LayerFilter { exclude: vec![matcher("メモ", "any")], ..Default::default() };
+        assert_eq!(render(&filter)[3], [128, 128, 128]);
+        let filter = LayerFilter { exclude: vec![matcher("グループ2", "group")], ..Default::default() };
+        assert_eq!(render(&filter)[4..], [[128, 128, 128], [128, 128, 128], [128, 128, 128]]);
+        let filter = LayerFilter { exclude: vec![matcher("グループ", "group")], ..Default::default() };
+        assert_eq!(render(&filter)[..2], [[128, 128, 128], [128, 128, 128]]);
+
+        // 対象が一致しない条件は無視される
+        let filter = LayerFilter { exclude: vec![matcher("グループ", "layer")], ..Default::default() };
+        assert_eq!(render(&filter)[0], [0, 0, 255]);
+
+        // 非表示レイヤーの表示
+        let filter = LayerFilter {
+            exclude: vec![matcher("メモ", "any")],
+            show_hidden: true,
+            ..Default::default()
+        };
+        assert_eq!(render(&filter)[3], [0, 0, 0]);
+
+        // includeはグループの中身だけを白背景に描画する
+        let filter = LayerFilter { include: vec![matcher("グループ", "group")], ..Default::default() };
+        assert_eq!(render(&filter)[..4], [[0, 0, 255], [255, 255, 255], [255, 255, 255], [255, 255, 255]]);
+
+        // クリッピングベースが描画されなければクリップされたレイヤーも描画しない
+        let filter = LayerFilter { exclude: vec![matcher("ベース", "layer")], ..Default::default() };
+        assert_eq!(render(&filter)[0], [128, 128, 128]);
+    }
+
+    #[test]
+    fn test_blend_channel() {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+        assert!(close(blend_channel(BlendMode::Normal, 0.2, 0.6), 0.6));
+        assert!(close(blend_channel(BlendMode::Multiply, 0.5, 0.5), 0.25));
+        assert!(close(blend_channel(BlendMode::Screen, 0.5, 0.5), 0.75));
+        assert!(close(blend_channel(BlendMode::Overlay, 0.25, 1.0), 0.5));
+        assert!(close(blend_channel(BlendMode::HardLight, 1.0, 0.25), 0.5));
+        assert!(close(blend_channel(BlendMode::SoftLight, 0.5, 0.5), 0.5));
+        assert!(close(blend_channel(BlendMode::Difference, 0.2, 0.6), 0.4));
+        assert!(close(blend_channel(BlendMode::LinearDodge, 0.7, 0.6), 1.0));
+        assert!(close(blend_channel(BlendMode::LinearBurn, 0.7, 0.6), 0.3));
+        assert!(close(blend_channel(BlendMode::ColorDodge, 0.25, 0.5), 0.5));
+        assert!(close(blend_channel(BlendMode::ColorBurn, 0.75, 0.5), 0.5));
+
+        assert_eq!(blend_mode_from_key(b"mul "), BlendMode::Multiply);
+        assert_eq!(blend_mode_from_key(b"hue "), BlendMode::Normal);
+        assert_eq!(blend_mode_name(b"sLit"), "soft_light");
+    }
+
+    #[test]
+    fn test_read_channel() {
+        // 16bitの予測付きZIP（サンプル単位の差分）
+        let header = PsdHeader {
+            version: 1,
+            channels: 1,
+            height: 1,
+            width: 3,
+            depth: 16,
+            color_mode: COLOR_MODE_GRAYSCALE,
+        };
+        let values = [0x1000u16, 0x0800, 0xFFFF];
+        let mut deltas = Vec::new();
+        let mut prev = 0u16;
+        for v in values {
+            deltas.extend_from_slice(&v.wrapping_sub(prev).to_be_bytes());
+            prev = v;
+        }
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&deltas).unwrap();
+        let mut data = vec![0xAA; 5]; // チャンネルより前の別データ
+        data.extend_from_slice(&3u16.to_be_bytes());
+        data.extend(encoder.finish().unwrap());
+
+        let channel = ChannelInfo { id: 0, offset: 5, length: data.len() as u64 - 5 };
+        let output = read_channel(&mut Cursor::new(&data), &channel, 3, 1, &header).unwrap();
+        let expected: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+        assert_eq!(output, expected);
+
+        // 範囲が空のレイヤー（グループなど）のチャンネルは空データ
+        let channel = ChannelInfo { id: 0, offset: 0, length: 2 };
+        assert!(read_channel(&mut Cursor::new(&[0u8, 0]), &channel, 0, 0, &header).unwrap().is_empty());
+
+        // 未対応の圧縮方式
+        let channel = ChannelInfo { id: 0, offset: 0, length: 4 };
+        assert!(read_channel(&mut Cursor::new(&[0u8, 9, 0, 0]), &channel, 3, 1, &header).is_err());
+    }
+}
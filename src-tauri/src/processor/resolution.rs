@@ -0,0 +1,208 @@
+//! タチミ - 解像度（dpi）メタデータの読み取り
+//! PSD: ResolutionInfo（1005） / JPEG: JFIF・EXIF / TIFF: XResolution / PNG: pHYs
+
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use super::image_loader::{extract_psd_resolution, is_psd_file};
+use super::jpeg::is_jpeg_file;
+
+/// JPEGのヘッダー解析で読み込む最大バイト数（APP0/APP1はファイル先頭にある）
+const JPEG_HEADER_READ_LIMIT: u64 = 256 * 1024;
+
+/// 有効とみなす解像度の範囲
+const MIN_VALID_DPI: f32 = 1.0;
+const MAX_VALID_DPI: f32 = 100_000.0;
+
+/// 画像ファイルの解像度（dpi、横方向）を読み取る
+/// 解像度情報がない・読めない場合は None
+pub fn read_image_dpi(path: &Path) -> Option<f32> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    let dpi = if is_psd_file(path) {
+        extract_psd_resolution(path)
+    } else if is_jpeg_file(path) {
+        let mut data = Vec::new();
+        File::open(path).ok()?.take(JPEG_HEADER_READ_LIMIT).read_to_end(&mut data).ok()?;
+        jpeg_dpi(&data)
+    } else if ext == "png" {
+        png_dpi(&mut BufReader::new(File::open(path).ok()?))
+    } else if ext == "tif" || ext == "tiff" {
+        tiff_dpi(&mut BufReader::new(File::open(path).ok()?))
+    } else {
+        None
+    };
+    dpi.filter(|dpi| (MIN_VALID_DPI..=MAX_VALID_DPI).contains(dpi))
+}
+
+/// 単位付きの密度をdpiに変換（unit: 1 = インチ, 2 = センチ）
+fn density_to_dpi(density: f32, unit: u16) -> Option<f32> {
+    match unit {
+        1 => Some(density),
+        2 => Some(density * 2.54),
+        _ => None,
+    }
+}
+
+/// JPEGのJFIF（APP0）またはEXIF（APP1）から解像度を取得
+/// JFIFが縦横比のみ（units=0）の場合はEXIFを参照する
+pub fn jpeg_dpi(data: &[u8]) -> Option<f32> {
+    if data.len() < 4 || data[0] != 0xFF || data[1] != 0xD8 {
+        return None;
+    }
+
+    let mut jfif_dpi = None;
+    let mut exif_dpi = None;
+    let mut i = 2;
+    while i + 4 <= data.len() && data[i] == 0xFF {
+        let marker = data[i + 1];
+        // SOS以降はヘッダーではない
+        if marker == 0xDA {
+            break;
+        }
+        let length = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+        let end = (i + 2 + length).min(data.len());
+        let segment = &data[(i + 4).min(end)..end];
+
+        match marker {
+            // JFIF: "JFIF\0" version(2) units(1) Xdensity(2) Ydensity(2)
+            0xE0 if segment.len() >= 12 && segment.starts_with(b"JFIF\0") => {
+                let density = u16::from_be_bytes([segment[8], segment[9]]) as f32;
+                jfif_dpi = density_to_dpi(density, segment[7] as u16);
+            }
+            // EXIF: "Exif\0\0" + TIFF構造
+            0xE1 if segment.starts_with(b"Exif\0\0") => {
+                exif_dpi = tiff_dpi(&mut Cursor::new(&segment[6..]));
+            }
+            _ => {}
+        }
+        i += 2 + length;
+    }
+
+    jfif_dpi.or(exif_dpi)
+}
+
+/// PNGのpHYsチャンクから解像度を取得（単位がメートルの場合のみ）
+pub fn png_dpi<R: Read>(reader: &mut R) -> Option<f32> {
+    let mut signature = [0u8; 8];
+    reader.read_exact(&mut signature).ok()?;
+    if signature != *b"\x89PNG\r\n\x1a\n" {
+        return None;
+    }
+
+    loop {
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header).ok()?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        match &header[4..8] {
+            b"pHYs" if length == 9 => {
+                let mut data = [0u8; 9];
+                reader.read_exact(&mut data).ok()?;
+                let ppu_x = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                return (data[8] == 1).then_some(ppu_x as f32 * 0.0254);
+            }
+            // pHYsは画像データより前にある
+            b"IDAT" | b"IEND" => return None,
+            _ => {
+                // チャンクデータ + CRC を読み飛ばす
+                std::io::copy(&mut reader.by_ref().take(length + 4), &mut std::io::sink()).ok()?;
+            }
+        }
+    }
+}
+
+/// TIFF（およびEXIF）の先頭IFDからXResolution / ResolutionUnitを取得
+pub fn tiff_dpi<R: Read + Seek>(reader: &mut R) -> Option<f32> {
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header).ok()?;
+    let big_endian = match &header[0..4] {
+        b"MM\0*" => true,
+        b"II*\0" => false,
+        _ => return None,
+    };
+    let u16_at = |b: &[u8]| if big_endian { u16::from_be_bytes([b[0], b[1]]) } else { u16::from_le_bytes([b[0], b[1]]) };
+    let u32_at = |b: &[u8]| {
+        if big_endian { u32::from_be_bytes([b[0], b[1], b[2], b[3]]) } else { u32::from_le_bytes([b[0], b[1], b[2], b[3]]) }
+    };
+
+    reader.seek(SeekFrom::Start(u32_at(&header[4..8]) as u64)).ok()?;
+    let mut count = [0u8; 2];
+    reader.read_exact(&mut count).ok()?;
+    let mut entries = vec![0u8; u16_at(&count) as usize * 12];
+    reader.read_exact(&mut entries).ok()?;
+
+    // ResolutionUnitの既定値は2（インチ）
+    let mut x_resolution_offset = None;
+    let mut unit = 2u16;
+    for entry in entries.chunks_exact(12) {
+        match u16_at(&entry[0..2]) {
+            // XResolution: RATIONAL（値はオフセット先）
+            0x011A => x_resolution_offset = Some(u32_at(&entry[8..12]) as u64),
+            // ResolutionUnit: SHORT（値はエントリ内）
+            0x0128 => unit = u16_at(&entry[8..10]),
+            _ => {}
+        }
+    }
+
+    reader.seek(SeekFrom::Start(x_resolution_offset?)).ok()?;
+    let mut rational = [0u8; 8];
+    reader.read_exact(&mut rational).ok()?;
+    let (numerator, denominator) = (u32_at(&rational[0..4]), u32_at(&rational[4..8]));
+    if denominator == 0 {
+        return None;
+    }
+    // TIFFのResolutionUnit: 2 = インチ, 3 = センチ
+    density_to_dpi(numerator as f32 / denominator as f32, unit.saturating_sub(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::image_writer::write_png;
+    use crate::processor::jpeg::encode_jpeg_mozjpeg;
+    use crate::processor::types::{JpegOptions, PngOptions};
+    use ::image::{DynamicImage, RgbImage};
+
+    #[test]
+    fn test_jpeg_jfif_dpi() {
+        let rgb = RgbImage::new(8, 8);
+        let options = JpegOptions { dpi: 600, ..JpegOptions::default() };
+        let data = encode_jpeg_mozjpeg(rgb.as_raw(), 8, 8, &options, None).unwrap();
+        assert_eq!(jpeg_dpi(&data), Some(600.0));
+    }
+
+    #[test]
+    fn test_png_phys_dpi() {
+        let img = DynamicImage::ImageRgb8(RgbImage::new(4, 4));
+        let path = std::env::temp_dir().join(format!("tachimi_dpi_{}.png", std::process::id()));
+        write_png(&img, None, &PngOptions::default(), 1200, &path).unwrap();
+        let dpi = read_image_dpi(&path);
+        std::fs::remove_file(&path).ok();
+        assert!((dpi.unwrap() - 1200.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_tiff_dpi_in_centimeters() {
+        // リトルエンディアン、IFDエントリ2つ（XResolution = 236/1 px/cm, ResolutionUnit = 3）
+        let mut data = b"II*\0".to_vec();
+        data.extend(8u32.to_le_bytes());
+        data.extend(2u16.to_le_bytes());
+        data.extend([0x1A, 0x01, 5, 0]);
+        data.extend(1u32.to_le_bytes());
+        data.extend(38u32.to_le_bytes());
+        data.extend([0x28, 0x01, 3, 0]);
+        data.extend(1u32.to_le_bytes());
+        data.extend([3, 0, 0, 0]);
+        data.extend(0u32.to_le_bytes());
+        data.extend(236u32.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+
+        let dpi = tiff_dpi(&mut Cursor::new(&data)).unwrap();
+        assert!((dpi - 599.44).abs() < 0.01);
+    }
+}
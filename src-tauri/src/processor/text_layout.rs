@@ -0,0 +1,331 @@
+//! タチミ - 文字組み（横書き・縦書き）
+//! 画像への文字描画で使う文字幅の計算・行の折り返しと、縦書きの組版
+//! 縦書きは句読点・括弧を縦書き用の字形に置き換え（フォントになければ位置をずらす・横倒し）、
+//! 長音・波ダッシュと英字の並びは横倒し、2〜3桁の数字は縦中横にする
+//! 描画先は TextCanvas で抽象化し、画像（ラスター）とPDF（テキスト）で同じ組版を使う
+
+use ::image::imageops::{self, FilterType};
+use ::image::{GrayImage, Luma, Rgba, RgbaImage};
+use ab_glyph::{Font, GlyphId, PxScale, ScaleFont};
+use imageproc::drawing::{draw_filled_rect_mut, draw_text_mut};
+use imageproc::rect::Rect;
+
+/// 組み方向: 縦書き（それ以外は横書き）
+pub const WRITING_MODE_VERTICAL: &str = "vertical";
+
+/// 縦中横にする数字の最大桁数（これより長い英数字の並びは横倒し）
+const TATE_CHU_YOKO_MAX_DIGITS: usize = 3;
+
+/// 行頭に置かない文字（行末にぶら下げる）
+const NO_LINE_START: &[char] = &[
+    '、', '。', '，', '．', ',', '.', '」', '』', '）', '】', '〉', '》', '〕', '］', '｝', ')', ']',
+    '！', '？', '!', '?', '・', '：', '；', 'ー', '…', '‥', '々', 'ゝ', 'ゞ', 'ヽ', 'ヾ',
+    'ぁ', 'ぃ', 'ぅ', 'ぇ', 'ぉ', 'っ', 'ゃ', 'ゅ', 'ょ', 'ゎ',
+    'ァ', 'ィ', 'ゥ', 'ェ', 'ォ', 'ッ', 'ャ', 'ュ', 'ョ', 'ヮ', 'ヵ', 'ヶ',
+];
+
+/// 縦書きで右上に寄せる小書きの仮名
+const SMALL_KANA: &[char] = &[
+    'ぁ', 'ぃ', 'ぅ', 'ぇ', 'ぉ', 'っ', 'ゃ', 'ゅ', 'ょ', 'ゎ',
+    'ァ', 'ィ', 'ゥ', 'ェ', 'ォ', 'ッ', 'ャ', 'ュ', 'ョ', 'ヮ', 'ヵ', 'ヶ',
+];
+
+/// 縦書きで横倒しにする文字（縦書き用の字形がないもの）
+const ROTATED_IN_VERTICAL: &[char] = &['ー', '〜', '～', '－', '-', '‐', '＝', '=', '→', '←', '<', '>', '＜', '＞'];
+
+/// 縦書きの1マス
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerticalCell {
+    /// 1文字（縦書き用の字形・横倒し・位置の調整は描画時に決める）
+    Char(char),
+    /// 縦中横（数字を横に並べて1マスに収める）
+    TateChuYoko(String),
+    /// 横倒し（英字などの並び）
+    Sideways(String),
+}
+
+/// 文字列の幅（カーニング込み）
+pub fn text_width<F: Font>(font: &F, scale: PxScale, text: &str) -> f32 {
+    let scaled = font.as_scaled(scale);
+    let mut width = 0.0;
+    let mut prev: Option<GlyphId> = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(prev) = prev {
+            width += scaled.kern(prev, id);
+        }
+        width += scaled.h_advance(id);
+        prev = Some(id);
+    }
+    width
+}
+
+/// 横書きの行を max_width に収まるよう折り返す（行頭禁則の文字は前の行の末尾にぶら下げる）
+pub fn wrap_horizontal<F: Font>(font: &F, scale: PxScale, text: &str, max_width: f32) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for &c in &chars {
+        let mut candidate = line.clone();
+        candidate.push(c);
+        if !line.is_empty() && !NO_LINE_START.contains(&c) && text_width(font, scale, &candidate) > max_width {
+            lines.push(std::mem::take(&mut line));
+        }
+        line.push(c);
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// 縦書きのマスに分ける
+pub fn vertical_cells(text: &str) -> Vec<VerticalCell> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut cells = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i].is_ascii_alphanumeric() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || (chars[i].is_ascii_punctuation() && chars[i] != '-')) {
+                i += 1;
+            }
+            let run: String = chars[start..i].iter().collect();
+            if run.chars().all(|c| c.is_ascii_digit()) && run.len() <= TATE_CHU_YOKO_MAX_DIGITS {
+                cells.push(VerticalCell::TateChuYoko(run));
+            } else {
+                cells.push(VerticalCell::Sideways(run));
+            }
+        } else {
+            cells.push(VerticalCell::Char(chars[i]));
+            i += 1;
+        }
+    }
+    cells
+}
+
+/// 縦書き用の字形（Unicodeの縦書き用形）
+fn vertical_form(c: char) -> Option<char> {
+    Some(match c {
+        '、' => '\u{FE11}',
+        '。' => '\u{FE12}',
+        '：' => '\u{FE13}',
+        '；' => '\u{FE14}',
+        '！' => '\u{FE15}',
+        '？' => '\u{FE16}',
+        '…' => '\u{FE19}',
+        '‥' => '\u{FE30}',
+        '—' | '―' => '\u{FE31}',
+        '（' => '\u{FE35}',
+        '）' => '\u{FE36}',
+        '｛' => '\u{FE37}',
+        '｝' => '\u{FE38}',
+        '〔' => '\u{FE39}',
+        '〕' => '\u{FE3A}',
+        '【' => '\u{FE3B}',
+        '】' => '\u{FE3C}',
+        '《' => '\u{FE3D}',
+        '》' => '\u{FE3E}',
+        '〈' => '\u{FE3F}',
+        '〉' => '\u{FE40}',
+        '「' => '\u{FE41}',
+        '」' => '\u{FE42}',
+        '『' => '\u{FE43}',
+        '』' => '\u{FE44}',
+        '［' => '\u{FE47}',
+        '］' => '\u{FE48}',
+        _ => return None,
+    })
+}
+
+/// 縦書きのマスの高さ
+fn cell_height<F: Font>(font: &F, scale: PxScale, cell: &VerticalCell) -> f32 {
+    match cell {
+        VerticalCell::Sideways(text) => text_width(font, scale, text),
+        _ => scale.y,
+    }
+}
+
+/// 縦書きの列の高さ
+pub fn column_height<F: Font>(font: &F, scale: PxScale, cells: &[VerticalCell]) -> f32 {
+    cells.iter().map(|cell| cell_height(font, scale, cell)).sum()
+}
+
+/// 縦書きの列を max_height に収まるよう折り返す（行頭禁則の文字は前の列の末尾にぶら下げる）
+pub fn wrap_vertical<F: Font>(font: &F, scale: PxScale, cells: Vec<VerticalCell>, max_height: f32) -> Vec<Vec<VerticalCell>> {
+    let mut columns = Vec::new();
+    let mut column: Vec<VerticalCell> = Vec::new();
+    let mut height = 0.0;
+    for cell in cells {
+        let cell_h = cell_height(font, scale, &cell);
+        let hangs = matches!(cell, VerticalCell::Char(c) if NO_LINE_START.contains(&c));
+        if !column.is_empty() && !hangs && height + cell_h > max_height {
+            columns.push(std::mem::take(&mut column));
+            height = 0.0;
+        }
+        height += cell_h;
+        column.push(cell);
+    }
+    if !column.is_empty() || columns.is_empty() {
+        columns.push(column);
+    }
+    columns
+}
+
+/// 文字の描画先（画像・PDF）
+/// 座標はページ左上を原点とするピクセル。文字の位置は行の箱（幅 = 文字列の幅、高さ = アセント - ディセント）の左上で指定する
+pub trait TextCanvas {
+    /// 横書きで描く（max_width を超える場合は横方向に縮める）
+    fn draw_text(&mut self, text: &str, x: f32, y: f32, scale: PxScale, max_width: Option<f32>, color: Rgba<u8>);
+    /// 時計回りに90度回して描く（x, y は回した後の箱の左上）
+    fn draw_text_sideways(&mut self, text: &str, x: f32, y: f32, scale: PxScale, color: Rgba<u8>);
+    /// 矩形を塗る（罫線用）
+    fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: Rgba<u8>);
+}
+
+/// 画像への描画
+pub struct ImageCanvas<'a, F: Font> {
+    pub img: &'a mut RgbaImage,
+    pub font: &'a F,
+}
+
+impl<F: Font> TextCanvas for ImageCanvas<'_, F> {
+    fn draw_text(&mut self, text: &str, x: f32, y: f32, scale: PxScale, max_width: Option<f32>, color: Rgba<u8>) {
+        let mask = text_mask(self.font, scale, text);
+        let mask = match max_width {
+            Some(max_width) if mask.width() as f32 > max_width => {
+                imageops::resize(&mask, max_width.max(1.0) as u32, mask.height(), FilterType::Triangle)
+            }
+            _ => mask,
+        };
+        blend_mask(self.img, &mask, x, y, color);
+    }
+
+    fn draw_text_sideways(&mut self, text: &str, x: f32, y: f32, scale: PxScale, color: Rgba<u8>) {
+        let mask = imageops::rotate90(&text_mask(self.font, scale, text));
+        blend_mask(self.img, &mask, x, y, color);
+    }
+
+    fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: Rgba<u8>) {
+        let rect = Rect::at(x.round() as i32, y.round() as i32).of_size(width.round().max(1.0) as u32, height.round().max(1.0) as u32);
+        draw_filled_rect_mut(self.img, rect, color);
+    }
+}
+
+/// 行の箱の高さ（アセント - ディセント）
+pub fn line_box_height<F: Font>(font: &F, scale: PxScale) -> f32 {
+    font.as_scaled(scale).height()
+}
+
+/// 縦書きの1列を描く（x_center: 列の中心、top: 列の上端）
+pub fn draw_vertical_column<F: Font, C: TextCanvas>(
+    canvas: &mut C,
+    font: &F,
+    scale: PxScale,
+    cells: &[VerticalCell],
+    x_center: f32,
+    top: f32,
+    color: Rgba<u8>,
+) {
+    let em = scale.y;
+    let has_glyph = |c: char| font.glyph_id(c).0 != 0;
+    let mut y = top;
+
+    for cell in cells {
+        let cell_h = cell_height(font, scale, cell);
+        let upright = |canvas: &mut C, c: char, offset: (f32, f32)| {
+            draw_upright(canvas, font, scale, &c.to_string(), x_center, y, offset, color)
+        };
+        let sideways = |canvas: &mut C, text: &str| {
+            let x = x_center - line_box_height(font, scale) / 2.0;
+            canvas.draw_text_sideways(text, x, y, scale, color)
+        };
+        match cell {
+            VerticalCell::Char(c) => {
+                let c = *c;
+                match vertical_form(c) {
+                    Some(v) if has_glyph(v) => upright(canvas, v, (0.0, 0.0)),
+                    // 句読点はフォントに縦書き用の字形がなければ右上へ移す
+                    Some(_) if matches!(c, '、' | '。') => upright(canvas, c, (em * 0.6, -em * 0.6)),
+                    Some(_) => sideways(canvas, &c.to_string()),
+                    None if ROTATED_IN_VERTICAL.contains(&c) => sideways(canvas, &c.to_string()),
+                    None if SMALL_KANA.contains(&c) => upright(canvas, c, (em * 0.1, -em * 0.1)),
+                    None => upright(canvas, c, (0.0, 0.0)),
+                }
+            }
+            VerticalCell::TateChuYoko(text) => {
+                // 1マスの幅を超える場合は横方向に縮める
+                let width = text_width(font, scale, text).min(em);
+                let y_offset = (em - line_box_height(font, scale)) / 2.0;
+                canvas.draw_text(text, x_center - width / 2.0, y + y_offset, scale, Some(em), color);
+            }
+            VerticalCell::Sideways(text) => sideways(canvas, text),
+        }
+        y += cell_h;
+    }
+}
+
+/// 正立で描く（マスの中央、offset で位置を調整）
+#[allow(clippy::too_many_arguments)]
+fn draw_upright<F: Font, C: TextCanvas>(
+    canvas: &mut C,
+    font: &F,
+    scale: PxScale,
+    text: &str,
+    x_center: f32,
+    top: f32,
+    offset: (f32, f32),
+    color: Rgba<u8>,
+) {
+    let x = x_center - text_width(font, scale, text) / 2.0 + offset.0;
+    let y = top + (scale.y - line_box_height(font, scale)) / 2.0 + offset.1;
+    canvas.draw_text(text, x, y, scale, None, color);
+}
+
+/// 文字の形（濃さ）を横書きで描いたマスク
+fn text_mask<F: Font>(font: &F, scale: PxScale, text: &str) -> GrayImage {
+    let width = text_width(font, scale, text).ceil().max(1.0) as u32;
+    let height = line_box_height(font, scale).ceil().max(1.0) as u32;
+    let mut mask = GrayImage::new(width, height);
+    draw_text_mut(&mut mask, Luma([255]), 0, 0, scale, font, text);
+    mask
+}
+
+/// マスクの濃さで色を重ねる
+fn blend_mask(img: &mut RgbaImage, mask: &GrayImage, x: f32, y: f32, color: Rgba<u8>) {
+    let (x, y) = (x.round() as i64, y.round() as i64);
+    let (width, height) = (img.width() as i64, img.height() as i64);
+    for (mx, my, coverage) in mask.enumerate_pixels() {
+        let (px, py) = (x + mx as i64, y + my as i64);
+        if coverage[0] == 0 || px < 0 || py < 0 || px >= width || py >= height {
+            continue;
+        }
+        let alpha = coverage[0] as f32 / 255.0;
+        let pixel = img.get_pixel_mut(px as u32, py as u32);
+        for c in 0..3 {
+            pixel[c] = (pixel[c] as f32 * (1.0 - alpha) + color[c] as f32 * alpha).round() as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vertical_cells() {
+        assert_eq!(
+            vertical_cells("第12巻"),
+            vec![
+                VerticalCell::Char('第'),
+                VerticalCell::TateChuYoko("12".to_string()),
+                VerticalCell::Char('巻'),
+            ]
+        );
+        assert_eq!(
+            vertical_cells("Vol.1024"),
+            vec![VerticalCell::Sideways("Vol.1024".to_string())]
+        );
+        assert_eq!(vertical_cells("ー"), vec![VerticalCell::Char('ー')]);
+    }
+}
@@ -0,0 +1,87 @@
+//! タチミ - トンボ（トリムマーク）の形状計算
+//! 日本式のダブルトンボ（仕上がり線 + 裁ち落とし線）とセンタートンボを線分として求める
+//! 画像（ラスター）とPDF（ベクター）で同じ形状を使う
+
+use super::types::TrimMarkOptions;
+
+/// トンボの外側に確保する余白（mm）
+const MARK_CLEARANCE_MM: f32 = 1.0;
+
+/// トンボの線分（mm、仕上がり枠の角を原点とし、枠外は負または幅・高さ超の座標）
+/// 水平・垂直の線のみ
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarkSegment {
+    pub x1: f32,
+    pub y1: f32,
+    pub x2: f32,
+    pub y2: f32,
+}
+
+/// 仕上がり枠の外側に必要な幅（mm）: 裁ち落とし + トンボまでの距離 + トンボの長さ + 余白
+pub fn trim_mark_margin_mm(options: &TrimMarkOptions) -> f32 {
+    options.bleed_mm + options.offset_mm + options.length_mm + MARK_CLEARANCE_MM
+}
+
+/// 仕上がりサイズ（mm）に対するトンボの線分を求める
+/// 上下左右対称のため、y軸の向き（画像は下向き、PDFは上向き）によらず使える
+pub fn trim_mark_segments(trim_width: f32, trim_height: f32, options: &TrimMarkOptions) -> Vec<MarkSegment> {
+    let bleed = options.bleed_mm;
+    let start = bleed + options.offset_mm;
+    let end = start + options.length_mm;
+    let mut segments = Vec::new();
+
+    // 角トンボ: 仕上がり線の延長（内トンボ）と、裁ち落とし角からのL字（外トンボ）
+    for (cx, dx) in [(0.0, -1.0), (trim_width, 1.0)] {
+        for (cy, dy) in [(0.0, -1.0), (trim_height, 1.0)] {
+            segments.push(MarkSegment { x1: cx + dx * start, y1: cy, x2: cx + dx * end, y2: cy });
+            segments.push(MarkSegment { x1: cx, y1: cy + dy * start, x2: cx, y2: cy + dy * end });
+            let (bx, by) = (cx + dx * bleed, cy + dy * bleed);
+            segments.push(MarkSegment { x1: bx, y1: by, x2: cx + dx * end, y2: by });
+            segments.push(MarkSegment { x1: bx, y1: by, x2: bx, y2: cy + dy * end });
+        }
+    }
+
+    // センタートンボ: 各辺の中央に十字
+    if options.center_marks {
+        let half = options.length_mm / 2.0;
+        let (mid_x, mid_y) = (trim_width / 2.0, trim_height / 2.0);
+        for (cy, dy) in [(0.0, -1.0), (trim_height, 1.0)] {
+            segments.push(MarkSegment { x1: mid_x, y1: cy + dy * start, x2: mid_x, y2: cy + dy * end });
+            let bar_y = cy + dy * (start + half);
+            segments.push(MarkSegment { x1: mid_x - half, y1: bar_y, x2: mid_x + half, y2: bar_y });
+        }
+        for (cx, dx) in [(0.0, -1.0), (trim_width, 1.0)] {
+            segments.push(MarkSegment { x1: cx + dx * start, y1: mid_y, x2: cx + dx * end, y2: mid_y });
+            let bar_x = cx + dx * (start + half);
+            segments.push(MarkSegment { x1: bar_x, y1: mid_y - half, x2: bar_x, y2: mid_y + half });
+        }
+    }
+
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_marks_stay_outside_art() {
+        let options = TrimMarkOptions::default();
+        let (width, height) = (182.0, 257.0);
+        let margin = trim_mark_margin_mm(&options);
+        let bleed = options.bleed_mm;
+
+        let segments = trim_mark_segments(width, height, &options);
+        assert_eq!(segments.len(), 16 + 8);
+        for s in segments {
+            for (x, y) in [(s.x1, s.y1), (s.x2, s.y2)] {
+                // 余白内に収まる
+                assert!(x >= -margin && x <= width + margin && y >= -margin && y <= height + margin);
+                // 裁ち落とし込みの絵柄の内側には入らない（外トンボの角が接するのみ）
+                let inside_x = x > -bleed && x < width + bleed;
+                let inside_y = y > -bleed && y < height + bleed;
+                assert!(!(inside_x && inside_y));
+            }
+        }
+    }
+}
@@ -27,7 +27,8 @@ pub struct ProcessOptions {
     pub crop_top: u32,
     pub crop_right: u32,
     pub crop_bottom: u32,
-    /// タチキリ処理タイプ: "none", "crop_only", "crop_and_stroke", "stroke_only", "fill_white", "fill_and_stroke"
+    /// タチキリ処理タイプ: "none", "crop_only", "crop_and_stroke", "stroke_only", "fill_white", "fill_and_stroke",
+    /// "trim_marks"（クロップ範囲を仕上がりとして裁ち落とし付きで切り出し、外側にトンボを描く）
     pub tachikiri_type: String,
     /// 線の色: "black", "white", "cyan"
     pub stroke_color: String,
@@ -47,17 +48,461 @@ pub struct ProcessOptions {
     pub nombre_start_number: u32,
     #[serde(default = "default_nombre_size")]
     pub nombre_size: String, // "small", "medium", "large", "xlarge"
+    /// ノンブルの縦位置・表記・色など（横位置は left_page / right_page）
+    #[serde(default)]
+    pub nombre: NombreOptions,
     /// リサイズ設定
     #[serde(default = "default_resize_mode")]
     pub resize_mode: String, // "none", "percent", "fixed"
     #[serde(default = "default_resize_percent")]
     pub resize_percent: u32,
+    /// PSDレイヤー表示ルール（指定時は統合画像ではなくレイヤーを合成する）
+    #[serde(default)]
+    pub layer_filter: Option<LayerFilter>,
+    /// 出力カラープロファイル: "keep"（変換せず元のプロファイルを埋め込む）, "srgb", "gray_gamma22"
+    #[serde(default = "default_color_profile")]
+    pub color_profile: String,
+    /// 出力カラーモード: "auto"（モノクロ原稿はグレースケール）, "rgb", "gray"
+    #[serde(default = "default_color_mode")]
+    pub color_mode: String,
+    /// JPEGエンコード設定
+    #[serde(default)]
+    pub jpeg: JpegOptions,
+    /// 出力形式: "jpeg", "png", "tiff", "webp"（ロスレス）, "psd"
+    #[serde(default = "default_output_format")]
+    pub output_format: String,
+    /// PNG出力設定
+    #[serde(default)]
+    pub png: PngOptions,
+    /// TIFF出力設定
+    #[serde(default)]
+    pub tiff: TiffOptions,
+    /// 出力ファイル名テンプレート（拡張子なし）: 例 "{title}_{volume}_{page:03}"
+    #[serde(default = "default_naming_template")]
+    pub naming_template: String,
+    /// 作品情報（ファイル名テンプレートの {title} などに使用）
+    #[serde(default)]
+    pub work_info: Option<WorkInfo>,
+    /// 解像度の上書き（dpi）。指定時は元画像の解像度情報を無視してこの値として扱う
+    #[serde(default)]
+    pub dpi_override: Option<f32>,
+    /// トンボ設定（tachikiri_type が "trim_marks" の場合に使用）
+    #[serde(default)]
+    pub trim_marks: TrimMarkOptions,
+    /// 裁ち切り枠（crop_* の仕上がり枠の外側、基準ドキュメント座標）
+    #[serde(default)]
+    pub bleed_frame: Option<FrameRect>,
+    /// 内枠（安全領域、基準ドキュメント座標）
+    #[serde(default)]
+    pub safe_frame: Option<FrameRect>,
+    /// 枠ごとの線・塗り（タチキリ処理タイプの線・塗りに加えて描画する）
+    #[serde(default)]
+    pub trim_style: FrameStyle,
+    #[serde(default)]
+    pub bleed_style: FrameStyle,
+    #[serde(default)]
+    pub safe_style: FrameStyle,
+    /// クロップ系のタチキリ処理で切り出す枠: "trim"（仕上がり）, "bleed"（裁ち切り、未指定なら仕上がり）
+    #[serde(default = "default_crop_frame")]
+    pub crop_frame: String,
+    /// 仕上がり枠の取得元: "global"（crop_* を全ファイル共通で使う）, "guides"（PSDごとのガイドから求める）,
+    /// "auto"（トンボ・紙の境界から自動検出する）
+    #[serde(default = "default_crop_source")]
+    pub crop_source: String,
+    /// 自動検出の結果を使う最低の信頼度（0.0〜1.0、下回れば crop_* を使う）
+    #[serde(default = "default_auto_crop_min_confidence")]
+    pub auto_crop_min_confidence: f32,
+    /// ガイドの選び方: "nearest"（crop_* に最も近いガイド）, "innermost"（最も内側）, "outermost"（最も外側）
+    #[serde(default = "default_guide_rule")]
+    pub guide_rule: String,
+    /// スキャン原稿の傾き補正・位置合わせ（クロップ・塗り・ノンブルの前に行う）
+    #[serde(default)]
+    pub align: AlignOptions,
+    /// 綴じ方向: "right"（右綴じ、奇数ページが左ページ）, "left"（左綴じ、奇数ページが右ページ）
+    #[serde(default = "default_binding")]
+    pub binding: String,
+    /// 左ページ・右ページごとの設定
+    #[serde(default)]
+    pub left_page: PageSideOptions,
+    #[serde(default)]
+    pub right_page: PageSideOptions,
 }
 
 pub fn default_nombre_start() -> u32 { 1 }
 pub fn default_nombre_size() -> String { "medium".to_string() }
 pub fn default_resize_mode() -> String { "none".to_string() }
 pub fn default_resize_percent() -> u32 { 50 }
+pub fn default_color_profile() -> String { "keep".to_string() }
+pub fn default_color_mode() -> String { "auto".to_string() }
+pub fn default_output_format() -> String { "jpeg".to_string() }
+pub fn default_crop_frame() -> String { "trim".to_string() }
+pub fn default_crop_source() -> String { "global".to_string() }
+pub fn default_guide_rule() -> String { "nearest".to_string() }
+pub fn default_auto_crop_min_confidence() -> f32 { 0.5 }
+pub fn default_binding() -> String { "right".to_string() }
+pub fn default_page_fit() -> String { "fit".to_string() }
+pub fn default_naming_template() -> String { "{stem}".to_string() }
+pub fn default_generated_text() -> String { "vector".to_string() }
+
+/// JPEGエンコード設定
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JpegOptions {
+    /// 品質: 1-100
+    #[serde(default = "default_jpeg_quality")]
+    pub quality: u8,
+    /// クロマサブサンプリング: "444", "420"（グレースケールJPEGでは無視）
+    #[serde(default = "default_jpeg_subsampling")]
+    pub subsampling: String,
+    /// プログレッシブJPEGにするか（falseならベースライン）
+    #[serde(default = "default_true")]
+    pub progressive: bool,
+    /// ハフマンテーブル・スキャン構成を最適化するか
+    #[serde(default = "default_true")]
+    pub optimize: bool,
+    /// トレリス量子化を行うか（mozjpegの最大圧縮プロファイルの一部のため、プログレッシブ時のみ有効）
+    #[serde(default = "default_true")]
+    pub trellis: bool,
+    /// 書き込む解像度（dpi）の既定値。元画像に解像度情報がない場合に使う。0なら縦横比のみ
+    #[serde(default = "default_jpeg_dpi")]
+    pub dpi: u16,
+}
+
+impl Default for JpegOptions {
+    fn default() -> Self {
+        Self {
+            quality: default_jpeg_quality(),
+            subsampling: default_jpeg_subsampling(),
+            progressive: true,
+            optimize: true,
+            trellis: true,
+            dpi: default_jpeg_dpi(),
+        }
+    }
+}
+
+pub fn default_true() -> bool { true }
+pub fn default_jpeg_quality() -> u8 { 95 }
+pub fn default_jpeg_subsampling() -> String { "420".to_string() }
+pub fn default_jpeg_dpi() -> u16 { 350 }
+/// PDF埋め込み用のJPEG設定（PDFは最高品質で埋め込む）
+pub fn default_pdf_jpeg_options() -> JpegOptions {
+    JpegOptions { quality: 100, ..JpegOptions::default() }
+}
+
+/// 枠の座標（crop_* と同じく基準ドキュメントサイズでスケーリングされる）
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct FrameRect {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+/// 枠の描画設定
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FrameStyle {
+    /// 枠線を描くか
+    #[serde(default)]
+    pub stroke: bool,
+    /// 枠の外側を塗るか（不透明度は fill_opacity）
+    #[serde(default)]
+    pub fill_outside: bool,
+    /// 線・塗りの色（未指定なら stroke_color / fill_color）
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+/// トンボ（トリムマーク）設定（単位はすべてmm）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrimMarkOptions {
+    /// 裁ち落とし幅
+    #[serde(default = "default_trim_bleed_mm")]
+    pub bleed_mm: f32,
+    /// トンボの長さ
+    #[serde(default = "default_trim_mark_length_mm")]
+    pub length_mm: f32,
+    /// 裁ち落とし線からトンボまでの距離
+    #[serde(default = "default_trim_mark_offset_mm")]
+    pub offset_mm: f32,
+    /// 線の太さ
+    #[serde(default = "default_trim_mark_line_width_mm")]
+    pub line_width_mm: f32,
+    /// センタートンボを描くか
+    #[serde(default = "default_true")]
+    pub center_marks: bool,
+}
+
+impl Default for TrimMarkOptions {
+    fn default() -> Self {
+        Self {
+            bleed_mm: default_trim_bleed_mm(),
+            length_mm: default_trim_mark_length_mm(),
+            offset_mm: default_trim_mark_offset_mm(),
+            line_width_mm: default_trim_mark_line_width_mm(),
+            center_marks: true,
+        }
+    }
+}
+
+pub fn default_trim_bleed_mm() -> f32 { 3.0 }
+pub fn default_trim_mark_length_mm() -> f32 { 10.0 }
+pub fn default_trim_mark_offset_mm() -> f32 { 3.0 }
+pub fn default_trim_mark_line_width_mm() -> f32 { 0.1 }
+
+/// 左ページ・右ページごとの設定（画像出力）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PageSideOptions {
+    /// 仕上がり枠（crop_* と裁ち切り枠・内枠）のずらし量（基準ドキュメント座標）
+    #[serde(default)]
+    pub crop_offset_x: i32,
+    #[serde(default)]
+    pub crop_offset_y: i32,
+    /// ノンブルの横位置: "center"（中央）, "outer"（小口側）, "inner"（ノド側）
+    #[serde(default = "default_nombre_position")]
+    pub nombre_position: String,
+}
+
+impl Default for PageSideOptions {
+    fn default() -> Self {
+        Self {
+            crop_offset_x: 0,
+            crop_offset_y: 0,
+            nombre_position: default_nombre_position(),
+        }
+    }
+}
+
+/// 左ページ・右ページごとの設定（PDF）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PdfPageSideOptions {
+    /// 画像の配置位置のずらし量（mm、右・上が正）
+    #[serde(default)]
+    pub offset_x_mm: f32,
+    #[serde(default)]
+    pub offset_y_mm: f32,
+    /// ノンブルの横位置: "center"（中央）, "outer"（小口側）, "inner"（ノド側）
+    #[serde(default = "default_nombre_position")]
+    pub nombre_position: String,
+}
+
+impl Default for PdfPageSideOptions {
+    fn default() -> Self {
+        Self {
+            offset_x_mm: 0.0,
+            offset_y_mm: 0.0,
+            nombre_position: default_nombre_position(),
+        }
+    }
+}
+
+pub fn default_nombre_position() -> String { "center".to_string() }
+
+/// ノンブルの表示設定
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NombreOptions {
+    /// 縦位置: "bottom"（下）, "top"（上）
+    #[serde(default = "default_nombre_vertical")]
+    pub vertical: String,
+    /// 位置のずらし量（mm、右・下が正）
+    #[serde(default)]
+    pub offset_x_mm: f32,
+    #[serde(default)]
+    pub offset_y_mm: f32,
+    /// 背景ボックスを描くか
+    #[serde(default = "default_true")]
+    pub show_box: bool,
+    /// 背景ボックスの色と不透明度（0-100）
+    #[serde(default = "default_nombre_box_color")]
+    pub box_color: String,
+    #[serde(default = "default_nombre_box_opacity")]
+    pub box_opacity: u8,
+    /// 文字の色（"black" などの色名または "#rrggbb"）
+    #[serde(default = "default_nombre_text_color")]
+    pub text_color: String,
+    /// 数字の前後に付ける文字列
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default)]
+    pub suffix: String,
+    /// 数字の表記: "arabic"（算用数字）, "padded"（ゼロ埋め）, "kanji"（漢数字）, "roman"（ローマ数字）
+    #[serde(default = "default_numeral_style")]
+    pub numeral_style: String,
+    /// ゼロ埋めの桁数
+    #[serde(default = "default_nombre_pad_width")]
+    pub pad_width: u32,
+    /// 隠しノンブル: ノド側の仕上がり線の内側（綴じると隠れる位置）に小さく入れる
+    /// 縦位置・ずらし量・表記・文字色は共通、背景ボックスは描かない
+    #[serde(default)]
+    pub hidden: bool,
+    /// 隠しノンブルの文字サイズ（pt）
+    #[serde(default = "default_hidden_nombre_size_pt")]
+    pub hidden_size_pt: f32,
+    /// フォント（未指定ならシステムのフォントを検索、欧文のみなら数字用フォント）
+    #[serde(default)]
+    pub font: Option<FontSpec>,
+}
+
+impl Default for NombreOptions {
+    fn default() -> Self {
+        Self {
+            vertical: default_nombre_vertical(),
+            offset_x_mm: 0.0,
+            offset_y_mm: 0.0,
+            show_box: true,
+            box_color: default_nombre_box_color(),
+            box_opacity: default_nombre_box_opacity(),
+            text_color: default_nombre_text_color(),
+            prefix: String::new(),
+            suffix: String::new(),
+            numeral_style: default_numeral_style(),
+            pad_width: default_nombre_pad_width(),
+            hidden: false,
+            hidden_size_pt: default_hidden_nombre_size_pt(),
+            font: None,
+        }
+    }
+}
+
+pub fn default_nombre_vertical() -> String { "bottom".to_string() }
+pub fn default_nombre_box_color() -> String { "white".to_string() }
+pub fn default_nombre_box_opacity() -> u8 { 82 }
+pub fn default_nombre_text_color() -> String { "#3c3c3c".to_string() }
+pub fn default_numeral_style() -> String { "arabic".to_string() }
+pub fn default_nombre_pad_width() -> u32 { 3 }
+pub fn default_hidden_nombre_size_pt() -> f32 { 5.0 }
+/// PDFのノンブルは余白に黒文字で入れる（背景ボックスなし）
+pub fn default_pdf_nombre_options() -> NombreOptions {
+    NombreOptions { show_box: false, text_color: "black".to_string(), ..NombreOptions::default() }
+}
+
+/// フォントの指定
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FontSpec {
+    /// フォントファイルのパス（.ttf, .otf, .ttc, .otc）
+    pub path: String,
+    /// TTC・OTCの書体番号（0から）
+    #[serde(default)]
+    pub face_index: u32,
+}
+
+/// 傾き補正・位置合わせ設定
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AlignOptions {
+    /// 傾きを検出して回転で補正するか
+    #[serde(default)]
+    pub deskew: bool,
+    /// 検出する最大の傾き（度）
+    #[serde(default = "default_max_skew_degrees")]
+    pub max_skew_degrees: f32,
+    /// これより小さい傾きは補正しない（度）
+    #[serde(default = "default_min_skew_degrees")]
+    pub min_skew_degrees: f32,
+    /// トンボ（なければ紙・絵柄の境界）の中心が crop_* の中心に来るよう平行移動するか
+    /// 検出の信頼度が auto_crop_min_confidence 未満なら移動しない
+    #[serde(default)]
+    pub register: bool,
+}
+
+impl Default for AlignOptions {
+    fn default() -> Self {
+        Self {
+            deskew: false,
+            max_skew_degrees: default_max_skew_degrees(),
+            min_skew_degrees: default_min_skew_degrees(),
+            register: false,
+        }
+    }
+}
+
+pub fn default_max_skew_degrees() -> f32 { 3.0 }
+pub fn default_min_skew_degrees() -> f32 { 0.05 }
+
+/// PNG出力設定
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PngOptions {
+    /// 圧縮レベル: "fast", "default", "best"
+    #[serde(default = "default_png_compression")]
+    pub compression: String,
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        Self { compression: default_png_compression() }
+    }
+}
+
+pub fn default_png_compression() -> String { "default".to_string() }
+
+/// TIFF出力設定
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TiffOptions {
+    /// 圧縮方式: "none", "lzw", "zip", "g4"（G4は1bit白黒）
+    #[serde(default = "default_tiff_compression")]
+    pub compression: String,
+    /// G4圧縮時の2値化閾値（これ未満を黒とする）
+    #[serde(default = "default_tiff_threshold")]
+    pub threshold: u8,
+}
+
+impl Default for TiffOptions {
+    fn default() -> Self {
+        Self {
+            compression: default_tiff_compression(),
+            threshold: default_tiff_threshold(),
+        }
+    }
+}
+
+pub fn default_tiff_compression() -> String { "lzw".to_string() }
+pub fn default_tiff_threshold() -> u8 { 128 }
+
+/// PSDレイヤー/グループの名前条件
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LayerMatcher {
+    /// レイヤー名またはグループ名
+    pub name: String,
+    /// 対象: "layer", "group", "any"
+    #[serde(default = "default_layer_target")]
+    pub target: String,
+    /// 一致方法: "exact", "contains", "prefix"
+    #[serde(default = "default_layer_match_mode")]
+    pub match_mode: String,
+}
+
+pub fn default_layer_target() -> String { "any".to_string() }
+pub fn default_layer_match_mode() -> String { "exact".to_string() }
+
+/// PSDレイヤー表示ルール
+/// excludeに一致したレイヤー/グループは中身ごと非表示
+/// includeを指定した場合は、一致したレイヤー/グループ（とその中身）のみ表示
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LayerFilter {
+    #[serde(default)]
+    pub include: Vec<LayerMatcher>,
+    #[serde(default)]
+    pub exclude: Vec<LayerMatcher>,
+    /// 保存時に非表示のレイヤーも表示する
+    #[serde(default)]
+    pub show_hidden: bool,
+}
+
+impl LayerMatcher {
+    /// レイヤー名が条件に一致するか判定
+    pub fn matches(&self, name: &str, is_group: bool) -> bool {
+        let target_ok = match self.target.as_str() {
+            "layer" => !is_group,
+            "group" => is_group,
+            _ => true,
+        };
+        let name = name.trim();
+        let pattern = self.name.trim();
+        target_ok && match self.match_mode.as_str() {
+            "contains" => name.contains(pattern),
+            "prefix" => name.starts_with(pattern),
+            _ => name == pattern,
+        }
+    }
+}
 
 /// リサイズターゲットサイズ（元のTachimiと同じ）
 pub const TARGET_RESIZE_WIDTH: u32 = 2250;
@@ -70,6 +515,39 @@ pub struct ProcessResult {
     pub total: usize,
     pub errors: Vec<String>,
     pub output_folder: String,
+    /// 出力ファイル名（入力ファイルと同じ順序）
+    #[serde(default)]
+    pub output_files: Vec<String>,
+    /// 処理できたページごとの結果（入力ファイルと同じ順序）
+    #[serde(default)]
+    pub pages: Vec<PageReport>,
+}
+
+/// PDF生成結果
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PdfResult {
+    /// 保存したPDFのパス（同名ファイルがある場合は連番付き）
+    pub output_path: String,
+    /// PDFは保存できたが反映できなかった設定（開始ページのない章のしおりなど）
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+/// ページごとの処理結果
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PageReport {
+    pub file: String,
+    /// 使用した仕上がり枠の取得元: "global"（共通のクロップ範囲）, "guides"（PSDのガイド）, "auto"（自動検出）
+    pub crop_source: String,
+    /// 自動検出の信頼度（自動検出した場合のみ、閾値未満で共通のクロップ範囲を使った場合も含む）
+    #[serde(default)]
+    pub crop_confidence: Option<f32>,
+    /// 補正した傾き（度、時計回りが正）
+    #[serde(default)]
+    pub skew_degrees: Option<f32>,
+    /// 位置合わせで移動した量（ピクセル、[x, y]）
+    #[serde(default)]
+    pub registration_offset: Option<[i32; 2]>,
 }
 
 /// 作品情報（白紙ページに印字）
@@ -89,15 +567,51 @@ pub struct WorkInfo {
     pub subtitle: String,     // サブタイトル
     #[serde(default)]
     pub version: String,      // 巻数
+    #[serde(default)]
+    pub writing_mode: String, // 組み方向: "horizontal"（横書き、既定）, "vertical"（縦書き）
+}
+
+/// 奥付情報（作品名・著者は WorkInfo から取る）
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ColophonInfo {
+    #[serde(default)]
+    pub publication_date: String,  // 発行日（刷ごとに改行で複数行）
+    #[serde(default)]
+    pub publisher: String,         // 発行所
+    #[serde(default)]
+    pub publisher_address: String, // 発行所の住所
+    #[serde(default)]
+    pub printer: String,           // 印刷所
+    #[serde(default)]
+    pub copyright: String,         // 著作権表示
+    #[serde(default)]
+    pub notes: String,             // 注意書き（改行で複数行）
+    /// 奥付テンプレート（空なら標準のテンプレート、書き方は colophon モジュール参照）
+    #[serde(default)]
+    pub template: String,
+}
+
+/// しおり（PDFのアウトラインの1項目）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PdfChapter {
+    /// 見出し
+    pub title: String,
+    /// 開始ページ（ノンブルの番号）
+    pub page: u32,
 }
 
 /// PDFオプション
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PdfOptions {
-    /// プリセット: "b4_single", "b4_spread", "a4_single", "a4_spread", "custom"
+    /// プリセット: "b4_single", "b4_spread", "a4_single", "a4_spread", "a5_single", "b5_single", "custom"
+    /// （"custom" は width_mm / height_mm を使用、"image" は画像サイズをそのままページサイズにする）
     pub preset: String,
+    /// 1ページ分の幅・高さ（mm、見開きでも片ページのサイズ）
     pub width_mm: f32,
     pub height_mm: f32,
+    /// ページへの画像配置: "fit"（収める）, "fill"（覆う）, "actual"（実寸・左上）, "center"（実寸・中央）
+    #[serde(default = "default_page_fit")]
+    pub page_fit: String,
     pub gutter: u32,
     pub padding: u32,
     pub is_spread: bool,
@@ -110,32 +624,88 @@ pub struct PdfOptions {
     /// 作品情報
     #[serde(default)]
     pub work_info: Option<WorkInfo>,
+    /// 作品情報のフォント（未指定ならシステムの日本語フォントを検索）
+    #[serde(default)]
+    pub work_info_font: Option<FontSpec>,
+    /// 奥付（指定時は末尾に奥付ページを追加、フォントは work_info_font）
+    #[serde(default)]
+    pub colophon: Option<ColophonInfo>,
+    /// 作品情報・奥付の文字: "vector"（PDFのテキスト、フォントを埋め込む）, "raster"（画像にして埋め込む）
+    #[serde(default = "default_generated_text")]
+    pub generated_text: String,
     /// PDF余白にノンブルを追加するか
     #[serde(default)]
     pub add_nombre: bool,
+    /// 先頭の画像のノンブル番号（白紙ページ・奥付には番号を振らない）
+    #[serde(default = "default_nombre_start")]
+    pub nombre_start_number: u32,
     /// ノンブルサイズ
     #[serde(default = "default_nombre_size")]
     pub nombre_size: String,
+    /// ノンブルの縦位置・表記・色など（横位置は left_page / right_page）
+    #[serde(default = "default_pdf_nombre_options")]
+    pub nombre: NombreOptions,
+    /// 出力カラープロファイル: "keep"（各画像のプロファイルをそのまま埋め込む）, "srgb", "gray_gamma22"
+    #[serde(default = "default_color_profile")]
+    pub color_profile: String,
+    /// 画像のカラーモード: "auto"（モノクロページはグレースケール）, "rgb", "gray"
+    #[serde(default = "default_color_mode")]
+    pub color_mode: String,
+    /// PDFに埋め込む画像のJPEGエンコード設定
+    #[serde(default = "default_pdf_jpeg_options")]
+    pub jpeg: JpegOptions,
+    /// 解像度の上書き（dpi）。未指定なら画像ごとの解像度情報（なければ350dpi）を使う
+    #[serde(default)]
+    pub dpi_override: Option<f32>,
+    /// トンボ（指定時はページを仕上がりとして、外側に裁ち落とし・トンボ分の余白を広げてベクターで描く）
+    #[serde(default)]
+    pub trim_marks: Option<TrimMarkOptions>,
+    /// 裁ち落とし幅（mm）。BleedBoxに使い、用紙をこの分広げる（トンボ指定時はトンボの裁ち落とし幅を使う）
+    #[serde(default)]
+    pub bleed_mm: f32,
+    /// 内枠の仕上がりからの距離（mm）。0より大きければArtBoxを設定する
+    #[serde(default)]
+    pub safe_margin_mm: f32,
+    /// 綴じ方向: "right"（右綴じ、奇数ページが左ページ）, "left"（左綴じ、奇数ページが右ページ）
+    /// 単ページPDFでは左右判定とビューアの表示方向（右綴じは右から左）に、
+    /// 見開きPDFでは読む順で先のページを右（右綴じ）・左（左綴じ）のどちらに置くかに使う
+    #[serde(default = "default_binding")]
+    pub binding: String,
+    /// 左ページ・右ページごとの設定
+    #[serde(default)]
+    pub left_page: PdfPageSideOptions,
+    #[serde(default)]
+    pub right_page: PdfPageSideOptions,
+    /// しおり（章の一覧、開始ページの順）
+    #[serde(default)]
+    pub chapters: Vec<PdfChapter>,
 }
 
 /// 色文字列からRGBA値を取得（塗り用、不透明度指定可）
 pub fn color_to_rgba(color: &str, opacity: u8) -> Rgba<u8> {
-    let alpha = (opacity as f32 * 2.55) as u8;
-    match color {
-        "white" => Rgba([255, 255, 255, alpha]),
-        "black" => Rgba([0, 0, 0, alpha]),
-        "cyan" => Rgba([0, 255, 255, alpha]),
-        _ => Rgba([255, 255, 255, alpha]),
-    }
+    let alpha = (opacity.min(100) as f32 * 2.55) as u8;
+    let [r, g, b] = parse_color(color).unwrap_or([255, 255, 255]);
+    Rgba([r, g, b, alpha])
 }
 
 /// 色文字列からRGB値を取得（線用、完全不透明）
 pub fn color_to_rgb(color: &str) -> Rgba<u8> {
+    let [r, g, b] = parse_color(color).unwrap_or([0, 0, 0]);
+    Rgba([r, g, b, 255])
+}
+
+/// 色名（"white", "black", "cyan", "gray"）または "#rrggbb" を解釈
+fn parse_color(color: &str) -> Option<[u8; 3]> {
     match color {
-        "white" => Rgba([255, 255, 255, 255]),
-        "black" => Rgba([0, 0, 0, 255]),
-        "cyan" => Rgba([0, 255, 255, 255]),
-        _ => Rgba([0, 0, 0, 255]),
+        "white" => Some([255, 255, 255]),
+        "black" => Some([0, 0, 0]),
+        "cyan" => Some([0, 255, 255]),
+        "gray" => Some([128, 128, 128]),
+        _ => {
+            let hex = color.strip_prefix('#').filter(|h| h.len() == 6 && h.is_ascii())?;
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            Some([channel(0)?, channel(2)?, channel(4)?])
+        }
     }
 }
 
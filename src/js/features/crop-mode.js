@@ -135,8 +135,8 @@ export function openCropMode(imageData) {
         renderGuides();
         updateGuideList();
 
-        // PSDファイルの場合、埋め込みガイドを読み込む
-        if (imageData.filePath && imageData.filePath.toLowerCase().endsWith('.psd') && appState.invoke) {
+        // PSD/PSBファイルの場合、埋め込みガイドを読み込む
+        if (imageData.filePath && /\.ps[db]$/i.test(imageData.filePath) && appState.invoke) {
             appState.invoke('get_psd_guides', { filePath: imageData.filePath })
                 .then(guides => {
                     if (guides && guides.length > 0) {
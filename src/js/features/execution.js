@@ -236,6 +236,7 @@ export async function execute() {
         let tempFolderUsed = false;
         let actualOutputFolder = appState.outputFolder;
         let jpegOutputFolder = null;  // Rust側が返す実際のJPEG出力パス
+        let processedFiles = null;  // Rust側が返す出力ファイル名（入力ファイルと同じ順序）
 
         // PDF出力が有効かチェック
         const savePdf = settings.savePdfSingle || settings.savePdfSpread;
@@ -287,6 +288,7 @@ export async function execute() {
 
             // Rust側が返す実際のJPEG出力パス（連番フォルダ: jpg, jpg(1), jpg(2)...）
             jpegOutputFolder = result.output_folder;
+            processedFiles = result.output_files;
 
             if (!tempFolderUsed) {
                 message += `画像処理完了: ${result.processed}/${result.total} ファイル\n`;
@@ -329,10 +331,8 @@ export async function execute() {
                 // Rust側が返す実際のJPEG出力パスを使用（連番フォルダ対応: jpg, jpg(1), jpg(2)...）
                 pdfSourceFolder = jpegOutputFolder || (actualOutputFolder + '\\jpg');
             }
-            pdfFiles = appState.targetFiles.map(f => {
-                const baseName = f.replace(/\.[^/.]+$/, '');
-                return baseName + '.jpg';
-            });
+            // 命名テンプレート・出力形式を反映した実際のファイル名を使用
+            pdfFiles = processedFiles || [];
         }
 
         // 単ページPDF出力
@@ -351,13 +351,15 @@ export async function execute() {
                 gutter: 0,
                 padding: singlePadding,
                 is_spread: false,
+                work_info: settings.workInfo || null,
                 add_nombre: singleAddNombre,
+                nombre_start_number: settings.nombreStartNumber || 1,
                 nombre_size: singleNombreSize
             };
 
             const singlePdfPath = appState.outputFolder + '\\' + (settings.outputName || '出力') + '_単ページ.pdf';
 
-            await appState.invoke('generate_pdf', {
+            const singlePdfResult = await appState.invoke('generate_pdf', {
                 inputFolder: pdfSourceFolder,
                 outputPath: singlePdfPath,
                 files: pdfFiles,
@@ -365,6 +367,9 @@ export async function execute() {
             });
 
             message += `単ページPDF生成完了\n`;
+            singlePdfResult.warnings.forEach(w => {
+                message += `・警告: ${w}\n`;
+            });
         }
 
         // 見開きPDF出力
@@ -392,12 +397,13 @@ export async function execute() {
                 print_work_info: settings.printWorkInfo || false,
                 work_info: settings.workInfo || null,
                 add_nombre: spreadAddNombre,
+                nombre_start_number: settings.nombreStartNumber || 1,
                 nombre_size: spreadNombreSize
             };
 
             const spreadPdfPath = appState.outputFolder + '\\' + (settings.outputName || '出力') + '_見開き.pdf';
 
-            await appState.invoke('generate_pdf', {
+            const spreadPdfResult = await appState.invoke('generate_pdf', {
                 inputFolder: pdfSourceFolder,
                 outputPath: spreadPdfPath,
                 files: pdfFiles,
@@ -405,6 +411,9 @@ export async function execute() {
             });
 
             message += `見開きPDF生成完了\n`;
+            spreadPdfResult.warnings.forEach(w => {
+                message += `・警告: ${w}\n`;
+            });
         }
 
         // 一時フォルダを使用した場合は削除
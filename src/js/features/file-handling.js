@@ -76,7 +76,7 @@ export async function handleDroppedPaths(paths) {
     // 既存のファイル選択をリセット
     resetFileSelection();
 
-    const supportedExts = ['png', 'jpg', 'jpeg', 'gif', 'webp', 'psd', 'tif', 'tiff'];
+    const supportedExts = ['png', 'jpg', 'jpeg', 'gif', 'webp', 'psd', 'psb', 'tif', 'tiff'];
     const firstPath = paths[0];
 
     // フォルダかファイルかを判定（拡張子の有無で判断）